@@ -9,7 +9,7 @@ fn main() {
 
     Codegen::new()
         .protoc()
-        .includes(&["proto"])
+        .includes(["proto"])
         .input("proto/api.proto")
         .out_dir(CARGO_OUT_DIR)
         .run_from_script();
@@ -4,20 +4,20 @@ SCRIPT_DIR=$( cd -- "$( dirname -- "${BASH_SOURCE[0]}" )" &> /dev/null && pwd )
 
 # Check if an argument is provided and is a positive integer
 if ! [[ $1 =~ ^[0-9]+$ ]] || [[ $1 -le 0 ]]; then
-    echo "Usage: $0 <positive_integer> <server file path>.txt"
+    echo "Usage: $0 <positive_integer> <server file path>.txt [start port]"
     exit 1
 fi
 
 # Check if argument is a text file path
 if ! [[ $2 =~ ^.+\.txt$ ]]; then
-    echo "Usage: $0 <positive_integer> <server file path>.txt"
+    echo "Usage: $0 <positive_integer> <server file path>.txt [start port]"
     exit 2
 fi
 
 N=$1
 OUTPUT_FILE=$(realpath -m "${SCRIPT_DIR}/../${2}")
 PORTS_FOUND=0
-START_PORT=1024
+START_PORT=${3:-1024}
 UPPER_BOUND=65535
 AVAILABLE_PORTS=()
 
@@ -9,9 +9,31 @@ sudo -v
 
 cargo test --test test_single_node_is_alive -- ${TEST_ARGS} && \
     cargo test --test test_single_node_basic_operations -- ${TEST_ARGS} && \
-    cargo test --test test_single_node_memory_capacity -- ${TEST_ARGS}
+    cargo test --test test_single_node_conditional_operations -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_batch_operations -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_counters -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_partial_values -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_key_expiry -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_eviction -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_durability -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_storage_engines -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_scan -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_large_values -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_memory_capacity -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_tcp_transport -- ${TEST_ARGS} && \
+    cargo test --test test_single_node_worker_pool -- ${TEST_ARGS} && \
+    cargo test --features async --test test_single_node_async -- ${TEST_ARGS}
 
-cargo test --test test_single_node_shutdown -- ${TEST_ARGS}
+cargo test --test test_multi_node_routing -- ${TEST_ARGS} && \
+    cargo test --test test_multi_node_join -- ${TEST_ARGS} && \
+    cargo test --test test_multi_node_consistency -- ${TEST_ARGS} && \
+    cargo test --test test_multi_node_replication -- ${TEST_ARGS} && \
+    cargo test --test test_multi_node_scan -- ${TEST_ARGS} && \
+    cargo test --test test_multi_node_batch_operations -- ${TEST_ARGS} && \
+    cargo test --test test_multi_node_counters -- ${TEST_ARGS}
+
+cargo test --test test_single_node_shutdown -- ${TEST_ARGS} && \
+    cargo test --test test_multi_node_shutdown -- ${TEST_ARGS}
 
 if [ $? -ne 0 ]; then
     echo "WARNING: The shutdown test failed. Killing server processes..."
@@ -5,9 +5,13 @@ SCRIPT_DIR=$( cd -- "$( dirname -- "${BASH_SOURCE[0]}" )" &> /dev/null && pwd )
 # FIXME: This doesn't actually do any memory restrictions yet, see start_node.sh
 VM_LIMIT_MB=64
 VM_LIMIT_B=$((VM_LIMIT_MB * 1024 * 1024))
+CLUSTER_REPLICATION_FACTOR=2
 
 function start_servers() {
     local SERVERS_FILE=$1
+    local CLUSTER_FILE=$2
+    local FIRST_ID=${3:-0}
+    local REPLICATION_FACTOR=${4:-1}
     local SERVERS_COUNT=$(wc -l < $SERVERS_FILE)
     local SERVERS=($(cat $SERVERS_FILE))
 
@@ -15,9 +19,17 @@ function start_servers() {
     for ((i=0; i<SERVERS_COUNT; i++)); do
         local SERVER=${SERVERS[$i]}
         local PORT=$(echo $SERVER | cut -d':' -f2)
-        ${SCRIPT_DIR}/start_node.sh ${PORT} ${i} ${VM_LIMIT_B}
+        ${SCRIPT_DIR}/start_node.sh ${PORT} $((FIRST_ID + i)) ${VM_LIMIT_B} ${CLUSTER_FILE} ${REPLICATION_FACTOR}
     done
 }
 
+SINGLE_SERVER_FILE=$(realpath -m ${SCRIPT_DIR}/../servers/single_server.txt)
+MULTI_SERVER_FILE=$(realpath -m ${SCRIPT_DIR}/../servers/multi_server.txt)
+
 ${SCRIPT_DIR}/generate_servers_list.sh 1 servers/single_server.txt
-start_servers $(realpath ${SCRIPT_DIR}/../servers/single_server.txt)
+start_servers ${SINGLE_SERVER_FILE}
+
+# Start the cluster after the single server's port so the two never overlap
+SINGLE_SERVER_PORT=$(cut -d':' -f2 < ${SINGLE_SERVER_FILE})
+${SCRIPT_DIR}/generate_servers_list.sh 3 servers/multi_server.txt $((SINGLE_SERVER_PORT + 1))
+start_servers ${MULTI_SERVER_FILE} ${MULTI_SERVER_FILE} 1 ${CLUSTER_REPLICATION_FACTOR}
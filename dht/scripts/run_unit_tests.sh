@@ -1,2 +1,2 @@
 #!/bin/bash
-cargo test --lib -- --show-output --include-ignored --test-threads 1
+cargo test --bins --lib -- --show-output --include-ignored --test-threads 1
@@ -3,8 +3,14 @@
 PORT=$1
 ID=$2
 VM_LIMIT_B=$3
+SERVERS_FILE=$4
+REPLICATION_FACTOR=${5:-1}
 
 # TODO: Enforce rules on memory usage robustly, ulimit does not work as expected
 # ulimit -v ${VM_LIMIT_B}
-cargo run --bin dht -- -p ${PORT} -s ${ID} -l info 2>/dev/null 1>&2 &
+if [ -n "${SERVERS_FILE}" ]; then
+    cargo run --bin dht -- -p ${PORT} -s ${ID} -l info -f ${SERVERS_FILE} -r ${REPLICATION_FACTOR} 2>/dev/null 1>&2 &
+else
+    cargo run --bin dht -- -p ${PORT} -s ${ID} -l info 2>/dev/null 1>&2 &
+fi
 echo "Started server on port ${PORT} with PID $!"
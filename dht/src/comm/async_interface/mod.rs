@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::io::{Result, Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use protobuf::Message;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::comm::{
+    encode_datagrams, get_retransmit_requests, proto, receive_datagram, Received,
+    LISTENING_TIMEOUT, MAX_BUFFER_SIZE_BYTES, MAX_RETRIES, SEND_RECV_TIMEOUT, TIMEOUT_MULTIPLIER
+};
+use crate::comm::fragment::{Reassembler, SentFragments, MAX_MESSAGE_SIZE_BYTES, RETRANSMIT_INTERVAL};
+use crate::comm::protogen::api::UDPMessage;
+
+/// Messages received but not yet taken by `listen`, past which more are
+/// dropped as a full socket buffer would
+const MAX_QUEUED_MESSAGES: usize = 1024 * 4;
+
+type Inbound = (UDPMessage, SocketAddr);
+type Waiting = Arc<Mutex<HashMap<Vec<u8>, oneshot::Sender<Inbound>>>>;
+
+/// The async counterpart of `ProtoInterface` over UDP, for callers running
+/// on the tokio runtime.
+///
+/// A task reads every datagram arriving on the socket. A reply goes to the
+/// request waiting on it, matched by the message Id the reply shares with
+/// it, and any other message is queued for `listen`. Many requests can thus
+/// be in flight at once over a single interface.
+pub struct AsyncProtoInterface {
+    socket: Arc<UdpSocket>,
+    ip: IpAddr,
+    port: u16,
+    sent_fragments: Arc<Mutex<SentFragments>>,
+    waiting: Waiting,
+    incoming: tokio::sync::Mutex<mpsc::Receiver<Inbound>>,
+    receiver: JoinHandle<()>,
+    send_recv_timeout: Duration,
+    listening_timeout: Duration,
+    max_retries: u32,
+}
+
+impl AsyncProtoInterface {
+    /// Binds to `socket_addr` and starts reading from it, so it must be
+    /// called from within the tokio runtime.
+    pub async fn bind(socket_addr: SocketAddr) -> Result<Self> {
+        let socket: Arc<UdpSocket> = Arc::new(UdpSocket::bind(socket_addr).await?);
+        let local_addr: SocketAddr = socket.local_addr()?;
+        let sent_fragments: Arc<Mutex<SentFragments>> = Arc::new(Mutex::new(SentFragments::default()));
+        let waiting: Waiting = Arc::new(Mutex::new(HashMap::new()));
+        let (sender, incoming) = mpsc::channel(MAX_QUEUED_MESSAGES);
+
+        let receiver: JoinHandle<()> = tokio::spawn(receive_datagrams(
+            socket.clone(),
+            sent_fragments.clone(),
+            waiting.clone(),
+            sender
+        ));
+
+        Ok(AsyncProtoInterface {
+            socket,
+            ip: local_addr.ip(),
+            port: local_addr.port(),
+            sent_fragments,
+            waiting,
+            incoming: tokio::sync::Mutex::new(incoming),
+            receiver,
+            send_recv_timeout: SEND_RECV_TIMEOUT,
+            listening_timeout: LISTENING_TIMEOUT,
+            max_retries: MAX_RETRIES,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port)
+    }
+
+    /// Largest message that can be sent
+    pub fn max_message_size(&self) -> usize {
+        MAX_MESSAGE_SIZE_BYTES
+    }
+
+    pub async fn send(&self, message: impl Message, server_addr: SocketAddr) -> Result<usize> {
+        let udp_message: UDPMessage = proto::create_udp_message(message, self.ip, self.port)?;
+        self.send_udp_message(&udp_message, server_addr).await
+    }
+
+    /// Sends `message` as the reply to the request with Id `request_id`.
+    pub async fn reply(&self, message: impl Message, request_id: &[u8], client_addr: SocketAddr) -> Result<usize> {
+        let udp_message: UDPMessage = proto::create_reply_message(message, request_id)?;
+        self.send_udp_message(&udp_message, client_addr).await
+    }
+
+    pub async fn forward(&self, message: &UDPMessage, server_addr: SocketAddr) -> Result<usize> {
+        self.send_udp_message(message, server_addr).await
+    }
+
+    /// Waits for the next message that is not a reply to a request of this
+    /// interface, failing with `WouldBlock` if none arrives in time.
+    pub async fn listen(&self) -> Result<(UDPMessage, SocketAddr)> {
+        let mut incoming = self.incoming.lock().await;
+        match tokio::time::timeout(self.listening_timeout, incoming.recv()).await {
+            Ok(Some((message, sender_addr))) => Ok((message, sender_addr)),
+            Ok(None) => Err(Error::new(ErrorKind::NotConnected, "Interface closed")),
+            Err(_) => Err(Error::new(ErrorKind::WouldBlock, "Timed out")),
+        }
+    }
+
+    /// Sends `message` and waits for its reply, sending it again with a
+    /// longer wait each time none comes. Any number of calls can wait on
+    /// their replies at once.
+    pub async fn send_and_recv(&self, message: impl Message, server_addr: SocketAddr) -> Result<(UDPMessage, SocketAddr)> {
+        let udp_message: UDPMessage = proto::create_udp_message(message, self.ip, self.port)?;
+        let (sender, mut receiver) = oneshot::channel();
+        let _waiter: Waiter = Waiter::new(&self.waiting, udp_message.id.clone(), sender);
+
+        let max_attempted_sends: u32 = self.max_retries + 1;
+        let mut timeout: Duration = self.send_recv_timeout;
+        for _ in 0..max_attempted_sends {
+            self.send_udp_message(&udp_message, server_addr).await?;
+            match tokio::time::timeout(timeout, &mut receiver).await {
+                Ok(Ok((message, sender_addr))) => return Ok((message, sender_addr)),
+                Ok(Err(_)) => return Err(Error::new(ErrorKind::NotConnected, "Interface closed")),
+                Err(_) => timeout = timeout.checked_mul(TIMEOUT_MULTIPLIER).unwrap(),
+            }
+        }
+
+        Err(Error::new(ErrorKind::TimedOut, "Timed out"))
+    }
+
+    async fn send_udp_message(&self, message: &UDPMessage, server_addr: SocketAddr) -> Result<usize> {
+        let datagrams: Vec<Vec<u8>> = encode_datagrams(message, Some(MAX_BUFFER_SIZE_BYTES))?;
+        let mut size: usize = 0;
+        for datagram in datagrams.iter() {
+            size += self.socket.send_to(datagram.as_slice(), server_addr).await?;
+        }
+
+        if datagrams.len() > 1 {
+            self.sent_fragments.lock().unwrap().insert(message.id.clone(), server_addr, datagrams, Instant::now());
+        }
+        Ok(size)
+    }
+}
+
+impl Drop for AsyncProtoInterface {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+/// A request waiting on its reply, which stops waiting once dropped even if
+/// the caller gave up on it partway.
+struct Waiter<'a> {
+    waiting: &'a Waiting,
+    id: Vec<u8>,
+}
+
+impl<'a> Waiter<'a> {
+    fn new(waiting: &'a Waiting, id: Vec<u8>, sender: oneshot::Sender<Inbound>) -> Self {
+        waiting.lock().unwrap().insert(id.clone(), sender);
+        Waiter { waiting, id }
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        self.waiting.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Reads every datagram arriving on `socket`, answering requests for
+/// fragments sent earlier and asking for those missing from stalled
+/// messages. Whole messages go to the request waiting on them, or else to
+/// `incoming`.
+async fn receive_datagrams(
+    socket: Arc<UdpSocket>,
+    sent_fragments: Arc<Mutex<SentFragments>>,
+    waiting: Waiting,
+    incoming: mpsc::Sender<Inbound>
+) {
+    let reassembler: Mutex<Reassembler> = Mutex::new(Reassembler::default());
+    let mut buf: Vec<u8> = vec![0; MAX_BUFFER_SIZE_BYTES];
+    let mut is_reassembling: bool = false;
+
+    loop {
+        if is_reassembling {
+            let (requests, still_reassembling) = get_retransmit_requests(&reassembler, Instant::now());
+            for (sender_addr, request_bytes) in requests {
+                let _ = socket.send_to(request_bytes.as_slice(), sender_addr).await;
+            }
+            is_reassembling = still_reassembling;
+        }
+
+        let (size, sender_addr) = tokio::select! {
+            result = socket.recv_from(&mut buf) => match result {
+                Ok((size, sender_addr)) => (size, sender_addr),
+                Err(e) => {
+                    log::debug!("Failed to receive datagram: {}", e);
+                    continue;
+                },
+            },
+            _ = tokio::time::sleep(RETRANSMIT_INTERVAL), if is_reassembling => continue,
+        };
+
+        let message: UDPMessage = match receive_datagram(buf[0..size].to_vec(), sender_addr, &reassembler, &sent_fragments) {
+            Ok(Received::Message(message)) => message,
+            Ok(Received::Retransmit(fragments)) => {
+                for fragment in fragments {
+                    let _ = socket.send_to(fragment.as_slice(), sender_addr).await;
+                }
+                continue;
+            },
+            Ok(Received::Fragment) => {
+                is_reassembling = true;
+                continue;
+            },
+            Err(e) => {
+                log::debug!("Dropping datagram from {}: {}", sender_addr, e);
+                continue;
+            },
+        };
+
+        let waiter_opt: Option<oneshot::Sender<Inbound>> = waiting.lock().unwrap().remove(&message.id);
+        match waiter_opt {
+            Some(waiter) => {
+                let _ = waiter.send((message, sender_addr));
+            },
+            None => {
+                if incoming.try_send((message, sender_addr)).is_err() {
+                    log::debug!("Dropping message from {}, too many queued", sender_addr);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comm::proto::{extract_request, Operation};
+    use crate::comm::protogen::api::Request;
+
+    async fn bind_local() -> AsyncProtoInterface {
+        AsyncProtoInterface::bind("127.0.0.1:0".parse().unwrap()).await.unwrap()
+    }
+
+    /// Answers every request with the request itself, once all are in and
+    /// in reverse order.
+    async fn echo(server: &AsyncProtoInterface, num_requests: usize) {
+        let mut held: Vec<Inbound> = Vec::new();
+        while held.len() < num_requests {
+            held.push(server.listen().await.unwrap());
+        }
+        for (message, sender_addr) in held.into_iter().rev() {
+            let request: Request = extract_request(&message).unwrap();
+            server.reply(request, &message.id, sender_addr).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replies_matched_to_concurrent_requests() {
+        let client: Arc<AsyncProtoInterface> = Arc::new(bind_local().await);
+        let server: AsyncProtoInterface = bind_local().await;
+        let server_addr: SocketAddr = server.local_addr();
+        const NUM_REQUESTS: usize = 64;
+
+        let requests: Vec<JoinHandle<()>> = (0..NUM_REQUESTS)
+            .map(|i| {
+                let client: Arc<AsyncProtoInterface> = client.clone();
+                tokio::spawn(async move {
+                    let mut request: Request = Request::new();
+                    request.operation = Operation::Put as u32;
+                    request.key = Some(i.to_be_bytes().to_vec());
+                    let (reply, _) = client.send_and_recv(request.clone(), server_addr).await.unwrap();
+                    assert_eq!(extract_request(&reply).unwrap(), request);
+                })
+            })
+            .collect();
+
+        echo(&server, NUM_REQUESTS).await;
+        for request in requests {
+            request.await.unwrap();
+        }
+        assert!(client.waiting.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_large_message_and_listen_timeout() {
+        let client: AsyncProtoInterface = bind_local().await;
+        let server: AsyncProtoInterface = bind_local().await;
+
+        let mut request: Request = Request::new();
+        request.operation = Operation::Put as u32;
+        request.value = Some(vec![7; MAX_BUFFER_SIZE_BYTES * 4]);
+        client.send(request.clone(), server.local_addr()).await.unwrap();
+
+        let (message, sender_addr) = server.listen().await.unwrap();
+        assert_eq!(extract_request(&message).unwrap(), request);
+        assert_eq!(sender_addr, client.local_addr());
+
+        let e: Error = server.listen().await.unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::WouldBlock);
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{Result, Error, ErrorKind};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::comm::MAX_BUFFER_SIZE_BYTES;
+use crate::comm::proto::calculate_checksum;
+use crate::comm::protogen::api::UDPMessage;
+
+/// Room left in a datagram for everything but the fragment's share of the
+/// message
+const FRAGMENT_HEADROOM_BYTES: usize = 1024;
+pub const MAX_FRAGMENT_PAYLOAD_BYTES: usize = MAX_BUFFER_SIZE_BYTES - FRAGMENT_HEADROOM_BYTES;
+/// Largest message that can be split into fragments
+pub const MAX_MESSAGE_SIZE_BYTES: usize = 1024 * 1024 * 8;
+/// Bytes held across every message being reassembled, or kept for
+/// retransmission, at any one time
+const MAX_BUFFERED_BYTES: usize = 1024 * 1024 * 64;
+/// How long a message being reassembled may go without a new fragment, and
+/// how long sent fragments are kept for retransmission
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a receiver waits on a stalled message before asking for the
+/// fragments it is missing
+pub const RETRANSMIT_INTERVAL: Duration = Duration::from_millis(10);
+/// Fragments resent for a single request, few enough not to overrun the
+/// receiver's socket buffer all over again
+const MAX_FRAGMENTS_PER_RETRANSMIT: usize = 8;
+/// Messages a single sender may have in reassembly at once
+const MAX_PARTIAL_MESSAGES_PER_SENDER: usize = 64;
+
+/// Splits the bytes of a message too large for one datagram into numbered
+/// fragments sharing the message's id.
+pub fn split_message(id: &[u8], msg_bytes: &[u8]) -> Result<Vec<UDPMessage>> {
+    if msg_bytes.len() > MAX_MESSAGE_SIZE_BYTES {
+        return Err(Error::new(ErrorKind::InvalidInput, "Message exceeds the maximum size"));
+    }
+
+    let chunks: Vec<&[u8]> = msg_bytes.chunks(MAX_FRAGMENT_PAYLOAD_BYTES).collect();
+    let fragment_count: u32 = chunks.len() as u32;
+    let fragments: Vec<UDPMessage> = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut fragment: UDPMessage = UDPMessage::new();
+            fragment.id = id.to_vec();
+            fragment.payload = chunk.to_vec();
+            fragment.checksum = calculate_checksum(id, chunk);
+            fragment.fragment_index = Some(index as u32);
+            fragment.fragment_count = Some(fragment_count);
+            fragment
+        })
+        .collect();
+    Ok(fragments)
+}
+
+/// Asks the sender of message `id` to resend the fragments it numbered
+/// `missing`.
+pub fn create_retransmit_request(id: &[u8], missing: Vec<u32>) -> UDPMessage {
+    let mut request: UDPMessage = UDPMessage::new();
+    request.id = id.to_vec();
+    request.checksum = calculate_checksum(id, &[]);
+    request.missing_fragments = missing;
+    request
+}
+
+pub fn is_fragment(message: &UDPMessage) -> bool {
+    message.fragment_count.is_some()
+}
+
+pub fn is_retransmit_request(message: &UDPMessage) -> bool {
+    !message.missing_fragments.is_empty()
+}
+
+struct PartialMessage {
+    fragments: Vec<Option<Vec<u8>>>,
+    num_received: usize,
+    size: usize,
+    last_progress: Instant,
+    last_retransmit_request: Option<Instant>,
+}
+
+impl PartialMessage {
+    fn get_missing(&self) -> Vec<u32> {
+        self.fragments
+            .iter()
+            .enumerate()
+            .filter(|(_, fragment)| fragment.is_none())
+            .map(|(index, _)| index as u32)
+            .take(MAX_FRAGMENTS_PER_RETRANSMIT)
+            .collect()
+    }
+}
+
+/// Messages whose fragments are still arriving, keyed by sender and id.
+#[derive(Default)]
+pub struct Reassembler {
+    partial: HashMap<(SocketAddr, Vec<u8>), PartialMessage>,
+    num_partial_per_sender: HashMap<SocketAddr, usize>,
+    size: usize,
+}
+
+impl Reassembler {
+    /// Stores `fragment`, returning the bytes of the whole message once its
+    /// last fragment is in. Fragments that could never form a message, or do
+    /// not fit in the buffer, are rejected.
+    ///
+    /// Each message is charged for the slots of all of its fragments as soon
+    /// as the first one arrives, as well as for the fragments themselves.
+    pub fn add(&mut self, sender: SocketAddr, fragment: UDPMessage, now: Instant) -> Result<Option<Vec<u8>>> {
+        let (index, count): (usize, usize) = match (fragment.fragment_index, fragment.fragment_count) {
+            (Some(index), Some(count)) if index < count => (index as usize, count as usize),
+            _ => return Err(Error::new(ErrorKind::InvalidData, "Invalid fragment number")),
+        };
+        if count > MAX_MESSAGE_SIZE_BYTES.div_ceil(MAX_FRAGMENT_PAYLOAD_BYTES) {
+            return Err(Error::new(ErrorKind::InvalidData, "Fragmented message exceeds the maximum size"));
+        }
+        if fragment.payload.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "Empty fragment"));
+        }
+
+        let key: (SocketAddr, Vec<u8>) = (sender, fragment.id);
+        if !self.partial.contains_key(&key) {
+            let num_partial: usize = self.num_partial_per_sender.get(&sender).copied().unwrap_or(0);
+            if num_partial >= MAX_PARTIAL_MESSAGES_PER_SENDER {
+                return Err(Error::new(ErrorKind::OutOfMemory, "Too many messages in reassembly from sender"));
+            }
+            let slots_size: usize = count * size_of::<Option<Vec<u8>>>();
+            if self.size + slots_size + fragment.payload.len() > MAX_BUFFERED_BYTES {
+                return Err(Error::new(ErrorKind::OutOfMemory, "Reassembly buffer is full"));
+            }
+
+            self.partial.insert(key.clone(), PartialMessage {
+                fragments: vec![None; count],
+                num_received: 0,
+                size: slots_size,
+                last_progress: now,
+                last_retransmit_request: None,
+            });
+            self.size += slots_size;
+            self.num_partial_per_sender.insert(sender, num_partial + 1);
+        } else if self.size + fragment.payload.len() > MAX_BUFFERED_BYTES {
+            return Err(Error::new(ErrorKind::OutOfMemory, "Reassembly buffer is full"));
+        }
+
+        let partial: &mut PartialMessage = self.partial.get_mut(&key).unwrap();
+        if partial.fragments.len() != count {
+            return Err(Error::new(ErrorKind::InvalidData, "Fragment count changed mid-message"));
+        }
+
+        if partial.fragments[index].is_none() {
+            partial.size += fragment.payload.len();
+            self.size += fragment.payload.len();
+            partial.fragments[index] = Some(fragment.payload);
+            partial.num_received += 1;
+            partial.last_progress = now;
+        }
+        if partial.num_received < count {
+            return Ok(None);
+        }
+
+        let partial: PartialMessage = self.remove(&key).unwrap();
+        let fragments: Vec<Vec<u8>> = partial.fragments.into_iter().flatten().collect();
+        Ok(Some(fragments.concat()))
+    }
+
+    fn remove(&mut self, key: &(SocketAddr, Vec<u8>)) -> Option<PartialMessage> {
+        let partial: PartialMessage = self.partial.remove(key)?;
+        self.size -= partial.size;
+        if let Some(num_partial) = self.num_partial_per_sender.get_mut(&key.0) {
+            *num_partial -= 1;
+            if *num_partial == 0 {
+                self.num_partial_per_sender.remove(&key.0);
+            }
+        }
+        Some(partial)
+    }
+
+    /// Requests for the fragments of every message that has stalled, along
+    /// with the sender each should go to. Messages stalled for longer than
+    /// `REASSEMBLY_TIMEOUT` are given up on.
+    pub fn get_retransmit_requests(&mut self, now: Instant) -> Vec<(SocketAddr, UDPMessage)> {
+        let expired: Vec<(SocketAddr, Vec<u8>)> = self.partial
+            .iter()
+            .filter(|(_, partial)| now.saturating_duration_since(partial.last_progress) > REASSEMBLY_TIMEOUT)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.remove(&key);
+        }
+
+        let mut requests: Vec<(SocketAddr, UDPMessage)> = Vec::new();
+        for ((sender, id), partial) in self.partial.iter_mut() {
+            let last_activity: Instant = partial.last_retransmit_request.map_or(partial.last_progress, |last_request| {
+                last_request.max(partial.last_progress)
+            });
+            if now.saturating_duration_since(last_activity) >= RETRANSMIT_INTERVAL {
+                requests.push((*sender, create_retransmit_request(id, partial.get_missing())));
+                partial.last_retransmit_request = Some(now);
+            }
+        }
+        requests
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partial.is_empty()
+    }
+}
+
+/// A message sent in fragments, along with when and where it went
+struct SentMessage {
+    sent_at: Instant,
+    destination: SocketAddr,
+    fragments: Vec<Vec<u8>>,
+}
+
+/// Fragments recently sent, kept so that the ones lost on the way can be
+/// sent again. The oldest messages are dropped first.
+#[derive(Default)]
+pub struct SentFragments {
+    messages: HashMap<Vec<u8>, SentMessage>,
+    order: VecDeque<Vec<u8>>,
+    size: usize,
+}
+
+impl SentFragments {
+    pub fn insert(&mut self, id: Vec<u8>, destination: SocketAddr, fragments: Vec<Vec<u8>>, now: Instant) {
+        self.expire(now);
+        let size: usize = fragments.iter().map(Vec::len).sum();
+        while self.size + size > MAX_BUFFERED_BYTES && self.pop_oldest() {}
+
+        self.size += size;
+        let message: SentMessage = SentMessage { sent_at: now, destination, fragments };
+        if let Some(replaced) = self.messages.insert(id.clone(), message) {
+            self.size -= replaced.fragments.iter().map(Vec::len).sum::<usize>();
+            self.order.retain(|queued_id| *queued_id != id);
+        }
+        self.order.push_back(id);
+    }
+
+    /// The fragments of message `id` numbered `indices`, as far as they are
+    /// still held. Only the node the message was sent to gets any.
+    pub fn get(&self, id: &[u8], requester: SocketAddr, indices: &[u32]) -> Vec<Vec<u8>> {
+        match self.messages.get(id) {
+            Some(message) if message.destination == requester => indices
+                .iter()
+                .take(MAX_FRAGMENTS_PER_RETRANSMIT)
+                .filter_map(|index| message.fragments.get(*index as usize).cloned())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(id) = self.order.front() {
+            match self.messages.get(id) {
+                Some(message) if now.saturating_duration_since(message.sent_at) <= REASSEMBLY_TIMEOUT => break,
+                _ => {
+                    self.pop_oldest();
+                },
+            }
+        }
+    }
+
+    fn pop_oldest(&mut self) -> bool {
+        match self.order.pop_front() {
+            Some(id) => {
+                if let Some(message) = self.messages.remove(&id) {
+                    self.size -= message.fragments.iter().map(Vec::len).sum::<usize>();
+                }
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_sender() -> SocketAddr {
+        "127.0.0.1:9000".parse().unwrap()
+    }
+
+    fn get_message_bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_split_and_reassemble_out_of_order() {
+        let msg_bytes: Vec<u8> = get_message_bytes(MAX_FRAGMENT_PAYLOAD_BYTES * 3 + 5);
+        let mut fragments: Vec<UDPMessage> = split_message(b"id", &msg_bytes).unwrap();
+        assert_eq!(fragments.len(), 4);
+        assert!(fragments.iter().all(is_fragment));
+
+        fragments.reverse();
+        let mut reassembler: Reassembler = Reassembler::default();
+        let now: Instant = Instant::now();
+        let last: UDPMessage = fragments.pop().unwrap();
+        for fragment in fragments {
+            assert_eq!(reassembler.add(get_sender(), fragment, now).unwrap(), None);
+        }
+        assert_eq!(reassembler.add(get_sender(), last, now).unwrap(), Some(msg_bytes));
+        assert!(reassembler.is_empty());
+        assert_eq!(reassembler.size, 0);
+    }
+
+    #[test]
+    fn test_split_rejects_oversized_message() {
+        let msg_bytes: Vec<u8> = vec![0; MAX_MESSAGE_SIZE_BYTES + 1];
+        assert!(split_message(b"id", &msg_bytes).is_err());
+    }
+
+    #[test]
+    fn test_reassemble_ignores_duplicates() {
+        let msg_bytes: Vec<u8> = get_message_bytes(MAX_FRAGMENT_PAYLOAD_BYTES + 1);
+        let fragments: Vec<UDPMessage> = split_message(b"id", &msg_bytes).unwrap();
+
+        let mut reassembler: Reassembler = Reassembler::default();
+        let now: Instant = Instant::now();
+        assert_eq!(reassembler.add(get_sender(), fragments[0].clone(), now).unwrap(), None);
+        assert_eq!(reassembler.add(get_sender(), fragments[0].clone(), now).unwrap(), None);
+        assert_eq!(reassembler.add(get_sender(), fragments[1].clone(), now).unwrap(), Some(msg_bytes));
+    }
+
+    #[test]
+    fn test_reassemble_rejects_invalid_fragments() {
+        let mut reassembler: Reassembler = Reassembler::default();
+        let now: Instant = Instant::now();
+
+        let mut fragment: UDPMessage = UDPMessage::new();
+        fragment.fragment_index = Some(2);
+        fragment.fragment_count = Some(2);
+        assert!(reassembler.add(get_sender(), fragment.clone(), now).is_err());
+
+        fragment.fragment_index = Some(0);
+        fragment.fragment_count = Some(u32::MAX);
+        assert!(reassembler.add(get_sender(), fragment.clone(), now).is_err());
+
+        // An empty fragment would hold a slot for every other fragment at
+        // no cost of its own
+        fragment.fragment_count = Some(2);
+        assert!(reassembler.add(get_sender(), fragment, now).is_err());
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn test_reassemble_charges_slots_and_caps_messages_per_sender() {
+        let mut reassembler: Reassembler = Reassembler::default();
+        let now: Instant = Instant::now();
+        let create_fragment = |id: usize| {
+            let mut fragment: UDPMessage = UDPMessage::new();
+            fragment.id = id.to_be_bytes().to_vec();
+            fragment.payload = vec![0];
+            fragment.fragment_index = Some(0);
+            fragment.fragment_count = Some(4);
+            fragment
+        };
+
+        assert_eq!(reassembler.add(get_sender(), create_fragment(0), now).unwrap(), None);
+        assert_eq!(reassembler.size, 4 * size_of::<Option<Vec<u8>>>() + 1);
+
+        for id in 1..MAX_PARTIAL_MESSAGES_PER_SENDER {
+            assert_eq!(reassembler.add(get_sender(), create_fragment(id), now).unwrap(), None);
+        }
+        let e: Error = reassembler.add(get_sender(), create_fragment(MAX_PARTIAL_MESSAGES_PER_SENDER), now).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::OutOfMemory);
+
+        // Other senders are not held back
+        let other: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        assert_eq!(reassembler.add(other, create_fragment(0), now).unwrap(), None);
+
+        assert!(reassembler.get_retransmit_requests(now + REASSEMBLY_TIMEOUT * 2).is_empty());
+        assert!(reassembler.is_empty());
+        assert!(reassembler.num_partial_per_sender.is_empty());
+        assert_eq!(reassembler.size, 0);
+    }
+
+    #[test]
+    fn test_retransmit_requests_for_stalled_message() {
+        let msg_bytes: Vec<u8> = get_message_bytes(MAX_FRAGMENT_PAYLOAD_BYTES * 2 + 1);
+        let fragments: Vec<UDPMessage> = split_message(b"id", &msg_bytes).unwrap();
+
+        let mut reassembler: Reassembler = Reassembler::default();
+        let now: Instant = Instant::now();
+        reassembler.add(get_sender(), fragments[1].clone(), now).unwrap();
+        assert!(reassembler.get_retransmit_requests(now).is_empty());
+
+        let requests: Vec<(SocketAddr, UDPMessage)> = reassembler.get_retransmit_requests(now + RETRANSMIT_INTERVAL);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].0, get_sender());
+        assert!(is_retransmit_request(&requests[0].1));
+        assert_eq!(requests[0].1.missing_fragments, vec![0, 2]);
+
+        // Asked again only once another interval has passed
+        assert!(reassembler.get_retransmit_requests(now + RETRANSMIT_INTERVAL).is_empty());
+        assert_eq!(reassembler.get_retransmit_requests(now + RETRANSMIT_INTERVAL * 2).len(), 1);
+
+        // And given up on once it has stalled for too long
+        let late: Instant = now + REASSEMBLY_TIMEOUT + RETRANSMIT_INTERVAL;
+        assert!(reassembler.get_retransmit_requests(late).is_empty());
+        assert!(reassembler.is_empty());
+        assert_eq!(reassembler.size, 0);
+    }
+
+    #[test]
+    fn test_sent_fragments_expire() {
+        let mut sent: SentFragments = SentFragments::default();
+        let now: Instant = Instant::now();
+        sent.insert(b"old".to_vec(), get_sender(), vec![vec![0], vec![1]], now);
+        assert_eq!(sent.get(b"old", get_sender(), &[1, 5]), vec![vec![1]]);
+
+        sent.insert(b"new".to_vec(), get_sender(), vec![vec![2]], now + REASSEMBLY_TIMEOUT * 2);
+        assert!(sent.get(b"old", get_sender(), &[0]).is_empty());
+        assert_eq!(sent.get(b"new", get_sender(), &[0]), vec![vec![2]]);
+        assert_eq!(sent.size, 1);
+    }
+
+    #[test]
+    fn test_sent_fragments_only_resent_to_destination() {
+        let mut sent: SentFragments = SentFragments::default();
+        sent.insert(b"id".to_vec(), get_sender(), vec![vec![0], vec![1]], Instant::now());
+        let other: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        assert!(sent.get(b"id", other, &[0, 1]).is_empty());
+        assert_eq!(sent.get(b"id", get_sender(), &[0, 1]).len(), 2);
+    }
+}
@@ -1,155 +1,398 @@
 use core::option::Option;
 use std::io::{Result, Error, ErrorKind};
-use std::net::{SocketAddr, IpAddr, UdpSocket};
-use std::time::Duration;
+use std::net::{SocketAddr, IpAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use protobuf::Message;
 
+use crate::comm::fragment::{Reassembler, SentFragments, MAX_MESSAGE_SIZE_BYTES, REASSEMBLY_TIMEOUT, RETRANSMIT_INTERVAL};
+use crate::comm::pipeline::{Pipeline, MAX_IN_FLIGHT};
 use crate::comm::proto::parse_message;
 use crate::comm::protogen::api::{UDPMessage};
+use crate::comm::transport::{Transport, UdpTransport};
 
+#[cfg(feature = "async")]
+pub mod async_interface;
+pub mod fragment;
+pub mod pipeline;
 pub mod proto;
 pub mod protogen;
+pub mod transport;
 
 const SEND_RECV_TIMEOUT: Duration = Duration::from_millis(100);
 const LISTENING_TIMEOUT: Duration = Duration::from_millis(1000);
 const MAX_RETRIES: u32 = 3;
-const MAX_BUFFER_SIZE_BYTES: usize = 1024 * 12;
+pub const MAX_BUFFER_SIZE_BYTES: usize = 1024 * 12;
 const TIMEOUT_MULTIPLIER: u32 = 2;
 
-pub struct ProtoInterface {
-    udp_interface: UdpInterface,
+/// What a datagram amounts to once taken in
+enum Received {
+    /// A whole message, arrived in one piece or put back together
+    Message(UDPMessage),
+    /// A request for fragments sent earlier, answered with these
+    Retransmit(Vec<Vec<u8>>),
+    /// A fragment of a message still missing others
+    Fragment,
+}
+
+/// Sends and receives protobuf messages over a `Transport`, UDP by default.
+/// Messages too large for a single datagram are split into fragments and put
+/// back together on the receiving end, which asks for any fragments lost on
+/// the way.
+pub struct ProtoInterface<T: Transport = UdpTransport> {
+    transport: T,
     ip: IpAddr,
     port: u16,
+    reassembler: Mutex<Reassembler>,
+    sent_fragments: Mutex<SentFragments>,
+    send_recv_timeout: Duration,
+    listening_timeout: Duration,
+    max_retries: u32,
 }
 
-impl ProtoInterface {
+impl ProtoInterface<UdpTransport> {
     pub fn new(socket_addr: SocketAddr) -> Result<Self> {
-        let udp_interface: UdpInterface = UdpInterface::new(
-            socket_addr,
-            Some(SEND_RECV_TIMEOUT),
-            Some(LISTENING_TIMEOUT),
-            MAX_RETRIES
-        )?;
+        Self::bind(socket_addr)
+    }
+}
+
+impl<T: Transport> ProtoInterface<T> {
+    pub fn bind(socket_addr: SocketAddr) -> Result<Self> {
+        let transport: T = T::bind(socket_addr)?;
         let ip = socket_addr.ip();
         let port = socket_addr.port();
-        Ok(ProtoInterface {udp_interface, ip, port})
+        let reassembler: Mutex<Reassembler> = Mutex::new(Reassembler::default());
+        let sent_fragments: Mutex<SentFragments> = Mutex::new(SentFragments::default());
+        Ok(ProtoInterface {
+            transport,
+            ip,
+            port,
+            reassembler,
+            sent_fragments,
+            send_recv_timeout: SEND_RECV_TIMEOUT,
+            listening_timeout: LISTENING_TIMEOUT,
+            max_retries: MAX_RETRIES,
+        })
+    }
+
+    /// Largest message that can be sent, whole or in fragments.
+    pub fn max_message_size(&self) -> usize {
+        MAX_MESSAGE_SIZE_BYTES
+    }
+
+    /// Whether `addr` is the other end of a connection, so that only this
+    /// interface can reach it.
+    pub fn is_connected(&self, addr: SocketAddr) -> bool {
+        self.transport.is_connected(addr)
     }
 
     pub fn send(&self, message: impl Message, server_addr: SocketAddr) -> Result<usize> {
         let udp_message: UDPMessage = proto::create_udp_message(message, self.ip, self.port)?;
-        let msg_bytes: Vec<u8> = UDPMessage::write_to_bytes(&udp_message)?;
-        self.udp_interface.send(msg_bytes.as_slice(), server_addr)
+        self.send_udp_message(&udp_message, server_addr)
     }
 
-    pub fn listen(&self) -> Result<(UDPMessage, SocketAddr)> {
-        let mut buf: [u8; MAX_BUFFER_SIZE_BYTES] = [0; MAX_BUFFER_SIZE_BYTES];
-        let (size, sender_addr) = match self.udp_interface.listen(&mut buf) {
-            Ok((size, sender_addr)) => (size, sender_addr),
-            Err(e) => {
-                return Err(e);
-            }
-        };
+    /// Sends `message` as the reply to the request with Id `request_id`.
+    pub fn reply(&self, message: impl Message, request_id: &[u8], client_addr: SocketAddr) -> Result<usize> {
+        let udp_message: UDPMessage = proto::create_reply_message(message, request_id)?;
+        self.send_udp_message(&udp_message, client_addr)
+    }
 
-        let recv_data: Vec<u8> = buf[0..size].to_vec();
-        let message: UDPMessage = parse_message(recv_data)?;
+    pub fn forward(&self, message: &UDPMessage, server_addr: SocketAddr) -> Result<usize> {
+        self.send_udp_message(message, server_addr)
+    }
 
-        match proto::validate_checksum(&message) {
-            Ok(_) => Ok((message, sender_addr)),
-            Err(e) => Err(e)
-        }
+    pub fn listen(&self) -> Result<(UDPMessage, SocketAddr)> {
+        self.recv_message(self.listening_timeout)
     }
 
+    /// Sends `message` and waits for the reply sharing its Id. Replies to
+    /// earlier requests arriving meanwhile are discarded.
     pub fn send_and_recv(&self, message: impl Message, server_addr: SocketAddr) -> Result<(UDPMessage, SocketAddr)> {
         let udp_message: UDPMessage = proto::create_udp_message(message, self.ip, self.port)?;
-        let msg_bytes: Vec<u8> = UDPMessage::write_to_bytes(&udp_message)?;
-        let mut buf: [u8; MAX_BUFFER_SIZE_BYTES] = [0; MAX_BUFFER_SIZE_BYTES];
-        let (size, sender_addr) = self.udp_interface.send_and_recv(&msg_bytes, server_addr, &mut buf)?;
-
-        let recv_data: Vec<u8> = buf[0..size].to_vec();
-        let message: UDPMessage = parse_message(recv_data)?;
+        let max_attempted_sends: u32 = self.max_retries + 1;
+        let mut timeout: Duration = self.send_recv_timeout;
 
-        match proto::validate_checksum(&message) {
-            Ok(_) => Ok((message, sender_addr)),
-            Err(e) => Err(e),
+        for _ in 0..max_attempted_sends {
+            self.send_udp_message(&udp_message, server_addr)?;
+            match self.recv_reply(&udp_message.id, timeout) {
+                Ok((message, sender_addr)) => return Ok((message, sender_addr)),
+                Err(e) => {
+                    if e.kind() == ErrorKind::TimedOut {
+                        timeout = timeout.checked_mul(TIMEOUT_MULTIPLIER).unwrap();
+                        continue;
+                    }
+                    return Err(e);
+                }
+            }
         }
+
+        Err(Error::new(ErrorKind::TimedOut, "Timed out"))
     }
-}
 
-struct UdpInterface {
-    socket: UdpSocket,
-    send_recv_timeout: Option<Duration>,
-    listening_timeout: Option<Duration>,
-    max_retries: u32,
-}
+    /// Sends `message` and waits up to `timeout` in all for its reply,
+    /// sending it again with a longer wait each time, for requests that take
+    /// the server longer to answer than `send_and_recv` waits.
+    pub fn send_and_recv_within(&self, message: impl Message, server_addr: SocketAddr, timeout: Duration) -> Result<(UDPMessage, SocketAddr)> {
+        let udp_message: UDPMessage = proto::create_udp_message(message, self.ip, self.port)?;
+        let deadline: Instant = Instant::now() + timeout;
+        let mut wait: Duration = self.send_recv_timeout;
 
-impl UdpInterface {
-    pub fn new(
-        socket_addr: SocketAddr,
-        send_recv_timeout: Option<Duration>,
-        listening_timeout: Option<Duration>,
-        max_retries: u32
-    ) -> Result<Self> {
-        let socket: UdpSocket = match UdpSocket::bind(socket_addr) {
-            Ok(socket) => socket,
-            Err(e) => return Err(e),
-        };
-        Ok(UdpInterface {socket, send_recv_timeout, listening_timeout, max_retries})
+        loop {
+            self.send_udp_message(&udp_message, server_addr)?;
+            let remaining: Duration = deadline.saturating_duration_since(Instant::now());
+            match self.recv_reply(&udp_message.id, wait.min(remaining)) {
+                Ok((message, sender_addr)) => return Ok((message, sender_addr)),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if Instant::now() >= deadline {
+                        return Err(Error::new(ErrorKind::TimedOut, "Timed out"));
+                    }
+                    wait = wait.checked_mul(TIMEOUT_MULTIPLIER).unwrap();
+                },
+                Err(e) => return Err(e),
+            }
+        }
     }
 
-    pub fn send(&self, message: &[u8], server_addr: SocketAddr) -> Result<usize> {
-        self.socket.send_to(message, server_addr)
+    /// Sends `message` a single time and waits up to `timeout` for its reply,
+    /// for callers with somewhere else to turn should none come.
+    pub fn send_and_recv_once(&self, message: impl Message, server_addr: SocketAddr, timeout: Duration) -> Result<(UDPMessage, SocketAddr)> {
+        let udp_message: UDPMessage = proto::create_udp_message(message, self.ip, self.port)?;
+        self.send_udp_message(&udp_message, server_addr)?;
+        self.recv_reply(&udp_message.id, timeout)
     }
 
-    pub fn listen(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
-        self.socket.set_read_timeout(self.listening_timeout)?;
-        self.socket.recv_from(buf)
+    /// Sends every request in `requests` to its server and returns the
+    /// replies in the same order, keeping up to `MAX_IN_FLIGHT` requests
+    /// awaiting replies at once.
+    ///
+    /// Replies are matched to requests by the Id they share, so a reply
+    /// arriving after its request was answered or given up on is discarded.
+    /// Each request is sent again on its own schedule, with a longer wait
+    /// each time, until answered or out of retries.
+    pub fn send_and_recv_all<M: Message>(&self, requests: Vec<(M, SocketAddr)>) -> Vec<Result<(UDPMessage, SocketAddr)>> {
+        let num_requests: usize = requests.len();
+        let mut replies: Vec<Option<Result<(UDPMessage, SocketAddr)>>> = (0..num_requests).map(|_| None).collect();
+        let mut pipeline: Pipeline = Pipeline::new(
+            MAX_IN_FLIGHT,
+            self.send_recv_timeout,
+            TIMEOUT_MULTIPLIER,
+            self.max_retries + 1
+        );
+        let mut requests = requests.into_iter().enumerate();
+
+        loop {
+            while pipeline.has_room() {
+                let (index, (message, server_addr)) = match requests.next() {
+                    Some(request) => request,
+                    None => break,
+                };
+                let sent: Result<UDPMessage> = proto::create_udp_message(message, self.ip, self.port)
+                    .and_then(|udp_message| self.send_udp_message(&udp_message, server_addr).map(|_| udp_message));
+                match sent {
+                    Ok(udp_message) => pipeline.insert(index, udp_message, server_addr, Instant::now()),
+                    Err(e) => replies[index] = Some(Err(e)),
+                }
+            }
+
+            let deadline: Instant = match pipeline.next_deadline() {
+                Some(deadline) => deadline,
+                None => break,
+            };
+            let wait: Duration = deadline.saturating_duration_since(Instant::now());
+            match self.recv_message(wait) {
+                Ok((message, sender_addr)) => match pipeline.complete(&message.id) {
+                    Some(index) => replies[index] = Some(Ok((message, sender_addr))),
+                    None => log::debug!("Discarding stale reply from {}", sender_addr),
+                },
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => (),
+                Err(e) => log::debug!("Failed to receive reply: {}", e),
+            }
+
+            let (resends, timed_out) = pipeline.take_due(Instant::now());
+            for (udp_message, server_addr) in resends {
+                if let Err(e) = self.send_udp_message(&udp_message, server_addr) {
+                    log::debug!("Failed to resend request to {}: {}", server_addr, e);
+                }
+            }
+            for index in timed_out {
+                replies[index] = Some(Err(Error::new(ErrorKind::TimedOut, "Timed out")));
+            }
+        }
+
+        replies
+            .into_iter()
+            .map(|reply| reply.unwrap_or_else(|| Err(Error::new(ErrorKind::TimedOut, "Timed out"))))
+            .collect()
     }
 
-    pub fn send_and_recv(&self, message: &[u8], server_addr: SocketAddr, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
-        self.socket.set_read_timeout(self.send_recv_timeout)?;
-        self.do_send_and_recv(message, server_addr, buf)
+    /// Sends `message` whole if the transport takes it in one piece, or else
+    /// as fragments that are kept for a while in case the receiver asks for
+    /// them again.
+    fn send_udp_message(&self, message: &UDPMessage, server_addr: SocketAddr) -> Result<usize> {
+        let datagrams: Vec<Vec<u8>> = encode_datagrams(message, self.transport.max_datagram_size(server_addr))?;
+        let mut size: usize = 0;
+        for datagram in datagrams.iter() {
+            size += self.transport.send(datagram.as_slice(), server_addr)?;
+        }
+
+        if datagrams.len() > 1 {
+            self.sent_fragments.lock().unwrap().insert(message.id.clone(), server_addr, datagrams, Instant::now());
+        }
+        Ok(size)
     }
 
-    fn do_send_and_recv(&self, message: &[u8], server_addr: SocketAddr, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
-        let max_attempted_sends: u32 = self.max_retries + 1;
-        let timeout: Duration = match self.send_recv_timeout {
-            Some(t) => t,
-            None => Duration::from_millis(100),
-        };
+    /// Waits up to `timeout` for the next whole message. Fragments and
+    /// retransmit requests are dealt with along the way, and push the
+    /// deadline back since a large exchange is still making progress, but
+    /// never by more than `REASSEMBLY_TIMEOUT` in all so that no sender can
+    /// keep the wait going forever.
+    fn recv_message(&self, timeout: Duration) -> Result<(UDPMessage, SocketAddr)> {
+        let mut deadline: Instant = Instant::now() + timeout;
+        let max_deadline: Instant = deadline + REASSEMBLY_TIMEOUT;
 
-        for _ in 0..max_attempted_sends {
-            match self.socket.send_to(message, server_addr) {
-                Ok(_size) => {
-                    match self.socket.recv_from(buf) {
-                        Ok((size, addr)) => {
-                            return Ok((size, addr))
-                        },
-                        Err(e) => {
-                            let timeout = timeout.checked_mul(TIMEOUT_MULTIPLIER).unwrap();
-                            if e.kind() == ErrorKind::TimedOut {
-                                self.socket.set_read_timeout(Some(timeout))?;
-                                continue;
-                            }
-                            return Err(e);
-                        }
+        loop {
+            let now: Instant = Instant::now();
+            let is_reassembling: bool = self.send_retransmit_requests(now);
+            let mut wait: Duration = deadline.saturating_duration_since(now).max(Duration::from_millis(1));
+            if is_reassembling {
+                wait = wait.min(RETRANSMIT_INTERVAL);
+            }
+
+            let (msg_bytes, sender_addr) = match self.transport.recv(wait) {
+                Ok((msg_bytes, sender_addr)) => (msg_bytes, sender_addr),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if Instant::now() >= deadline {
+                        return Err(e);
                     }
+                    continue;
                 },
-                Err(e) => {
-                    return Err(e)
-                },
+                Err(e) => return Err(e),
+            };
+
+            match self.take_datagram(msg_bytes, sender_addr)? {
+                Some(message) => return Ok((message, sender_addr)),
+                None => deadline = (Instant::now() + timeout).min(max_deadline),
             }
         }
+    }
 
-        Err(Error::new(ErrorKind::TimedOut, "Timed out"))
+    /// Waits up to `timeout` for the reply to the request with Id `id`,
+    /// discarding any other message arriving meanwhile.
+    fn recv_reply(&self, id: &[u8], timeout: Duration) -> Result<(UDPMessage, SocketAddr)> {
+        let deadline: Instant = Instant::now() + timeout;
+        loop {
+            let wait: Duration = deadline.saturating_duration_since(Instant::now());
+            let (message, sender_addr) = self.recv_message(wait)?;
+            if message.id == id {
+                return Ok((message, sender_addr));
+            }
+            log::debug!("Discarding stale reply from {}", sender_addr);
+        }
+    }
+
+    /// Takes in a datagram from `sender_addr`, answering it if it asks for
+    /// fragments again, and returns the message it completes, if any.
+    pub fn take_datagram(&self, msg_bytes: Vec<u8>, sender_addr: SocketAddr) -> Result<Option<UDPMessage>> {
+        match receive_datagram(msg_bytes, sender_addr, &self.reassembler, &self.sent_fragments)? {
+            Received::Message(message) => Ok(Some(message)),
+            Received::Retransmit(fragments) => {
+                for fragment in fragments {
+                    self.transport.send(fragment.as_slice(), sender_addr)?;
+                }
+                Ok(None)
+            },
+            Received::Fragment => Ok(None),
+        }
+    }
+
+    /// Asks the senders of stalled messages for their missing fragments,
+    /// returning whether any message is still being reassembled.
+    pub fn send_retransmit_requests(&self, now: Instant) -> bool {
+        let (requests, is_reassembling) = get_retransmit_requests(&self.reassembler, now);
+        for (sender_addr, request_bytes) in requests {
+            let _ = self.transport.send(request_bytes.as_slice(), sender_addr);
+        }
+        is_reassembling
     }
 }
 
+/// The datagrams carrying `message`: the message whole if it fits in one,
+/// or else its fragments.
+fn encode_datagrams(message: &UDPMessage, max_datagram_size: Option<usize>) -> Result<Vec<Vec<u8>>> {
+    let msg_bytes: Vec<u8> = UDPMessage::write_to_bytes(message)?;
+    match max_datagram_size {
+        Some(max_size) if msg_bytes.len() > max_size => (),
+        _ => return Ok(vec![msg_bytes]),
+    }
+
+    let mut fragment_bytes: Vec<Vec<u8>> = Vec::new();
+    for fragment in fragment::split_message(&message.id, &msg_bytes)? {
+        fragment_bytes.push(UDPMessage::write_to_bytes(&fragment)?);
+    }
+    Ok(fragment_bytes)
+}
+
+/// Parses a datagram from `sender_addr`, adding it to the message it is a
+/// fragment of or looking up the fragments it asks for again.
+fn receive_datagram(
+    msg_bytes: Vec<u8>,
+    sender_addr: SocketAddr,
+    reassembler: &Mutex<Reassembler>,
+    sent_fragments: &Mutex<SentFragments>
+) -> Result<Received> {
+    let message: UDPMessage = parse_message(msg_bytes)?;
+    proto::validate_checksum(&message)?;
+
+    if fragment::is_retransmit_request(&message) {
+        let fragments: Vec<Vec<u8>> = sent_fragments
+            .lock()
+            .unwrap()
+            .get(&message.id, sender_addr, &message.missing_fragments);
+        return Ok(Received::Retransmit(fragments));
+    }
+
+    if fragment::is_fragment(&message) {
+        let msg_bytes_opt: Option<Vec<u8>> = reassembler
+            .lock()
+            .unwrap()
+            .add(sender_addr, message, Instant::now())?;
+        return match msg_bytes_opt {
+            Some(msg_bytes) => {
+                let message: UDPMessage = parse_message(msg_bytes)?;
+                proto::validate_checksum(&message)?;
+                Ok(Received::Message(message))
+            },
+            None => Ok(Received::Fragment),
+        };
+    }
+
+    Ok(Received::Message(message))
+}
+
+/// The requests for missing fragments due by `now`, each with the address to
+/// send it to, and whether any message is still being reassembled.
+fn get_retransmit_requests(reassembler: &Mutex<Reassembler>, now: Instant) -> (Vec<(SocketAddr, Vec<u8>)>, bool) {
+    let mut reassembler = reassembler.lock().unwrap();
+    let requests: Vec<(SocketAddr, Vec<u8>)> = reassembler
+        .get_retransmit_requests(now)
+        .into_iter()
+        .filter_map(|(sender_addr, request)| {
+            UDPMessage::write_to_bytes(&request).ok().map(|request_bytes| (sender_addr, request_bytes))
+        })
+        .collect();
+    (requests, !reassembler.is_empty())
+}
+
 #[cfg(test)]
 mod tests {
     use protogen::api::Request;
 
     use super::*;
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
     use crate::comm::proto::Operation;
+    use crate::comm::transport::TcpTransport;
 
     fn create_client_and_server() -> (ProtoInterface, SocketAddr, ProtoInterface, SocketAddr) {
         let server_addr: SocketAddr = UdpSocket::bind("127.0.0.1:0")
@@ -189,4 +432,182 @@ mod tests {
         let result = client_interface.send_and_recv(sent_request.clone(), server_addr);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_proto_interface_send_and_recv_once_times_out() {
+        let (client_interface, _, _server_interface, server_addr) = create_client_and_server();
+        let mut sent_request: Request = Request::new();
+        sent_request.operation = Operation::Ping as u32;
+
+        let start: Instant = Instant::now();
+        let e: Error = client_interface.send_and_recv_once(sent_request, server_addr, Duration::from_millis(50)).unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut));
+        assert!(start.elapsed() < SEND_RECV_TIMEOUT * 2);
+    }
+
+    #[test]
+    fn test_proto_interface_stalled_fragments_extend_wait_only_so_far() {
+        let (client_interface, _, server_interface, server_addr) = create_client_and_server();
+        let is_done: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+
+        // The first fragment of a message that never completes, sent again
+        // and again
+        let mut fragment: UDPMessage = UDPMessage::new();
+        fragment.id = b"stalled".to_vec();
+        fragment.payload = vec![0];
+        fragment.checksum = proto::calculate_checksum(&fragment.id, &fragment.payload);
+        fragment.fragment_index = Some(0);
+        fragment.fragment_count = Some(2);
+        let fragment_bytes: Vec<u8> = UDPMessage::write_to_bytes(&fragment).unwrap();
+        let sender = {
+            let is_done: Arc<AtomicBool> = is_done.clone();
+            std::thread::spawn(move || {
+                while !is_done.load(Ordering::SeqCst) {
+                    let _ = client_interface.transport.send(&fragment_bytes, server_addr);
+                    std::thread::sleep(RETRANSMIT_INTERVAL);
+                }
+            })
+        };
+
+        let timeout: Duration = Duration::from_millis(50);
+        let start: Instant = Instant::now();
+        assert!(server_interface.recv_message(timeout).is_err());
+        assert!(start.elapsed() < timeout + REASSEMBLY_TIMEOUT + SEND_RECV_TIMEOUT);
+        is_done.store(true, Ordering::SeqCst);
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn test_proto_interface_receive_large_message() {
+        let (client_interface, client_addr, server_interface, server_addr) = create_client_and_server();
+
+        let mut sent_request: Request = Request::new();
+        sent_request.operation = Operation::Put as u32;
+        sent_request.value = Some(vec![7; MAX_BUFFER_SIZE_BYTES * 4]);
+        client_interface.send(sent_request.clone(), server_addr).unwrap();
+
+        let (received_message, sender_socket) = server_interface.listen().unwrap();
+        let received_request = Request::parse_from_bytes(&received_message.payload).unwrap();
+
+        assert_eq!(received_request, sent_request);
+        assert_eq!(sender_socket, client_addr);
+    }
+
+    #[test]
+    fn test_proto_interface_retransmits_lost_fragment() {
+        let (client_interface, _, server_interface, server_addr) = create_client_and_server();
+
+        let mut sent_request: Request = Request::new();
+        sent_request.operation = Operation::Put as u32;
+        sent_request.value = Some(vec![7; MAX_BUFFER_SIZE_BYTES * 2]);
+        let udp_message: UDPMessage = proto::create_udp_message(sent_request.clone(), client_interface.ip, client_interface.port).unwrap();
+        let msg_bytes: Vec<u8> = UDPMessage::write_to_bytes(&udp_message).unwrap();
+
+        // Every fragment but the second is sent, as though it was lost
+        let fragments: Vec<Vec<u8>> = fragment::split_message(&udp_message.id, &msg_bytes)
+            .unwrap()
+            .iter()
+            .map(|fragment| UDPMessage::write_to_bytes(fragment).unwrap())
+            .collect();
+        for (index, fragment) in fragments.iter().enumerate() {
+            if index != 1 {
+                client_interface.transport.send(fragment, server_addr).unwrap();
+            }
+        }
+        client_interface.sent_fragments.lock().unwrap().insert(udp_message.id.clone(), server_addr, fragments, Instant::now());
+
+        let server = std::thread::spawn(move || server_interface.listen().unwrap());
+        // The client answers the server's request for the lost fragment while
+        // it waits for a reply that never comes
+        let _ = client_interface.recv_message(Duration::from_millis(200));
+
+        let (received_message, _) = server.join().unwrap();
+        let received_request = Request::parse_from_bytes(&received_message.payload).unwrap();
+        assert_eq!(received_request, sent_request);
+    }
+
+    #[test]
+    fn test_proto_interface_discards_stale_reply() {
+        let (client_interface, client_addr, server_interface, server_addr) = create_client_and_server();
+
+        let server = std::thread::spawn(move || {
+            let (message, _) = server_interface.listen().unwrap();
+            let request: Request = Request::parse_from_bytes(&message.payload).unwrap();
+            let mut stale_request: Request = request.clone();
+            stale_request.operation = Operation::Get as u32;
+            server_interface.reply(stale_request, b"earlier request", client_addr).unwrap();
+            server_interface.reply(request, &message.id, client_addr).unwrap();
+        });
+
+        let mut sent_request: Request = Request::new();
+        sent_request.operation = Operation::Ping as u32;
+        let (reply, _) = client_interface.send_and_recv(sent_request.clone(), server_addr).unwrap();
+        assert_eq!(Request::parse_from_bytes(&reply.payload).unwrap(), sent_request);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_proto_interface_send_and_recv_all() {
+        let (client_interface, client_addr, server_interface, server_addr) = create_client_and_server();
+        const NUM_REQUESTS: usize = MAX_IN_FLIGHT + 8;
+
+        // Answers in reverse order as requests come in, twice for the first
+        // and not at all the first time the second arrives
+        let server = std::thread::spawn(move || {
+            let mut num_seen: usize = 0;
+            let mut held: Vec<UDPMessage> = Vec::new();
+            while let Ok((message, _)) = server_interface.listen() {
+                num_seen += 1;
+                let request: Request = Request::parse_from_bytes(&message.payload).unwrap();
+                match request.limit {
+                    Some(0) => {
+                        server_interface.reply(request, &message.id, client_addr).unwrap();
+                    },
+                    Some(1) if num_seen <= NUM_REQUESTS => continue,
+                    _ => (),
+                }
+                held.push(message);
+                if held.len() >= 8 || num_seen >= NUM_REQUESTS {
+                    for message in held.drain(..).rev() {
+                        let request: Request = Request::parse_from_bytes(&message.payload).unwrap();
+                        server_interface.reply(request, &message.id, client_addr).unwrap();
+                    }
+                }
+            }
+        });
+
+        let requests: Vec<(Request, SocketAddr)> = (0..NUM_REQUESTS)
+            .map(|i| {
+                let mut request: Request = Request::new();
+                request.operation = Operation::Get as u32;
+                request.limit = Some(i as u32);
+                (request, server_addr)
+            })
+            .collect();
+        let replies: Vec<Result<(UDPMessage, SocketAddr)>> = client_interface.send_and_recv_all(requests.clone());
+        assert_eq!(replies.len(), NUM_REQUESTS);
+        for ((request, _), reply) in requests.iter().zip(replies) {
+            let (reply, _) = reply.unwrap();
+            assert_eq!(Request::parse_from_bytes(&reply.payload).unwrap(), *request);
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_proto_interface_sends_large_message_whole_over_tcp() {
+        let client_interface: ProtoInterface<TcpTransport> = ProtoInterface::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let server_interface: ProtoInterface<TcpTransport> = ProtoInterface::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut sent_request: Request = Request::new();
+        sent_request.operation = Operation::Put as u32;
+        sent_request.value = Some(vec![7; MAX_BUFFER_SIZE_BYTES * 4]);
+        let server_addr: SocketAddr = server_interface.transport.local_addr();
+        client_interface.send(sent_request.clone(), server_addr).unwrap();
+        assert!(client_interface.is_connected(server_addr));
+
+        let (received_message, sender_addr) = server_interface.listen().unwrap();
+        let received_request = Request::parse_from_bytes(&received_message.payload).unwrap();
+        assert_eq!(received_request, sent_request);
+        assert!(server_interface.is_connected(sender_addr));
+    }
 }
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::comm::protogen::api::UDPMessage;
+
+/// Requests kept awaiting replies at once by a pipelined exchange
+pub const MAX_IN_FLIGHT: usize = 64;
+
+/// A request sent and not yet answered
+struct InFlight {
+    index: usize,
+    message: UDPMessage,
+    server_addr: SocketAddr,
+    num_sends: u32,
+    timeout: Duration,
+    resend_at: Instant,
+}
+
+/// Requests sent and awaiting replies, keyed by message Id, each sent again
+/// on a schedule of its own. The wait before each resend doubles, and a
+/// request is given up on once it was sent `max_sends` times and its last
+/// wait ran out.
+pub struct Pipeline {
+    in_flight: HashMap<Vec<u8>, InFlight>,
+    max_in_flight: usize,
+    first_timeout: Duration,
+    timeout_multiplier: u32,
+    max_sends: u32,
+}
+
+impl Pipeline {
+    pub fn new(max_in_flight: usize, first_timeout: Duration, timeout_multiplier: u32, max_sends: u32) -> Self {
+        Pipeline {
+            in_flight: HashMap::new(),
+            max_in_flight,
+            first_timeout,
+            timeout_multiplier,
+            max_sends,
+        }
+    }
+
+    pub fn has_room(&self) -> bool {
+        self.in_flight.len() < self.max_in_flight
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Records `message`, the request numbered `index`, as first sent to
+    /// `server_addr` at `now`.
+    pub fn insert(&mut self, index: usize, message: UDPMessage, server_addr: SocketAddr, now: Instant) {
+        let in_flight: InFlight = InFlight {
+            index,
+            message,
+            server_addr,
+            num_sends: 1,
+            timeout: self.first_timeout,
+            resend_at: now + self.first_timeout,
+        };
+        self.in_flight.insert(in_flight.message.id.clone(), in_flight);
+    }
+
+    /// The number of the request that a reply with Id `id` answers, or
+    /// `None` if the reply is stale: its request was answered already, was
+    /// given up on, or was never sent.
+    pub fn complete(&mut self, id: &[u8]) -> Option<usize> {
+        self.in_flight.remove(id).map(|in_flight| in_flight.index)
+    }
+
+    /// When the next request is due to be sent again or given up on.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.in_flight.values().map(|in_flight| in_flight.resend_at).min()
+    }
+
+    /// The requests whose wait ran out by `now`: those to send again, each
+    /// with the address to send it to, and the numbers of those given up on.
+    pub fn take_due(&mut self, now: Instant) -> (Vec<(UDPMessage, SocketAddr)>, Vec<usize>) {
+        let mut resends: Vec<(UDPMessage, SocketAddr)> = Vec::new();
+        let mut timed_out: Vec<Vec<u8>> = Vec::new();
+        for (id, in_flight) in self.in_flight.iter_mut() {
+            if in_flight.resend_at > now {
+                continue;
+            }
+            if in_flight.num_sends >= self.max_sends {
+                timed_out.push(id.clone());
+                continue;
+            }
+            in_flight.num_sends += 1;
+            in_flight.timeout = in_flight.timeout.saturating_mul(self.timeout_multiplier);
+            in_flight.resend_at = now + in_flight.timeout;
+            resends.push((in_flight.message.clone(), in_flight.server_addr));
+        }
+
+        let timed_out: Vec<usize> = timed_out
+            .iter()
+            .filter_map(|id| self.in_flight.remove(id))
+            .map(|in_flight| in_flight.index)
+            .collect();
+        (resends, timed_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn get_server() -> SocketAddr {
+        "127.0.0.1:9000".parse().unwrap()
+    }
+
+    fn create_message(id: &[u8]) -> UDPMessage {
+        let mut message: UDPMessage = UDPMessage::new();
+        message.id = id.to_vec();
+        message
+    }
+
+    #[test]
+    fn test_replies_matched_by_id() {
+        let mut pipeline: Pipeline = Pipeline::new(2, TIMEOUT, 2, 3);
+        let now: Instant = Instant::now();
+        pipeline.insert(0, create_message(b"first"), get_server(), now);
+        assert!(pipeline.has_room());
+        pipeline.insert(1, create_message(b"second"), get_server(), now);
+        assert!(!pipeline.has_room());
+
+        assert_eq!(pipeline.complete(b"second"), Some(1));
+        // A second reply to the same request is stale, as is one to no request
+        assert_eq!(pipeline.complete(b"second"), None);
+        assert_eq!(pipeline.complete(b"other"), None);
+        assert_eq!(pipeline.complete(b"first"), Some(0));
+        assert!(pipeline.is_empty());
+    }
+
+    #[test]
+    fn test_each_request_resent_on_its_own_schedule() {
+        let mut pipeline: Pipeline = Pipeline::new(8, TIMEOUT, 2, 3);
+        let now: Instant = Instant::now();
+        pipeline.insert(0, create_message(b"early"), get_server(), now);
+        pipeline.insert(1, create_message(b"late"), get_server(), now + TIMEOUT / 2);
+        assert_eq!(pipeline.next_deadline(), Some(now + TIMEOUT));
+
+        let (resends, timed_out) = pipeline.take_due(now + TIMEOUT);
+        assert_eq!(resends.len(), 1);
+        assert_eq!(resends[0].0.id, b"early");
+        assert!(timed_out.is_empty());
+        // The wait before the next resend doubles
+        assert_eq!(pipeline.next_deadline(), Some(now + TIMEOUT / 2 + TIMEOUT));
+        let (resends, _) = pipeline.take_due(now + TIMEOUT * 2);
+        assert_eq!(resends.len(), 1);
+        assert_eq!(resends[0].0.id, b"late");
+        assert_eq!(pipeline.next_deadline(), Some(now + TIMEOUT * 3));
+    }
+
+    #[test]
+    fn test_request_given_up_on_after_max_sends() {
+        let mut pipeline: Pipeline = Pipeline::new(8, TIMEOUT, 2, 2);
+        let now: Instant = Instant::now();
+        pipeline.insert(7, create_message(b"id"), get_server(), now);
+
+        let (resends, timed_out) = pipeline.take_due(now + TIMEOUT);
+        assert_eq!((resends.len(), timed_out.len()), (1, 0));
+        let (resends, timed_out) = pipeline.take_due(now + TIMEOUT * 3);
+        assert_eq!(resends.len(), 0);
+        assert_eq!(timed_out, vec![7]);
+        assert!(pipeline.is_empty());
+        assert_eq!(pipeline.complete(b"id"), None);
+    }
+}
@@ -1,7 +1,7 @@
 use std::io::{Result, Error, ErrorKind};
 use std::convert::TryFrom;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use crc::{Crc, CRC_32_CKSUM};
 use protobuf::Message;
 use rand;
@@ -17,7 +17,31 @@ pub enum Operation {
     Wipe = 3,
     Ping = 4,
     Shutdown = 5,
-    GetPid = 6
+    GetPid = 6,
+    Gossip = 7,
+    Join = 8,
+    Handoff = 9,
+    HardShutdown = 10,
+    Transfer = 11,
+    ReplicaPut = 12,
+    ReplicaDelete = 13,
+    ReplicaGet = 14,
+    CompareAndSwap = 15,
+    PutIfAbsent = 16,
+    DeleteIfValue = 17,
+    GetMemoryUsage = 18,
+    Snapshot = 19,
+    Scan = 20,
+    PrefixScan = 21,
+    LocalScan = 22,
+    MultiGet = 23,
+    MultiPut = 24,
+    MultiDelete = 25,
+    Increment = 26,
+    Decrement = 27,
+    Append = 28,
+    SetRange = 29,
+    GetRange = 30
 }
 
 impl TryFrom<u32> for Operation {
@@ -32,6 +56,30 @@ impl TryFrom<u32> for Operation {
             4 => Ok(Operation::Ping),
             5 => Ok(Operation::Shutdown),
             6 => Ok(Operation::GetPid),
+            7 => Ok(Operation::Gossip),
+            8 => Ok(Operation::Join),
+            9 => Ok(Operation::Handoff),
+            10 => Ok(Operation::HardShutdown),
+            11 => Ok(Operation::Transfer),
+            12 => Ok(Operation::ReplicaPut),
+            13 => Ok(Operation::ReplicaDelete),
+            14 => Ok(Operation::ReplicaGet),
+            15 => Ok(Operation::CompareAndSwap),
+            16 => Ok(Operation::PutIfAbsent),
+            17 => Ok(Operation::DeleteIfValue),
+            18 => Ok(Operation::GetMemoryUsage),
+            19 => Ok(Operation::Snapshot),
+            20 => Ok(Operation::Scan),
+            21 => Ok(Operation::PrefixScan),
+            22 => Ok(Operation::LocalScan),
+            23 => Ok(Operation::MultiGet),
+            24 => Ok(Operation::MultiPut),
+            25 => Ok(Operation::MultiDelete),
+            26 => Ok(Operation::Increment),
+            27 => Ok(Operation::Decrement),
+            28 => Ok(Operation::Append),
+            29 => Ok(Operation::SetRange),
+            30 => Ok(Operation::GetRange),
             _ => Err(Error::new(ErrorKind::InvalidData, "Invalid operation")),
         }
     }
@@ -47,7 +95,10 @@ pub enum Status {
     OutOfMemory = 6,
     UndefinedOperation = 7,
     InternalError = 8,
-    InvalidValueSize = 9
+    InvalidValueSize = 9,
+    QuorumNotReached = 10,
+    PreconditionFailed = 11,
+    KeyExists = 12
 }
 
 impl TryFrom<u32> for Status {
@@ -64,11 +115,45 @@ impl TryFrom<u32> for Status {
             6 => Ok(Status::OutOfMemory),
             7 => Ok(Status::UndefinedOperation),
             8 => Ok(Status::InternalError),
+            9 => Ok(Status::InvalidValueSize),
+            10 => Ok(Status::QuorumNotReached),
+            11 => Ok(Status::PreconditionFailed),
+            12 => Ok(Status::KeyExists),
             _ => Err(Error::new(ErrorKind::InvalidData, "Invalid status")),
         }
     }
 }
 
+/// How many copies of a key must answer before a request succeeds.
+pub enum Consistency {
+    One = 0,
+    Quorum = 1,
+    All = 2
+}
+
+impl TryFrom<u32> for Consistency {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self> {
+        match value {
+            0 => Ok(Consistency::One),
+            1 => Ok(Consistency::Quorum),
+            2 => Ok(Consistency::All),
+            _ => Err(Error::new(ErrorKind::InvalidData, "Invalid consistency level")),
+        }
+    }
+}
+
+impl Consistency {
+    pub fn required_replies(&self, num_copies: usize) -> usize {
+        match self {
+            Consistency::One => 1,
+            Consistency::Quorum => num_copies / 2 + 1,
+            Consistency::All => num_copies,
+        }
+    }
+}
+
 pub fn create_udp_message(message: impl Message, ip: IpAddr, port: u16) -> Result<UDPMessage> {
     let mut udp_message: UDPMessage = UDPMessage::new();
     udp_message.id = generate_message_id(ip, port, NUM_RAND_BYTES)?;
@@ -77,6 +162,24 @@ pub fn create_udp_message(message: impl Message, ip: IpAddr, port: u16) -> Resul
     Ok(udp_message)
 }
 
+/// Wraps `message` as the reply to the request with Id `request_id`, which
+/// the reply shares so that a client with many requests in flight can tell
+/// which one it answers.
+pub fn create_reply_message(message: impl Message, request_id: &[u8]) -> Result<UDPMessage> {
+    let mut udp_message: UDPMessage = UDPMessage::new();
+    udp_message.id = request_id.to_vec();
+    udp_message.payload = Message::write_to_bytes(&message)?;
+    udp_message.checksum = calculate_checksum(&udp_message.id, &udp_message.payload);
+    Ok(udp_message)
+}
+
+/// Marks `message` as forwarded on behalf of the client at `reply_addr`,
+/// which its checksum then covers too.
+pub fn set_reply_addr(message: &mut UDPMessage, reply_addr: SocketAddr) {
+    message.reply_addr = Some(reply_addr.to_string());
+    message.checksum = calculate_message_checksum(message);
+}
+
 pub fn parse_message(message_bytes: Vec<u8>) -> Result<UDPMessage> {
     Ok(UDPMessage::parse_from_bytes(message_bytes.as_slice())?)
 }
@@ -102,7 +205,7 @@ pub fn calculate_checksum(id: &[u8], payload: &[u8]) -> u64 {
 
 pub fn validate_checksum(message: &UDPMessage) -> Result<()> {
     let checksum: u64 = message.checksum;
-    let recalc_checksum: u64 = calculate_checksum(&message.id, &message.payload);
+    let recalc_checksum: u64 = calculate_message_checksum(message);
     if checksum == recalc_checksum {
         Ok(())
     } else {
@@ -110,6 +213,17 @@ pub fn validate_checksum(message: &UDPMessage) -> Result<()> {
     }
 }
 
+/// The checksum over the Id, the payload and the reply address if any
+fn calculate_message_checksum(message: &UDPMessage) -> u64 {
+    match message.reply_addr.as_deref() {
+        Some(reply_addr) => {
+            let content: Vec<u8> = [message.payload.as_slice(), reply_addr.as_bytes()].concat();
+            calculate_checksum(&message.id, &content)
+        },
+        None => calculate_checksum(&message.id, &message.payload),
+    }
+}
+
 fn generate_message_id(ip: IpAddr, port: u16, num_rand_bytes: usize) -> Result<Vec<u8>> {
     let binding: String = ip.to_string();
     let ip_bytes: &[u8] = binding.as_bytes();
@@ -127,7 +241,7 @@ fn generate_message_id(ip: IpAddr, port: u16, num_rand_bytes: usize) -> Result<V
     }
 
     let mut id: Vec<u8> = Vec::new();
-    id.extend_from_slice(&ip_bytes);
+    id.extend_from_slice(ip_bytes);
     id.extend_from_slice(&port_bytes);
     id.extend_from_slice(&nano_sec_bytes);
     id.extend_from_slice(&rand_bytes);
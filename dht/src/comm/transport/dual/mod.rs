@@ -0,0 +1,101 @@
+use std::io::{Result, ErrorKind};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::comm::transport::{TcpTransport, Transport, UdpTransport};
+
+/// Attempts at finding a port free for both UDP and TCP when any will do
+const MAX_BIND_ATTEMPTS: u32 = 8;
+/// How long each transport is waited on in turn while the other is idle,
+/// which bounds the delay of a message arriving on the other
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Listens for datagrams and for connections on the same port. Messages to
+/// the other end of a connection go over it, whole, and all others go over
+/// UDP, so clients pick a transport by how they reach the node.
+pub struct DualTransport {
+    udp: UdpTransport,
+    tcp: TcpTransport,
+}
+
+impl Transport for DualTransport {
+    fn bind(socket_addr: SocketAddr) -> Result<Self> {
+        let mut attempt: u32 = 1;
+        loop {
+            let udp: UdpTransport = UdpTransport::bind(socket_addr)?;
+            let udp_addr: SocketAddr = udp.local_addr()?;
+            match TcpTransport::bind(udp_addr) {
+                Ok(tcp) => return Ok(DualTransport {udp, tcp}),
+                // Another socket holds the TCP side of the port handed out
+                // for UDP, so another port is tried
+                Err(e) if e.kind() == ErrorKind::AddrInUse && socket_addr.port() == 0 && attempt < MAX_BIND_ATTEMPTS => {
+                    attempt += 1;
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn send(&self, message: &[u8], addr: SocketAddr) -> Result<usize> {
+        match self.tcp.is_connected(addr) {
+            true => self.tcp.send(message, addr),
+            false => self.udp.send(message, addr),
+        }
+    }
+
+    fn recv(&self, timeout: Duration) -> Result<(Vec<u8>, SocketAddr)> {
+        let deadline: Instant = Instant::now() + timeout;
+        loop {
+            match self.tcp.recv(Duration::ZERO) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+                result => return result,
+            }
+
+            let wait: Duration = deadline.saturating_duration_since(Instant::now()).clamp(Duration::from_millis(1), POLL_INTERVAL);
+            match self.udp.recv(wait) {
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) && Instant::now() < deadline => (),
+                result => return result,
+            }
+        }
+    }
+
+    fn max_datagram_size(&self, addr: SocketAddr) -> Option<usize> {
+        match self.tcp.is_connected(addr) {
+            true => self.tcp.max_datagram_size(addr),
+            false => self.udp.max_datagram_size(addr),
+        }
+    }
+
+    fn is_connected(&self, addr: SocketAddr) -> bool {
+        self.tcp.is_connected(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+
+    #[test]
+    fn test_replies_go_back_the_way_requests_came() {
+        let server: DualTransport = DualTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let server_addr: SocketAddr = server.udp.local_addr().unwrap();
+        assert_eq!(server.tcp.local_addr(), server_addr);
+
+        let udp_client: UdpSocket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        udp_client.send_to(b"over udp", server_addr).unwrap();
+        let (message, sender_addr) = server.recv(Duration::from_secs(1)).unwrap();
+        assert_eq!(message, b"over udp");
+        assert!(server.max_datagram_size(sender_addr).is_some());
+
+        let tcp_client: TcpTransport = TcpTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        tcp_client.send(b"over tcp", server_addr).unwrap();
+        let (message, sender_addr) = server.recv(Duration::from_secs(1)).unwrap();
+        assert_eq!(message, b"over tcp");
+        assert!(server.is_connected(sender_addr));
+        assert_eq!(server.max_datagram_size(sender_addr), None);
+
+        server.send(b"reply", sender_addr).unwrap();
+        assert_eq!(tcp_client.recv(Duration::from_secs(1)).unwrap().0, b"reply");
+    }
+}
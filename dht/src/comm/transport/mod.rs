@@ -0,0 +1,99 @@
+use std::io::{Result, Error, ErrorKind};
+use std::net::{SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::comm::MAX_BUFFER_SIZE_BYTES;
+
+pub mod dual;
+pub mod tcp;
+
+pub use dual::DualTransport;
+pub use tcp::TcpTransport;
+
+/// Moves the bytes of whole messages between this node and others.
+pub trait Transport: Sized + Send + Sync + 'static {
+    fn bind(socket_addr: SocketAddr) -> Result<Self>;
+
+    fn send(&self, message: &[u8], addr: SocketAddr) -> Result<usize>;
+
+    /// Waits up to `timeout` for the next message, failing with `WouldBlock`
+    /// if none arrives.
+    fn recv(&self, timeout: Duration) -> Result<(Vec<u8>, SocketAddr)>;
+
+    /// Largest message sent to `addr` in one piece, past which messages have
+    /// to be split into fragments, or `None` if messages of any size go whole.
+    fn max_datagram_size(&self, addr: SocketAddr) -> Option<usize>;
+
+    /// Whether `addr` is the other end of a connection, which only that
+    /// connection reaches.
+    fn is_connected(&self, _addr: SocketAddr) -> bool {
+        false
+    }
+}
+
+/// Which transports a node listens on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransportKind {
+    Udp,
+    Tcp,
+    Both,
+}
+
+impl FromStr for TransportKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "udp" => Ok(TransportKind::Udp),
+            "tcp" => Ok(TransportKind::Tcp),
+            "both" => Ok(TransportKind::Both),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("Invalid transport: {}", s))),
+        }
+    }
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl Transport for UdpTransport {
+    fn bind(socket_addr: SocketAddr) -> Result<Self> {
+        let socket: UdpSocket = UdpSocket::bind(socket_addr)?;
+        Ok(UdpTransport {socket})
+    }
+
+    fn send(&self, message: &[u8], addr: SocketAddr) -> Result<usize> {
+        self.socket.send_to(message, addr)
+    }
+
+    fn recv(&self, timeout: Duration) -> Result<(Vec<u8>, SocketAddr)> {
+        let mut buf: [u8; MAX_BUFFER_SIZE_BYTES] = [0; MAX_BUFFER_SIZE_BYTES];
+        self.socket.set_read_timeout(Some(timeout))?;
+        let (size, sender_addr) = self.socket.recv_from(&mut buf)?;
+        Ok((buf[0..size].to_vec(), sender_addr))
+    }
+
+    fn max_datagram_size(&self, _addr: SocketAddr) -> Option<usize> {
+        Some(MAX_BUFFER_SIZE_BYTES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_kind_from_str() {
+        assert_eq!("udp".parse::<TransportKind>().unwrap(), TransportKind::Udp);
+        assert_eq!("tcp".parse::<TransportKind>().unwrap(), TransportKind::Tcp);
+        assert_eq!("both".parse::<TransportKind>().unwrap(), TransportKind::Both);
+        assert!("quic".parse::<TransportKind>().is_err());
+    }
+}
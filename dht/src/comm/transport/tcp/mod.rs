@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::io::{Read, Write, Result, Error, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::comm::fragment::MAX_MESSAGE_SIZE_BYTES;
+use crate::comm::transport::Transport;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(100);
+/// How long a write may block, and how long the rest of a frame may take to
+/// arrive once it has started, before the connection is dropped
+const IO_TIMEOUT: Duration = Duration::from_secs(2);
+/// Connections open at once, each read on a thread of its own, past which
+/// new ones are refused
+const MAX_CONNECTIONS: usize = 256;
+
+/// An open connection, written to by one sender at a time
+struct Connection {
+    id: u64,
+    writer: Mutex<TcpStream>,
+}
+
+/// State shared with the threads accepting and reading connections
+#[derive(Clone)]
+struct Shared {
+    connections: Arc<Mutex<HashMap<SocketAddr, Arc<Connection>>>>,
+    sender: Sender<(Vec<u8>, SocketAddr)>,
+    num_connections: Arc<AtomicUsize>,
+    next_id: Arc<AtomicU64>,
+    is_closed: Arc<AtomicBool>,
+}
+
+/// Sends messages over persistent TCP connections, each message framed by
+/// its length as a big-endian `u32`.
+///
+/// Every transport also listens for connections. Messages arriving over a
+/// connection are reported as coming from the address of its other end, and
+/// replies to that address go back over it.
+pub struct TcpTransport {
+    local_addr: SocketAddr,
+    shared: Shared,
+    receiver: Mutex<Receiver<(Vec<u8>, SocketAddr)>>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl Transport for TcpTransport {
+    fn bind(socket_addr: SocketAddr) -> Result<Self> {
+        let listener: TcpListener = TcpListener::bind(socket_addr)?;
+        let local_addr: SocketAddr = listener.local_addr()?;
+        let (sender, receiver) = mpsc::channel();
+        let shared: Shared = Shared {
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            sender,
+            num_connections: Arc::new(AtomicUsize::new(0)),
+            next_id: Arc::new(AtomicU64::new(0)),
+            is_closed: Arc::new(AtomicBool::new(false)),
+        };
+
+        let accept_shared: Shared = shared.clone();
+        let acceptor: JoinHandle<()> = thread::spawn(move || accept_connections(listener, accept_shared));
+
+        Ok(TcpTransport {local_addr, shared, receiver: Mutex::new(receiver), acceptor: Some(acceptor)})
+    }
+
+    /// Writes `message` to the connection with `addr`, opening one if there
+    /// is none. Other connections can be written to meanwhile.
+    fn send(&self, message: &[u8], addr: SocketAddr) -> Result<usize> {
+        let frame: Vec<u8> = encode_frame(message)?;
+        let connection_opt: Option<Arc<Connection>> = self.shared.connections.lock().unwrap().get(&addr).cloned();
+        if let Some(connection) = connection_opt {
+            if connection.writer.lock().unwrap().write_all(&frame).is_ok() {
+                return Ok(message.len());
+            }
+            self.shared.close(addr, &connection);
+        }
+
+        let stream: TcpStream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        let connection: Arc<Connection> = self.shared.open(stream, addr)?;
+        let result: Result<()> = connection.writer.lock().unwrap().write_all(&frame);
+        if let Err(e) = result {
+            self.shared.close(addr, &connection);
+            return Err(e);
+        }
+        Ok(message.len())
+    }
+
+    fn recv(&self, timeout: Duration) -> Result<(Vec<u8>, SocketAddr)> {
+        match self.receiver.lock().unwrap().recv_timeout(timeout) {
+            Ok((message, sender_addr)) => Ok((message, sender_addr)),
+            Err(RecvTimeoutError::Timeout) => Err(Error::new(ErrorKind::WouldBlock, "Timed out")),
+            Err(RecvTimeoutError::Disconnected) => Err(Error::new(ErrorKind::NotConnected, "Transport closed")),
+        }
+    }
+
+    fn max_datagram_size(&self, _addr: SocketAddr) -> Option<usize> {
+        None
+    }
+
+    fn is_connected(&self, addr: SocketAddr) -> bool {
+        self.shared.connections.lock().unwrap().contains_key(&addr)
+    }
+}
+
+impl TcpTransport {
+    /// The address this transport listens on for connections.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for TcpTransport {
+    fn drop(&mut self) {
+        self.shared.is_closed.store(true, Ordering::SeqCst);
+        // Wakes the thread accepting connections so that it lets go of the
+        // listener and the address can be bound again
+        let wake_addr: SocketAddr = match self.local_addr.ip().is_unspecified() {
+            true => SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), self.local_addr.port()),
+            false => self.local_addr,
+        };
+        let _ = TcpStream::connect_timeout(&wake_addr, CONNECT_TIMEOUT);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+
+        for connection in self.shared.connections.lock().unwrap().values() {
+            let _ = connection.writer.lock().unwrap().shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Shared {
+    /// Takes `stream` to `addr` in as a connection and starts reading the
+    /// messages arriving over it. A live connection with `addr` is never
+    /// replaced.
+    fn open(&self, stream: TcpStream, addr: SocketAddr) -> Result<Arc<Connection>> {
+        let is_reserved: bool = self.num_connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |num| (num < MAX_CONNECTIONS).then_some(num + 1))
+            .is_ok();
+        if !is_reserved {
+            return Err(Error::new(ErrorKind::OutOfMemory, "Too many connections"));
+        }
+
+        let result: Result<Arc<Connection>> = self.start_reading(stream, addr);
+        if result.is_err() {
+            self.num_connections.fetch_sub(1, Ordering::SeqCst);
+        }
+        result
+    }
+
+    fn start_reading(&self, stream: TcpStream, addr: SocketAddr) -> Result<Arc<Connection>> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let reader: TcpStream = stream.try_clone()?;
+
+        let connection: Arc<Connection> = Arc::new(Connection {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            writer: Mutex::new(stream),
+        });
+        {
+            let mut connections = self.connections.lock().unwrap();
+            if connections.contains_key(&addr) {
+                let _ = reader.shutdown(Shutdown::Both);
+                return Err(Error::new(ErrorKind::AlreadyExists, "Already connected"));
+            }
+            connections.insert(addr, connection.clone());
+        }
+
+        let (shared, reading) = (self.clone(), connection.clone());
+        thread::spawn(move || {
+            read_frames(reader, addr, &shared);
+            shared.close(addr, &reading);
+        });
+        Ok(connection)
+    }
+
+    /// Shuts `connection` down, forgetting it unless another connection with
+    /// `addr` has taken its place.
+    fn close(&self, addr: SocketAddr, connection: &Arc<Connection>) {
+        let mut connections = self.connections.lock().unwrap();
+        if connections.get(&addr).is_some_and(|open| open.id == connection.id) {
+            connections.remove(&addr);
+            self.num_connections.fetch_sub(1, Ordering::SeqCst);
+        }
+        drop(connections);
+        let _ = connection.writer.lock().unwrap().shutdown(Shutdown::Both);
+    }
+}
+
+fn accept_connections(listener: TcpListener, shared: Shared) {
+    for stream in listener.incoming() {
+        if shared.is_closed.load(Ordering::SeqCst) {
+            break;
+        }
+        let (stream, peer_addr): (TcpStream, SocketAddr) = match stream.and_then(|stream| {
+            let peer_addr: SocketAddr = stream.peer_addr()?;
+            Ok((stream, peer_addr))
+        }) {
+            Ok((stream, peer_addr)) => (stream, peer_addr),
+            Err(_) => continue,
+        };
+
+        if let Err(e) = shared.open(stream, peer_addr) {
+            log::debug!("Refusing connection from {}: {}", peer_addr, e);
+        }
+    }
+}
+
+/// Passes on every message arriving over `stream` as sent by `peer_addr`
+/// until the connection closes or a frame stalls partway.
+fn read_frames(mut stream: TcpStream, peer_addr: SocketAddr, shared: &Shared) {
+    loop {
+        match read_frame(&mut stream, &shared.is_closed) {
+            Ok(Some(frame)) => {
+                if shared.sender.send((frame, peer_addr)).is_err() {
+                    break;
+                }
+            },
+            Ok(None) => break,
+            Err(e) => {
+                log::debug!("Dropping connection with {}: {}", peer_addr, e);
+                break;
+            },
+        }
+    }
+}
+
+fn encode_frame(message: &[u8]) -> Result<Vec<u8>> {
+    if message.len() > MAX_MESSAGE_SIZE_BYTES {
+        return Err(Error::new(ErrorKind::InvalidInput, "Message exceeds the maximum size"));
+    }
+    let len: u32 = message.len() as u32;
+    let mut frame: Vec<u8> = Vec::with_capacity(4 + message.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(message);
+    Ok(frame)
+}
+
+/// The next frame on `stream`, or `None` once the connection closes. The
+/// connection may stay idle between frames for as long as the transport is
+/// open, but a frame that has started must go on arriving within the read
+/// timeout of `stream`.
+fn read_frame(stream: &mut TcpStream, is_closed: &AtomicBool) -> Result<Option<Vec<u8>>> {
+    let mut header: [u8; 4] = [0; 4];
+    loop {
+        match stream.read(&mut header[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(e) if is_timeout(&e) && !is_closed.load(Ordering::SeqCst) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    stream.read_exact(&mut header[1..])?;
+
+    let len: usize = u32::from_be_bytes(header) as usize;
+    if len > MAX_MESSAGE_SIZE_BYTES {
+        return Err(Error::new(ErrorKind::InvalidData, "Frame exceeds the maximum message size"));
+    }
+    let mut frame: Vec<u8> = Vec::new();
+    Read::by_ref(stream).take(len as u64).read_to_end(&mut frame)?;
+    if frame.len() != len {
+        return Ok(None);
+    }
+    Ok(Some(frame))
+}
+
+fn is_timeout(e: &Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind_local() -> TcpTransport {
+        TcpTransport::bind("127.0.0.1:0".parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_send_and_reply_over_one_connection() {
+        let client: TcpTransport = bind_local();
+        let server: TcpTransport = bind_local();
+
+        client.send(b"request", server.local_addr).unwrap();
+        let (message, sender_addr) = server.recv(Duration::from_secs(1)).unwrap();
+        assert_eq!(message, b"request");
+        assert!(server.is_connected(sender_addr));
+
+        server.send(b"reply", sender_addr).unwrap();
+        let (message, sender_addr) = client.recv(Duration::from_secs(1)).unwrap();
+        assert_eq!(message, b"reply");
+        assert_eq!(sender_addr, server.local_addr);
+        assert_eq!(client.shared.connections.lock().unwrap().len(), 1);
+        assert_eq!(server.shared.connections.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_large_messages_and_timeout() {
+        let client: TcpTransport = bind_local();
+        let server: TcpTransport = bind_local();
+
+        let message: Vec<u8> = (0..1024 * 256).map(|i| (i % 251) as u8).collect();
+        client.send(&message, server.local_addr).unwrap();
+        client.send(b"next", server.local_addr).unwrap();
+        assert_eq!(server.recv(Duration::from_secs(5)).unwrap().0, message);
+        assert_eq!(server.recv(Duration::from_secs(1)).unwrap().0, b"next");
+
+        let e: Error = server.recv(Duration::from_millis(10)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn test_oversized_frame_drops_connection() {
+        let server: TcpTransport = bind_local();
+        assert!(encode_frame(&vec![0; MAX_MESSAGE_SIZE_BYTES + 1]).is_err());
+
+        let mut stream: TcpStream = TcpStream::connect(server.local_addr).unwrap();
+        stream.write_all(&u32::MAX.to_be_bytes()).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(stream.read(&mut [0; 1]).unwrap_or(0), 0);
+        assert!(server.recv(Duration::from_millis(10)).is_err());
+    }
+
+    #[test]
+    fn test_stalled_frame_does_not_hold_up_others() {
+        let client: TcpTransport = bind_local();
+        let server: TcpTransport = bind_local();
+
+        // Half a header that never gets the rest
+        let mut stalled: TcpStream = TcpStream::connect(server.local_addr).unwrap();
+        stalled.write_all(&[0, 0]).unwrap();
+
+        client.send(b"message", server.local_addr).unwrap();
+        assert_eq!(server.recv(Duration::from_secs(1)).unwrap().0, b"message");
+    }
+
+    #[test]
+    fn test_address_free_once_dropped() {
+        let server: TcpTransport = bind_local();
+        let server_addr: SocketAddr = server.local_addr;
+        drop(server);
+        assert!(TcpTransport::bind(server_addr).is_ok());
+    }
+}
@@ -1,10 +1,17 @@
 use clap::Parser;
-use log;
 use log::LevelFilter;
+use std::io::Result;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use crate::comm::transport::{DualTransport, TcpTransport, Transport, TransportKind, UdpTransport};
 use crate::logging::server::init_logger;
-use crate::server::data::Node;
+use crate::server::data::{Node, NodeConfig};
+use crate::server::eviction::EvictionPolicy;
+use crate::server::storage::{BTreeMapEngine, HashMapEngine, StorageEngine, StorageEngineKind};
+use crate::server::storage::disk::DiskEngine;
+use crate::util::read_socket_addresses;
 
 pub mod comm;
 pub mod logging;
@@ -30,6 +37,51 @@ struct Args {
     /// Max memory in megabytes
     #[arg(short, long, default_value = "32")]
     max_memory: u32,
+
+    /// Largest value accepted in kilobytes
+    #[arg(long, default_value = "10")]
+    max_value_size: u32,
+
+    /// File listing the addresses of every server in the cluster
+    #[arg(short = 'f', long)]
+    servers_file: Option<String>,
+
+    /// Number of nodes holding a copy of each key
+    #[arg(short, long, default_value = "1")]
+    replication_factor: usize,
+
+    /// What to evict once memory is full: noeviction, lru, lfu, random or ttl
+    #[arg(short, long, default_value = "noeviction")]
+    eviction_policy: EvictionPolicy,
+
+    /// Directory for the write-ahead log; keys are kept in memory only if unset
+    #[arg(short, long)]
+    data_dir: Option<PathBuf>,
+
+    /// Where keys are stored: hashmap, btreemap or disk, which needs a data directory
+    #[arg(long, default_value = "hashmap")]
+    storage_engine: StorageEngineKind,
+
+    /// Address of a running server to join the cluster through
+    #[arg(short, long)]
+    bootstrap: Option<SocketAddr>,
+
+    /// What messages travel over: udp, tcp for large values with no size cap, or both on the same port
+    #[arg(long, default_value = "both")]
+    transport: TransportKind,
+
+    /// Worker threads handling requests, each sharing a data store split into as many shards
+    #[arg(short, long, default_value = "1")]
+    threads: usize,
+
+    /// Seconds a deleted key's tombstone is kept, which should outlast any replica catching up on the delete
+    #[arg(long, default_value = "600")]
+    tombstone_grace_period: u64,
+
+    /// Run on the tokio runtime, handling each request as it arrives
+    #[cfg(feature = "async")]
+    #[arg(long = "async")]
+    run_async: bool,
 }
 
 fn main() {
@@ -58,10 +110,86 @@ fn main() {
         }
     };
 
+    let peers: Vec<SocketAddr> = match &args.servers_file {
+        Some(servers_file) => match read_socket_addresses(Path::new(servers_file)) {
+            Ok(addrs) => addrs,
+            Err(e) => {
+                eprintln!("Failed to read servers file {}: {}", servers_file, e);
+                return;
+            }
+        },
+        None => Vec::new(),
+    };
+
     log::set_max_level(log_level);
     init_logger(log_level, args.server_id);
 
-    let mut server: Node = match Node::new(server_addr, args.server_id, args.max_memory) {
+    let config: NodeConfig = NodeConfig {
+        id: args.server_id,
+        max_mem_mb: args.max_memory,
+        max_value_kb: args.max_value_size,
+        peers,
+        replication_factor: args.replication_factor,
+        eviction_policy: args.eviction_policy,
+        data_dir: args.data_dir.clone(),
+        num_workers: args.threads.max(1),
+        tombstone_grace_period: Duration::from_secs(args.tombstone_grace_period),
+    };
+
+    match args.transport {
+        TransportKind::Udp => start_server::<UdpTransport>(server_addr, config, &args),
+        TransportKind::Tcp => start_server::<TcpTransport>(server_addr, config, &args),
+        // Clients pick UDP, or TCP for bulk and large values, by how they
+        // reach the server
+        TransportKind::Both => start_server::<DualTransport>(server_addr, config, &args),
+    }
+}
+
+fn start_server<T: Transport + 'static>(server_addr: SocketAddr, config: NodeConfig, args: &Args) {
+    let num_shards: usize = config.num_workers;
+    match args.storage_engine {
+        StorageEngineKind::HashMap => {
+            let data_stores: Vec<HashMapEngine> = (0..num_shards).map(|_| HashMapEngine::default()).collect();
+            run_server::<HashMapEngine, T>(server_addr, config, data_stores, args)
+        },
+        StorageEngineKind::BTreeMap => {
+            let data_stores: Vec<BTreeMapEngine> = (0..num_shards).map(|_| BTreeMapEngine::default()).collect();
+            run_server::<BTreeMapEngine, T>(server_addr, config, data_stores, args)
+        },
+        StorageEngineKind::Disk => match &args.data_dir {
+            Some(data_dir) => match open_disk_shards(data_dir, num_shards) {
+                Ok(data_stores) => run_server::<DiskEngine, T>(server_addr, config, data_stores, args),
+                Err(e) => eprintln!("Failed to open data directory {}: {}", data_dir.display(), e),
+            },
+            None => eprintln!("The disk storage engine needs a data directory"),
+        },
+    }
+}
+
+/// Opens a disk engine per shard, the first in `data_dir` and the others in
+/// a directory of their own within it. Shards left by an earlier run with
+/// more threads are opened too, so that none of their keys are lost.
+fn open_disk_shards(data_dir: &Path, num_shards: usize) -> Result<Vec<DiskEngine>> {
+    let mut num_shards: usize = num_shards;
+    while data_dir.join(format!("shard-{}", num_shards)).is_dir() {
+        num_shards += 1;
+    }
+
+    let mut data_stores: Vec<DiskEngine> = vec![DiskEngine::open(data_dir)?];
+    for index in 1..num_shards {
+        data_stores.push(DiskEngine::open(&data_dir.join(format!("shard-{}", index)))?);
+    }
+    Ok(data_stores)
+}
+
+fn run_server<S: StorageEngine + 'static, T: Transport + 'static>(
+    server_addr: SocketAddr,
+    config: NodeConfig,
+    data_stores: Vec<S>,
+    args: &Args
+) {
+    let server_id: u32 = config.id;
+    let server: Node<S, T> = match Node::new(server_addr, config, data_stores) {
         Ok(node) => node,
         Err(e) => {
             eprintln!("Failed to create server: {}", e);
@@ -69,7 +197,25 @@ fn main() {
         }
     };
 
-    log::info!("Server N{} bound to address {}", args.server_id, server_addr);
+    log::info!("Server N{} bound to address {}", server_id, server_addr);
+
+    if let Some(bootstrap) = args.bootstrap {
+        if let Err(e) = server.join(bootstrap) {
+            eprintln!("Failed to join the cluster through {}: {}", bootstrap, e);
+            return;
+        }
+    }
+
+    #[cfg(feature = "async")]
+    if args.run_async {
+        match tokio::runtime::Runtime::new() {
+            Ok(runtime) => {
+                let _ = runtime.block_on(std::sync::Arc::new(server).run_async());
+            },
+            Err(e) => eprintln!("Failed to start the async runtime: {}", e),
+        }
+        return;
+    }
 
     let _ = server.run();
 }
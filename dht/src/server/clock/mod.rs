@@ -0,0 +1,178 @@
+use std::cmp::Ordering;
+use std::io::{Error, ErrorKind, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::comm::protogen::api::Version;
+
+/// How far ahead of the local wall clock, in milliseconds, an observed
+/// version may be. Anything further would drag every later version with it.
+pub const MAX_CLOCK_SKEW_MS: u64 = 60 * 1000;
+
+/// Hybrid logical clock used to version stored values.
+///
+/// Versions follow wall clock milliseconds but never go backwards, and the
+/// counter orders events within the same millisecond. Concurrent writes are
+/// resolved by keeping the highest version, with the node id breaking ties.
+pub struct HybridClock {
+    node_id: u32,
+    timestamp: u64,
+    counter: u32,
+}
+
+impl HybridClock {
+    pub fn new(node_id: u32) -> Self {
+        HybridClock { node_id, timestamp: 0, counter: 0 }
+    }
+
+    /// Returns a version newer than any this clock has produced or observed.
+    pub fn now(&mut self) -> Version {
+        self.tick(physical_time())
+    }
+
+    /// Advances the clock past a version received from another node or client,
+    /// failing with `InvalidData` if the version is too far ahead to follow.
+    pub fn observe(&mut self, version: &Version) -> Result<()> {
+        self.update(version, physical_time())
+    }
+
+    fn tick(&mut self, physical: u64) -> Version {
+        if physical > self.timestamp {
+            self.timestamp = physical;
+            self.counter = 0;
+        } else {
+            self.advance_from(self.timestamp, self.counter);
+        }
+        self.to_version()
+    }
+
+    fn update(&mut self, version: &Version, physical: u64) -> Result<()> {
+        if version.counter == u32::MAX {
+            return Err(Error::new(ErrorKind::InvalidData, "Version counter is at its maximum"));
+        }
+        if version.timestamp > physical.saturating_add(MAX_CLOCK_SKEW_MS) {
+            return Err(Error::new(ErrorKind::InvalidData, "Version is too far ahead of the clock"));
+        }
+
+        let timestamp: u64 = physical.max(self.timestamp).max(version.timestamp);
+        if timestamp == self.timestamp && timestamp == version.timestamp {
+            self.advance_from(timestamp, self.counter.max(version.counter));
+        } else if timestamp == self.timestamp {
+            self.advance_from(timestamp, self.counter);
+        } else if timestamp == version.timestamp {
+            self.advance_from(timestamp, version.counter);
+        } else {
+            self.timestamp = timestamp;
+            self.counter = 0;
+        }
+        Ok(())
+    }
+
+    /// Moves the clock to the event after `counter` within `timestamp`, or to
+    /// the next millisecond once the counter runs out.
+    fn advance_from(&mut self, timestamp: u64, counter: u32) {
+        match counter.checked_add(1) {
+            Some(counter) => {
+                self.timestamp = timestamp;
+                self.counter = counter;
+            },
+            None => {
+                self.timestamp = timestamp.saturating_add(1);
+                self.counter = 0;
+            },
+        }
+    }
+
+    fn to_version(&self) -> Version {
+        let mut version: Version = Version::new();
+        version.timestamp = self.timestamp;
+        version.counter = self.counter;
+        version.node_id = self.node_id;
+        version
+    }
+}
+
+pub fn compare_versions(a: &Version, b: &Version) -> Ordering {
+    (a.timestamp, a.counter, a.node_id).cmp(&(b.timestamp, b.counter, b.node_id))
+}
+
+/// Milliseconds since the Unix epoch
+pub fn physical_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_version(timestamp: u64, counter: u32, node_id: u32) -> Version {
+        let mut version: Version = Version::new();
+        version.timestamp = timestamp;
+        version.counter = counter;
+        version.node_id = node_id;
+        version
+    }
+
+    #[test]
+    fn test_tick_follows_physical_time() {
+        let mut clock: HybridClock = HybridClock::new(1);
+        assert_eq!(clock.tick(100), create_version(100, 0, 1));
+        assert_eq!(clock.tick(200), create_version(200, 0, 1));
+    }
+
+    #[test]
+    fn test_tick_never_goes_backwards() {
+        let mut clock: HybridClock = HybridClock::new(1);
+        let first: Version = clock.tick(100);
+        let second: Version = clock.tick(100);
+        let third: Version = clock.tick(50);
+        assert_eq!(compare_versions(&first, &second), Ordering::Less);
+        assert_eq!(compare_versions(&second, &third), Ordering::Less);
+        assert_eq!(third, create_version(100, 2, 1));
+    }
+
+    #[test]
+    fn test_observe_moves_past_remote_version() {
+        let mut clock: HybridClock = HybridClock::new(1);
+        clock.tick(100);
+
+        let remote: Version = create_version(500, 3, 2);
+        clock.update(&remote, 100).unwrap();
+        let next: Version = clock.tick(100);
+        assert_eq!(compare_versions(&remote, &next), Ordering::Less);
+        assert_eq!(next.node_id, 1);
+    }
+
+    #[test]
+    fn test_counter_overflow_moves_to_next_millisecond() {
+        let mut clock: HybridClock = HybridClock::new(1);
+        clock.update(&create_version(100, u32::MAX - 1, 2), 100).unwrap();
+        assert_eq!(clock.to_version(), create_version(100, u32::MAX, 1));
+        assert_eq!(clock.tick(50), create_version(101, 0, 1));
+    }
+
+    #[test]
+    fn test_observe_rejects_invalid_versions() {
+        let mut clock: HybridClock = HybridClock::new(1);
+        clock.tick(100);
+
+        let e: Error = clock.update(&create_version(100, u32::MAX, 2), 100).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        let e: Error = clock.update(&create_version(100 + MAX_CLOCK_SKEW_MS + 1, 0, 2), 100).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(clock.to_version(), create_version(100, 0, 1));
+
+        clock.update(&create_version(100 + MAX_CLOCK_SKEW_MS, 0, 2), 100).unwrap();
+        assert_eq!(clock.to_version(), create_version(100 + MAX_CLOCK_SKEW_MS, 1, 1));
+    }
+
+    #[test]
+    fn test_node_id_breaks_ties() {
+        let a: Version = create_version(100, 0, 1);
+        let b: Version = create_version(100, 0, 2);
+        assert_eq!(compare_versions(&a, &b), Ordering::Less);
+        assert_eq!(compare_versions(&a, &a.clone()), Ordering::Equal);
+    }
+}
@@ -1,128 +1,804 @@
 #![allow(unreachable_code)]
 
+use std::cmp::Ordering;
 use std::io::{Result, Error, ErrorKind};
-use std::net::SocketAddr;
-use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
 use std::process;
-use log;
-use mini_moka::unsync::Cache;
-use protobuf::Message;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+use protobuf::{Message, MessageField};
 
 use crate::comm::ProtoInterface;
-use crate::comm::proto::{Operation, Status, extract_request};
-use crate::comm::protogen::api::{UDPMessage, Request, Reply};
+use crate::comm::transport::Transport;
+use crate::comm::proto::{self, Consistency, Operation, Status, extract_request, extract_reply};
+use crate::comm::protogen::api::{UDPMessage, Request, Reply, KeyValue, LogRecord, Version};
+use crate::server::clock::{HybridClock, compare_versions};
+use crate::server::eviction::EvictionPolicy;
+use crate::server::membership::{Membership, MemberState};
+use crate::server::pool::ThreadPool;
+use crate::server::ring::HashRing;
+use crate::server::scan::{ScanPage, get_entry_size, get_max_page_size, get_prefix_end, merge_pages};
+use crate::server::shard::{ReplyCache, Shard, ShardGuard, ShardedStore};
+use crate::server::storage::{StorageEngine, StoredValue};
+use crate::server::wal::WriteAheadLog;
+use crate::util::get_resident_memory;
 
 const MAX_CACHE_CAPACITY_PERCENT: f64 = 0.1;
-const MAX_VALUE_PAYLOAD_SIZE_BYTES: usize = 1024 * 10;
+/// Room left in a message for everything but the value it carries
+const VALUE_HEADROOM_BYTES: usize = 1024 * 64;
+const NUM_VIRTUAL_NODES: u32 = 64;
+const GOSSIP_INTERVAL: Duration = Duration::from_secs(1);
+const GOSSIP_FANOUT: usize = 2;
+/// Exchanges with other nodes made at once for replication and scans, and
+/// how many more may wait for a thread before writes wait too
+const PEER_POOL_THREADS: usize = 32;
+const PEER_POOL_MAX_QUEUED: usize = 1024;
+const HANDOFF_BATCH_SIZE_BYTES: usize = 1024 * 8;
+/// Times keys rejected by the node taking them over are sent before giving
+/// up on leaving
+const MAX_HANDOFF_ATTEMPTS: u32 = 3;
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a node reading a key on behalf of a client waits on its owner
+/// before reading it from the replicas instead
+const OWNER_READ_TIMEOUT: Duration = Duration::from_millis(50);
+/// How long a scan waits on each peer before relying on the other replicas
+/// of its keys
+const SCAN_PEER_TIMEOUT: Duration = Duration::from_millis(50);
+/// Requests waiting on a free worker, past which more are dropped for their
+/// clients to retry
+const MAX_QUEUED_REQUESTS: usize = 1024;
+/// How often the async run loop checks whether to keep running, and how
+/// often it runs the periodic tasks
+#[cfg(feature = "async")]
+const ASYNC_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Requests handled at once on the async runtime, past which the next
+/// message waits to be taken in
+#[cfg(feature = "async")]
+const MAX_ASYNC_REQUESTS: usize = 256;
 
-pub struct Node {
-    proto_interface: ProtoInterface,
-    data_store: HashMap<Vec<u8>, Vec<u8>>,
-    request_cache: Cache<Vec<u8>, Vec<u8>>,
+/// A message received along with the address it came from
+type Inbound = (UDPMessage, SocketAddr);
+/// Interfaces kept for exchanges with other nodes, shared with the threads
+/// copying writes to the replicas
+type PeerInterfaces<T> = Arc<Mutex<Vec<ProtoInterface<T>>>>;
+/// A page of scanned keys along with the peer that returned it
+type PeerPage = (SocketAddr, ScanPage);
+/// Settings a node is started with
+pub struct NodeConfig {
+    pub id: u32,
+    pub max_mem_mb: u32,
+    /// Largest value accepted, which may be far larger than a datagram
+    pub max_value_kb: u32,
+    pub peers: Vec<SocketAddr>,
+    pub replication_factor: usize,
+    pub eviction_policy: EvictionPolicy,
+    /// Directory for the write-ahead log, which storage engines keeping the
+    /// keys on disk do without
+    pub data_dir: Option<PathBuf>,
+    /// Number of workers handling requests; a single one handles them on the
+    /// thread receiving them
+    pub num_workers: usize,
+    /// How long a deleted key's tombstone is kept, by which time any copy of
+    /// the key that missed the delete is expected to have caught up
+    pub tombstone_grace_period: Duration,
+}
+
+/// When each periodic task last ran
+struct TaskTimes {
+    last_gossip: Instant,
+    last_expiry_sweep: Instant,
+    last_snapshot: Instant,
+}
+
+impl TaskTimes {
+    fn new() -> Self {
+        let now: Instant = Instant::now();
+        TaskTimes { last_gossip: now, last_expiry_sweep: now, last_snapshot: now }
+    }
+}
+
+/// A node of the cluster. Its state sits behind locks so that a pool of
+/// workers can handle requests side by side; the data store and the reply
+/// cache are sharded so that workers seldom wait on each other.
+///
+/// Locks are taken in a fixed order to rule out deadlocks: shards in order
+/// of their index, then the write-ahead log. The clock, the membership, the
+/// ring and the reply cache are only ever held on their own.
+pub struct Node<S: StorageEngine, T: Transport> {
+    proto_interface: ProtoInterface<T>,
+    peer_interfaces: PeerInterfaces<T>,
+    /// Threads copying writes to the replicas and scanning peers
+    peer_pool: ThreadPool,
+    addr: SocketAddr,
+    ring: RwLock<HashRing>,
+    replication_factor: usize,
+    membership: Mutex<Membership>,
+    clock: Mutex<HybridClock>,
+    store: ShardedStore<S>,
+    wal: Mutex<Option<WriteAheadLog>>,
+    request_cache: ReplyCache,
     id: u32,
     max_mem: u64,
+    max_value_size: usize,
+    /// Bytes of entries a scan page or batch reply holds at most
+    max_page_size: usize,
+    num_workers: usize,
+    tombstone_grace_period: Duration,
     process_id: u32,
-    data_store_mem_usage: u64,
-    should_keep_running: bool,
+    should_keep_running: AtomicBool,
 }
 
-impl Node {
-    pub fn new(socket_addr: SocketAddr, id: u32, max_mem_mb: u32) -> Result<Self> {
-        let proto_interface: ProtoInterface = ProtoInterface::new(socket_addr)?;
-        let data_store: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+impl<S: StorageEngine, T: Transport> Node<S, T> {
+    /// Creates a node keeping its keys in `data_stores`, one per shard.
+    pub fn new(socket_addr: SocketAddr, config: NodeConfig, data_stores: Vec<S>) -> Result<Self> {
+        let NodeConfig {
+            id,
+            max_mem_mb,
+            max_value_kb,
+            peers,
+            replication_factor,
+            eviction_policy,
+            data_dir,
+            num_workers,
+            tombstone_grace_period,
+        } = config;
+        if data_stores.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "A node needs at least one data store"));
+        }
+        let max_value_size: usize = (max_value_kb as usize) * 1024;
+        let proto_interface: ProtoInterface<T> = ProtoInterface::bind(socket_addr)?;
+        let max_message_size: usize = proto_interface.max_message_size();
+        if max_value_size + VALUE_HEADROOM_BYTES > max_message_size {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Values are limited to {} KB", (max_message_size - VALUE_HEADROOM_BYTES) / 1024)
+            ));
+        }
+        if !peers.is_empty() && !peers.contains(&socket_addr) {
+            log::warn!("Server address {} is not in the list of peers, adding it to the ring", socket_addr);
+        }
+        let membership: Membership = Membership::new(socket_addr, &peers);
+        let ring: HashRing = Self::create_ring(&membership);
         let max_mem_bytes: u64 = (max_mem_mb as u64) * 1024 * 1024;
         let process_id: u32 = process::id();
-        let request_cache: Cache<Vec<u8>, Vec<u8>> = Cache::builder()
-            .max_capacity((MAX_CACHE_CAPACITY_PERCENT * max_mem_bytes as f64) as u64)
-            .time_to_idle(std::time::Duration::from_secs(1))
-            .weigher(|k: &Vec<u8>, v: &Vec<u8>| (k.len() + v.len()) as u32)
-            .build();
+        let store: ShardedStore<S> = ShardedStore::new(data_stores, eviction_policy);
+        let request_cache: ReplyCache = ReplyCache::new((MAX_CACHE_CAPACITY_PERCENT * max_mem_bytes as f64) as u64);
 
-        Ok(Node {
+        let node: Node<S, T> = Node {
             proto_interface,
-            data_store,
+            peer_interfaces: Arc::new(Mutex::new(Vec::new())),
+            peer_pool: ThreadPool::new(PEER_POOL_THREADS, PEER_POOL_MAX_QUEUED),
+            addr: socket_addr,
+            ring: RwLock::new(ring),
+            replication_factor: replication_factor.max(1),
+            membership: Mutex::new(membership),
+            clock: Mutex::new(HybridClock::new(id)),
+            store,
+            wal: Mutex::new(None),
             request_cache,
             id,
             max_mem: max_mem_bytes,
-            process_id: process_id,
-            data_store_mem_usage: 0,
-            should_keep_running: true,
-        } )
+            max_value_size,
+            max_page_size: get_max_page_size(max_value_size),
+            num_workers: num_workers.max(1),
+            tombstone_grace_period,
+            process_id,
+            should_keep_running: AtomicBool::new(true),
+        };
+
+        node.load_data_store()?;
+        if let Some(data_dir) = data_dir.filter(|_| !node.is_persistent()) {
+            let (wal, records) = WriteAheadLog::open(&data_dir)?;
+            node.replay_log(records)?;
+            *node.wal.lock().unwrap() = Some(wal);
+        }
+        Ok(node)
     }
 
-    pub fn run(&mut self) -> Result<()> {
-        log::info!("Server N{} starting up...", self.id);
+    fn is_persistent(&self) -> bool {
+        self.store.lock_index(0).data_store.is_persistent()
+    }
 
-        while self.should_keep_running {
-            let (msg, sender_addr) = match self.proto_interface.listen() {
-                Ok((msg, addr)) => {
-                    log::trace!("Received message from {}", addr);
-                    (msg, addr)
-                },
-                Err(e) => match e.kind() {
-                    ErrorKind::WouldBlock => {
-                        log::trace!("Timeout on UDP port");
-                        continue;
-                    },
-                    _ => {
-                        log::error!("Internal server error: {}", e);
-                        continue;
+    /// Picks up the keys persistent storage engines already hold, so that
+    /// they count towards memory and new versions are ordered after them.
+    /// Keys found in another shard than their own, as after a change in the
+    /// number of shards, are moved to it.
+    fn load_data_store(&self) -> Result<()> {
+        let mut shards: Vec<ShardGuard<S>> = self.store.lock_all();
+        // Keys stored while the node ran with another number of shards
+        for index in 0..shards.len() {
+            let keys: Vec<Vec<u8>> = shards[index].data_store.keys().cloned().collect();
+            for key in keys {
+                let owner: usize = self.store.get_shard_index(&key);
+                if owner != index {
+                    if let Some(stored) = shards[index].data_store.get(&key)? {
+                        shards[owner].data_store.put(key.clone(), stored)?;
                     }
+                    shards[index].data_store.delete(&key)?;
                 }
-            };
+            }
+        }
 
-            let reply: Reply = match self.get_reply(msg) {
-                Ok(reply) => reply,
-                Err(e) => {
-                    log::debug!("Failed to get reply: {}", e);
-                    let mut reply: Reply = Reply::new();
-                    reply.status = Status::InternalError as u32;
-                    reply
+        // Values are read one at a time, as they may not all fit in memory
+        let mut num_keys: usize = 0;
+        for shard in shards.iter_mut() {
+            let keys: Vec<Vec<u8>> = shard.data_store.keys().cloned().collect();
+            for key in keys {
+                let stored: StoredValue = match shard.data_store.get(&key)? {
+                    Some(stored) => stored,
+                    None => continue,
+                };
+                if let Err(e) = self.clock.lock().unwrap().observe(&stored.version) {
+                    log::warn!("Not following the version of a loaded key: {}", e);
                 }
-            };
+                shard.evictor.on_insert(&key, stored.expires_at);
+                shard.evictor_mem_usage += shard.evictor.get_key_mem_usage(key.len());
+                num_keys += 1;
+            }
+        }
+        for shard in shards.iter() {
+            self.store.update_mem_usage(0, shard.mem_usage());
+        }
+
+        if num_keys > 0 {
+            log::info!("Loaded {} keys, {} B in use", num_keys, self.get_data_store_mem_usage());
+        }
+        Ok(())
+    }
 
-            match self.proto_interface.send(reply, sender_addr) {
-                Ok(_) => (),
-                Err(e) => log::debug!("Failed to send reply: {}", e),
+    /// Rebuilds the data store from the records of a previous run. The log
+    /// is not attached yet, so nothing is written back to it.
+    fn replay_log(&self, records: Vec<LogRecord>) -> Result<()> {
+        for record in records {
+            match Operation::try_from(record.operation) {
+                Ok(Operation::Put) => {
+                    let key: Vec<u8> = record.key.clone();
+                    let version: &Version = record.version.as_ref().unwrap_or(Version::default_instance());
+                    if let Err(e) = self.clock.lock().unwrap().observe(version) {
+                        log::warn!("Not following the version of a replayed key: {}", e);
+                    }
+                    let mut shard: ShardGuard<S> = self.store.lock(&key);
+                    match StoredValue::from_log_record(record) {
+                        Some(stored) => {
+                            if !self.insert_key_value(&mut shard, key, stored)? {
+                                log::warn!("Dropped replayed key, hit memory limit");
+                            }
+                        },
+                        // Expired while the node was down
+                        None => {
+                            self.remove_key_value(&mut shard, &key)?;
+                        },
+                    }
+                },
+                Ok(Operation::Delete) => match record.version.into_option() {
+                    Some(version) => {
+                        if let Err(e) = self.clock.lock().unwrap().observe(&version) {
+                            log::warn!("Not following the version of a replayed delete: {}", e);
+                        }
+                        match self.delete_at_version(&mut self.store.lock(&record.key), &record.key, version) {
+                            Err(e) if e.kind() == ErrorKind::OutOfMemory => log::warn!("Dropped replayed delete, hit memory limit"),
+                            Err(e) => return Err(e),
+                            Ok(_) => (),
+                        }
+                    },
+                    None => {
+                        self.remove_key_value(&mut self.store.lock(&record.key), &record.key)?;
+                    },
+                },
+                Ok(Operation::Wipe) => {
+                    for mut shard in self.store.lock_all() {
+                        self.wipe(&mut shard)?;
+                    }
+                },
+                _ => log::warn!("Skipping log record with unexpected operation {}", record.operation),
             }
+        }
+
+        let num_keys: usize = (0..self.store.num_shards()).map(|index| self.store.lock_index(index).data_store.len()).sum();
+        log::info!("Recovered {} keys, {} B in use", num_keys, self.store.mem_usage());
+        Ok(())
+    }
+
+    /// Records a change to the data store before it is made, if this node
+    /// keeps a write-ahead log. Callers hold the shards the change touches.
+    fn log_change(&self, operation: Operation, key: &[u8], stored: Option<&StoredValue>) -> Result<()> {
+        let record: LogRecord = match stored {
+            Some(stored) => stored.to_log_record(key.to_vec()),
+            None => {
+                let mut record: LogRecord = LogRecord::new();
+                record.operation = operation as u32;
+                record.key = key.to_vec();
+                record
+            },
+        };
+        self.append_to_log(&record)
+    }
+
+    fn append_to_log(&self, record: &LogRecord) -> Result<()> {
+        let mut wal_opt = self.wal.lock().unwrap();
+        let wal: &mut WriteAheadLog = match wal_opt.as_mut() {
+            Some(wal) => wal,
+            None => return Ok(()),
+        };
+        wal.append(record).inspect_err(|e| log::error!("Failed to write to the write-ahead log: {}", e))
+    }
+
+    /// Writes every live key to a new snapshot and empties the write-ahead
+    /// log, returning the number of records written. Tombstones go first, as
+    /// any value kept alongside one is newer. Every shard stays locked
+    /// meanwhile so that no write lands in the log being emptied.
+    fn snapshot(&self) -> Result<usize> {
+        if self.is_persistent() {
+            log::debug!("Storage engine keeps keys on disk, no snapshot needed");
+            return Ok(0);
+        }
+        let shards: Vec<ShardGuard<S>> = self.store.lock_all();
+        let mut wal_opt = self.wal.lock().unwrap();
+        let wal: &mut WriteAheadLog = match wal_opt.as_mut() {
+            Some(wal) => wal,
+            None => return Err(Error::new(ErrorKind::Unsupported, "No data directory to write a snapshot to")),
         };
 
-        if self.should_keep_running {
+        let now: Instant = Instant::now();
+        let tombstone_records = shards
+            .iter()
+            .flat_map(|shard| shard.tombstones())
+            .map(|(key, tombstone)| create_delete_record(key.clone(), tombstone.version.clone()));
+        let records = shards
+            .iter()
+            .flat_map(|shard| shard.data_store.iter())
+            .filter(|(_, stored)| !stored.is_expired(now))
+            .map(|(key, stored)| stored.to_log_record(key));
+        wal.write_snapshot(tombstone_records.chain(records)).inspect_err(|e| log::error!("Failed to write snapshot: {}", e))
+    }
+
+    fn create_ring(membership: &Membership) -> HashRing {
+        let mut ring: HashRing = HashRing::new(NUM_VIRTUAL_NODES);
+        for member in membership.live_members() {
+            ring.add_node(member);
+        }
+
+        log::info!("Hash ring created with {} nodes", ring.len());
+        ring
+    }
+
+    /// Enters a running cluster through `bootstrap` and pulls the keys this
+    /// node now owns from the peers that held them.
+    pub fn join(&self, bootstrap: SocketAddr) -> Result<()> {
+        log::info!("Server N{} joining the cluster through {}", self.id, bootstrap);
+
+        let mut request: Request = Request::new();
+        request.operation = Operation::Join as u32;
+        request.node_addr = Some(self.addr.to_string());
+        request.members = self.membership.lock().unwrap().to_proto();
+
+        let (reply_msg, _) = self.send_and_recv_peer(request, bootstrap)?;
+        let reply: Reply = extract_reply(&reply_msg)?;
+        if reply.status != Status::Success as u32 {
+            return Err(Error::other(format!("Join rejected by {} with status {}", bootstrap, reply.status)));
+        }
+
+        let changes: Vec<(SocketAddr, MemberState)> = self.membership.lock().unwrap().merge(&reply.members, Instant::now());
+        self.update_ring(changes);
+        log::info!("Joined the cluster, hash ring has {} nodes", self.ring.read().unwrap().len());
+
+        // With virtual nodes the keys this node now owns are spread across
+        // every other member, so each one is asked for its share
+        let peers: Vec<SocketAddr> = self.membership.lock().unwrap().live_members()
+            .into_iter()
+            .filter(|peer| *peer != self.addr)
+            .collect();
+        for peer in peers {
+            match self.pull_keys(peer) {
+                Ok(num_keys) => log::info!("Received {} keys from peer {}", num_keys, peer),
+                Err(e) => log::error!("Failed to pull keys from peer {}: {}", peer, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pulls the keys this node now owns from `peer` a page at a time. Each
+    /// request acknowledges the keys stored from the page before, which only
+    /// then leave `peer`. Should memory run out, the keys stored so far are
+    /// acknowledged and `peer` keeps the rest.
+    fn pull_keys(&self, peer: SocketAddr) -> Result<usize> {
+        log::trace!("Entering pull_keys");
+
+        let mut num_keys: usize = 0;
+        let mut cursor: Option<Vec<u8>> = None;
+        let mut acked: Vec<KeyValue> = Vec::new();
+        let mut is_out_of_memory: bool = false;
+        loop {
+            let mut request: Request = Request::new();
+            request.operation = Operation::Handoff as u32;
+            request.key = cursor.clone();
+            request.entries = std::mem::take(&mut acked);
+            request.node_addr = Some(self.addr.to_string());
+            request.members = self.membership.lock().unwrap().to_proto();
+            if is_out_of_memory {
+                request.limit = Some(0);
+            }
+
+            let (reply_msg, _) = self.send_and_recv_peer(request, peer)?;
+            let reply: Reply = extract_reply(&reply_msg)?;
+            if reply.status != Status::Success as u32 {
+                return Err(Error::other(format!("Handoff failed with status {}", reply.status)));
+            }
+            if is_out_of_memory {
+                log::trace!("Exiting pull_keys");
+                return Err(Error::new(ErrorKind::OutOfMemory, format!("Hit memory limit after {} keys", num_keys)));
+            }
+            if reply.entries.is_empty() {
+                break;
+            }
+
+            for entry in reply.entries {
+                cursor = Some(entry.key.clone());
+                let ack: KeyValue = create_ack(&entry);
+                let mut shard: ShardGuard<S> = self.store.lock(&entry.key);
+                match self.apply_key_value(&mut shard, entry) {
+                    Ok(true) => {},
+                    Ok(false) => {
+                        is_out_of_memory = true;
+                        break;
+                    },
+                    // Left unacked, so the sender keeps it
+                    Err(e) if e.kind() == ErrorKind::InvalidData => {
+                        log::warn!("Refused handed off key: {}", e);
+                        continue;
+                    },
+                    Err(e) => return Err(e),
+                }
+                acked.push(ack);
+                num_keys += 1;
+            }
+        }
+
+        log::trace!("Exiting pull_keys");
+        Ok(num_keys)
+    }
+
+    pub fn run(&self) -> Result<()> {
+        log::info!("Server N{} starting up...", self.id);
+
+        let mut task_times: TaskTimes = TaskTimes::new();
+        if self.num_workers > 1 {
+            self.run_workers(&mut task_times);
+        } else {
+            while self.should_keep_running.load(atomic::Ordering::SeqCst) {
+                self.run_periodic_tasks(&mut task_times);
+                if let Some((msg, sender_addr)) = self.receive_message() {
+                    self.reply_to(msg, sender_addr);
+                }
+            }
+        }
+
+        if self.should_keep_running.load(atomic::Ordering::SeqCst) {
             log::error!("Node run loop exited unexpectedly");
-            Err(Error::new(ErrorKind::Other, "Node run loop exited unexpectedly"))
+            Err(Error::other("Node run loop exited unexpectedly"))
         } else {
             log::info!("Server N{} shutting down...", self.id);
             Ok(())
         }
     }
 
-    fn get_reply(&mut self, msg: UDPMessage) -> Result<Reply> {
+    /// Receives messages on this thread and hands them to a pool of workers,
+    /// each taking the next message as soon as it is free. Up to
+    /// `MAX_QUEUED_REQUESTS` wait on the workers, and the messages arriving
+    /// while the queue is full are dropped.
+    fn run_workers(&self, task_times: &mut TaskTimes) {
+        log::info!("Handling requests with {} workers", self.num_workers);
+
+        let (sender, receiver): (SyncSender<Inbound>, Receiver<Inbound>) = mpsc::sync_channel(MAX_QUEUED_REQUESTS);
+        let receiver: Mutex<Receiver<Inbound>> = Mutex::new(receiver);
+        thread::scope(|scope| {
+            for _ in 0..self.num_workers {
+                scope.spawn(|| loop {
+                    let next = receiver.lock().unwrap().recv();
+                    match next {
+                        Ok((msg, sender_addr)) => self.reply_to(msg, sender_addr),
+                        Err(_) => break,
+                    }
+                });
+            }
+
+            while self.should_keep_running.load(atomic::Ordering::SeqCst) {
+                self.run_periodic_tasks(task_times);
+                if let Some((msg, sender_addr)) = self.receive_message() {
+                    match sender.try_send((msg, sender_addr)) {
+                        Ok(()) => (),
+                        Err(TrySendError::Full((_, sender_addr))) => {
+                            log::warn!("Dropping message from {}, too many requests queued", sender_addr);
+                        },
+                        Err(TrySendError::Disconnected(_)) => {
+                            log::error!("Workers stopped taking requests");
+                            break;
+                        },
+                    }
+                }
+            }
+            // Lets the workers finish what is queued and stop
+            drop(sender);
+        });
+    }
+
+    /// Runs the node on the tokio runtime. Messages are taken in on a
+    /// blocking thread, so that the socket stays blocking for the replies
+    /// sent from the others, and each request is handled on the blocking pool
+    /// so that many can be in flight without holding up the others. Up to
+    /// `MAX_ASYNC_REQUESTS` are handled at once. The periodic tasks run on a
+    /// blocking thread of their own.
+    #[cfg(feature = "async")]
+    pub async fn run_async(self: Arc<Self>) -> Result<()>
+    where
+        S: 'static,
+        T: 'static,
+    {
+        log::info!("Server N{} starting up on the async runtime...", self.id);
+
+        let node: Arc<Self> = self.clone();
+        let periodic_tasks = tokio::task::spawn_blocking(move || {
+            let mut task_times: TaskTimes = TaskTimes::new();
+            while node.should_keep_running.load(atomic::Ordering::SeqCst) {
+                node.run_periodic_tasks(&mut task_times);
+                thread::sleep(ASYNC_POLL_INTERVAL);
+            }
+        });
+
+        let node: Arc<Self> = self.clone();
+        let runtime: tokio::runtime::Handle = tokio::runtime::Handle::current();
+        let requests: Arc<tokio::sync::Semaphore> = Arc::new(tokio::sync::Semaphore::new(MAX_ASYNC_REQUESTS));
+        let receiver = tokio::task::spawn_blocking(move || {
+            while node.should_keep_running.load(atomic::Ordering::SeqCst) {
+                let permit: tokio::sync::OwnedSemaphorePermit = match runtime.block_on(requests.clone().acquire_owned()) {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                if let Some((msg, sender_addr)) = node.receive_message() {
+                    let node: Arc<Self> = node.clone();
+                    runtime.spawn_blocking(move || {
+                        node.reply_to(msg, sender_addr);
+                        drop(permit);
+                    });
+                }
+            }
+        });
+
+        let _ = periodic_tasks.await;
+        let _ = receiver.await;
+        log::info!("Server N{} shutting down...", self.id);
+        Ok(())
+    }
+
+    fn receive_message(&self) -> Option<(UDPMessage, SocketAddr)> {
+        match self.proto_interface.listen() {
+            Ok((msg, addr)) => {
+                log::trace!("Received message from {}", addr);
+                Some((msg, addr))
+            },
+            Err(e) => match e.kind() {
+                ErrorKind::WouldBlock => {
+                    log::trace!("Timeout on UDP port");
+                    None
+                },
+                _ => {
+                    log::error!("Internal server error: {}", e);
+                    None
+                }
+            }
+        }
+    }
+
+    fn reply_to(&self, msg: UDPMessage, sender_addr: SocketAddr) {
+        let reply_addr: SocketAddr = self.get_forwarded_reply_addr(&msg, sender_addr).unwrap_or(sender_addr);
+        let request_id: Vec<u8> = msg.id.clone();
+        let reply: Reply = match self.get_reply(msg, sender_addr) {
+            Ok(Some(reply)) => reply,
+            Ok(None) => return,
+            Err(e) => {
+                log::debug!("Failed to get reply: {}", e);
+                let mut reply: Reply = Reply::new();
+                reply.status = Status::InternalError as u32;
+                reply
+            }
+        };
+
+        match self.proto_interface.reply(reply, &request_id, reply_addr) {
+            Ok(_) => (),
+            Err(e) => log::debug!("Failed to send reply: {}", e),
+        }
+    }
+
+    /// Sends `request` to another node and waits for its reply over an
+    /// interface of its own, so that replies are never mixed up with the
+    /// requests arriving on the main one or with the replies other workers
+    /// wait for.
+    fn send_and_recv_peer(&self, request: Request, peer: SocketAddr) -> Result<(UDPMessage, SocketAddr)> {
+        self.with_peer_interface(|peer_interface| peer_interface.send_and_recv(request, peer))
+    }
+
+    fn with_peer_interface<R>(&self, exchange: impl FnOnce(&ProtoInterface<T>) -> Result<R>) -> Result<R> {
+        with_peer_interface(&self.peer_interfaces, self.addr.ip(), exchange)
+    }
+
+    fn run_periodic_tasks(&self, task_times: &mut TaskTimes) {
+        if task_times.last_gossip.elapsed() >= GOSSIP_INTERVAL {
+            self.gossip();
+            task_times.last_gossip = Instant::now();
+        }
+
+        if task_times.last_expiry_sweep.elapsed() >= EXPIRY_SWEEP_INTERVAL {
+            self.expire_keys();
+            task_times.last_expiry_sweep = Instant::now();
+        }
+
+        if task_times.last_snapshot.elapsed() >= SNAPSHOT_INTERVAL {
+            // Nothing logged since the last snapshot means nothing to compact
+            if self.wal.lock().unwrap().as_ref().is_some_and(|wal| !wal.is_empty()) {
+                let _ = self.snapshot();
+            }
+            task_times.last_snapshot = Instant::now();
+        }
+    }
+
+    fn expire_keys(&self) {
+        log::trace!("Entering expire_keys");
+
+        let now: Instant = Instant::now();
+        let tombstone_cutoff: Option<Instant> = now.checked_sub(self.tombstone_grace_period);
+        let mut num_expired: usize = 0;
+        let mut num_tombstones: usize = 0;
+        for index in 0..self.store.num_shards() {
+            let mut shard: ShardGuard<S> = self.store.lock_index(index);
+            let expired_keys: Vec<Vec<u8>> = shard.data_store.get_expired_keys(now);
+            for key in expired_keys.iter() {
+                if self.remove_key_value(&mut shard, key).is_err() {
+                    break;
+                }
+                num_expired += 1;
+            }
+
+            if let Some(cutoff) = tombstone_cutoff {
+                let shard_mem_usage: u64 = shard.mem_usage();
+                num_tombstones += shard.drop_tombstones(cutoff);
+                self.store.update_mem_usage(shard_mem_usage, shard.mem_usage());
+            }
+        }
+
+        if num_expired > 0 {
+            log::debug!("Expired {} keys, {} B in use", num_expired, self.store.mem_usage());
+        }
+        if num_tombstones > 0 {
+            log::debug!("Dropped {} tombstones past their grace period", num_tombstones);
+        }
+        log::trace!("Exiting expire_keys");
+    }
+
+    /// Drops `key` from `shard` if its TTL has run out, so that every
+    /// operation sees expired keys as missing even between sweeps.
+    fn expire_key(&self, shard: &mut Shard<S>, key: &[u8]) {
+        if shard.data_store.get(key).is_ok_and(|stored_opt| stored_opt.is_some_and(|stored| stored.is_expired(Instant::now()))) {
+            log::debug!("Key expired (key size: {})", key.len());
+            let _ = self.remove_key_value(shard, key);
+        }
+    }
+
+    fn gossip(&self) {
+        log::trace!("Entering gossip");
+
+        let (peers, members) = {
+            let mut membership = self.membership.lock().unwrap();
+            membership.tick();
+            (membership.random_peers(GOSSIP_FANOUT), membership.to_proto())
+        };
+        for peer in peers {
+            let mut request: Request = Request::new();
+            request.operation = Operation::Gossip as u32;
+            request.members = members.clone();
+            if let Err(e) = self.proto_interface.send(request, peer) {
+                log::debug!("Failed to gossip with peer {}: {}", peer, e);
+            }
+        }
+
+        let changes: Vec<(SocketAddr, MemberState)> = self.membership.lock().unwrap().detect_failures(Instant::now());
+        self.update_ring(changes);
+
+        log::trace!("Exiting gossip");
+    }
+
+    fn update_ring(&self, changes: Vec<(SocketAddr, MemberState)>) {
+        let mut ring = self.ring.write().unwrap();
+        for (peer, state) in changes {
+            match state {
+                MemberState::Alive => ring.add_node(peer),
+                _ => ring.remove_node(&peer),
+            }
+            log::info!("Hash ring updated for peer {}, {} nodes total", peer, ring.len());
+        }
+    }
+
+    /// Nodes holding a copy of `key`, the first being its owner
+    fn get_preference_list(&self, key: &[u8]) -> Vec<SocketAddr> {
+        self.ring.read().unwrap().get_nodes(key, self.replication_factor)
+    }
+
+    fn is_alive(&self, node: &SocketAddr) -> bool {
+        self.membership.lock().unwrap().get_state(node) == Some(MemberState::Alive)
+    }
+
+    /// The client a message was forwarded on behalf of, if it was. Only
+    /// another node of the ring forwards messages, so a reply address from
+    /// anyone else is ignored rather than letting them aim replies at a
+    /// third party.
+    fn get_forwarded_reply_addr(&self, msg: &UDPMessage, sender_addr: SocketAddr) -> Option<SocketAddr> {
+        let reply_addr: &str = msg.reply_addr.as_deref()?;
+        if !self.ring.read().unwrap().contains(&sender_addr) {
+            log::warn!("Ignoring reply address from {}, which is not in the ring", sender_addr);
+            return None;
+        }
+
+        match reply_addr.parse::<SocketAddr>() {
+            Ok(reply_addr) => Some(reply_addr),
+            Err(e) => {
+                log::warn!("Invalid reply address from {}: {}", sender_addr, e);
+                None
+            },
+        }
+    }
+
+    /// Returns `None` when no reply is due: either the message was forwarded
+    /// to the node owning its key, which replies to the client directly, or
+    /// it was one-way gossip.
+    ///
+    /// A retry of a request still being handled by another worker is dropped
+    /// too, the client retries again and finds the reply cached by then.
+    fn get_reply(&self, msg: UDPMessage, sender_addr: SocketAddr) -> Result<Option<Reply>> {
         log::trace!("Entering get_reply: handling with Id of size {}", msg.id.len());
 
         let reply: Reply = match self.get_reply_from_cache(&msg) {
             Ok(cached_reply) => cached_reply,
             Err(_) => {
-                let reply: Reply = match self.handle_message(&msg) {
-                    Ok(reply) => reply,
+                if !self.request_cache.start(&msg.id) {
+                    log::debug!("Dropping retry of a request still being handled");
+                    log::trace!("Exiting get_reply: request in flight");
+                    return Ok(None);
+                }
+                let reply: Reply = match self.handle_message(&msg, sender_addr) {
+                    Ok(Some(reply)) => reply,
+                    Ok(None) => {
+                        self.request_cache.finish(&msg.id);
+                        log::trace!("Exiting get_reply: message was forwarded");
+                        return Ok(None);
+                    },
                     Err(e) => {
                         log::error!("Failed to handle message: {}", e);
                         self.handle_internal_error()
                     }
                 };
 
-                if reply.status != (Status::OutOfMemory as u32) {
-                    self.cache_reply(&msg.id, &reply)?;
-                }
+                let cache_result: Result<()> = match reply.status != (Status::OutOfMemory as u32) {
+                    true => self.cache_reply(&msg.id, &reply),
+                    false => Ok(()),
+                };
+                self.request_cache.finish(&msg.id);
+                cache_result?;
 
                 reply
             }
         };
 
         log::trace!("Exiting get_reply: generated reply with status {}", reply.status);
-        Ok(reply)
+        Ok(Some(reply))
     }
 
-    fn get_reply_from_cache(&mut self, msg: &UDPMessage) -> Result<Reply> {
+    fn get_reply_from_cache(&self, msg: &UDPMessage) -> Result<Reply> {
         log::trace!("Entering get_reply_from_cache: handling with Id of size {}", msg.id.len());
 
         let reply: Result<Reply> = match self.request_cache.get(&msg.id) {
@@ -141,10 +817,43 @@ impl Node {
         reply
     }
 
-    fn handle_message(&mut self, msg: &UDPMessage) -> Result<Reply> {
+    fn handle_message(&self, msg: &UDPMessage, sender_addr: SocketAddr) -> Result<Option<Reply>> {
         log::trace!("Entering handle_message");
 
         let request: Request = extract_request(msg)?;
+        if let Ok(Operation::Gossip) = request.operation.try_into() {
+            self.handle_gossip(request);
+            log::trace!("Exiting handle_message");
+            return Ok(None);
+        }
+
+        if let Some(owner) = self.get_remote_coordinator(&request) {
+            // A forwarded message is handled where it lands so that nodes with
+            // different views of the ring never bounce it back and forth
+            if self.get_forwarded_reply_addr(msg, sender_addr).is_none() {
+                if self.is_read_from_one_copy(&request) {
+                    let reply: Reply = self.get_from_owner(request, owner);
+                    log::trace!("Exiting handle_message");
+                    return Ok(Some(reply));
+                }
+                // The owner cannot reach a client on a connection to this
+                // node, so the reply comes back through here
+                if self.proto_interface.is_connected(sender_addr) {
+                    let reply: Reply = self.relay_to_owner(request, owner)?;
+                    log::trace!("Exiting handle_message");
+                    return Ok(Some(reply));
+                }
+                self.forward_message(msg, sender_addr, owner)?;
+                log::trace!("Exiting handle_message");
+                return Ok(None);
+            }
+            log::warn!("Handling forwarded request for a key owned by {}", owner);
+        }
+
+        if let Some(key) = request.key.as_deref() {
+            self.expire_key(&mut self.store.lock(key), key);
+        }
+
         let reply: Reply = match request.operation.try_into() {
             Ok(Operation::Put) => self.handle_put(request),
             Ok(Operation::Get) => self.handle_get(request),
@@ -153,14 +862,246 @@ impl Node {
             Ok(Operation::Ping) => self.handle_ping(),
             Ok(Operation::Shutdown) => self.handle_shutdown(),
             Ok(Operation::GetPid) => self.handle_getpid(),
+            Ok(Operation::Join) => self.handle_join(request),
+            Ok(Operation::Handoff) => self.handle_handoff(request),
+            Ok(Operation::HardShutdown) => self.handle_hard_shutdown(),
+            Ok(Operation::Transfer) => self.handle_transfer(request),
+            Ok(Operation::ReplicaPut) => self.handle_replica_put(request),
+            Ok(Operation::ReplicaDelete) => self.handle_replica_delete(request),
+            Ok(Operation::ReplicaGet) => self.handle_replica_get(request),
+            Ok(Operation::CompareAndSwap) => self.handle_compare_and_swap(request),
+            Ok(Operation::PutIfAbsent) => self.handle_put_if_absent(request),
+            Ok(Operation::DeleteIfValue) => self.handle_delete_if_value(request),
+            Ok(Operation::GetMemoryUsage) => self.handle_get_memory_usage(),
+            Ok(Operation::Snapshot) => self.handle_snapshot(),
+            Ok(Operation::Scan) => self.handle_scan(request, "SCAN"),
+            Ok(Operation::PrefixScan) => self.handle_scan(request, "PREFIXSCAN"),
+            Ok(Operation::LocalScan) => self.handle_local_scan(request),
+            Ok(Operation::MultiGet) => self.handle_multi(request, sender_addr, "MULTIGET"),
+            Ok(Operation::MultiPut) => self.handle_multi(request, sender_addr, "MULTIPUT"),
+            Ok(Operation::MultiDelete) => self.handle_multi(request, sender_addr, "MULTIDELETE"),
+            Ok(Operation::Increment) => self.handle_increment(request, "INCREMENT"),
+            Ok(Operation::Decrement) => self.handle_increment(request, "DECREMENT"),
+            Ok(Operation::Append) => self.handle_append(request),
+            Ok(Operation::SetRange) => self.handle_set_range(request),
+            Ok(Operation::GetRange) => self.handle_get_range(request),
             _ => self.handle_undefined_operation(request.operation),
         };
 
         log::trace!("Exiting handle_message");
+        Ok(Some(reply))
+    }
+
+    fn get_remote_coordinator(&self, request: &Request) -> Option<SocketAddr> {
+        let key: &[u8] = match request.operation.try_into() {
+            Ok(Operation::Put)
+            | Ok(Operation::Get)
+            | Ok(Operation::Delete)
+            | Ok(Operation::CompareAndSwap)
+            | Ok(Operation::PutIfAbsent)
+            | Ok(Operation::DeleteIfValue)
+            | Ok(Operation::Increment)
+            | Ok(Operation::Decrement)
+            | Ok(Operation::Append)
+            | Ok(Operation::SetRange)
+            | Ok(Operation::GetRange) => request.key.as_deref()?,
+            _ => return None,
+        };
+
+        match self.get_coordinator(key) {
+            Some(coordinator) if coordinator != self.addr => Some(coordinator),
+            _ => None,
+        }
+    }
+
+    /// The first node holding `key` that is not suspected to have failed, so
+    /// that requests fall back to a replica while a silent owner is probed.
+    fn get_coordinator(&self, key: &[u8]) -> Option<SocketAddr> {
+        let preference_list: Vec<SocketAddr> = self.get_preference_list(key);
+        preference_list
+            .iter()
+            .find(|node| self.is_alive(node))
+            .or(preference_list.first())
+            .copied()
+    }
+
+    /// Replicas that should receive a copy of a write to `key`. Suspected
+    /// nodes are skipped so that a silent replica does not stall the writer.
+    fn get_replicas(&self, key: &[u8]) -> Vec<SocketAddr> {
+        self.get_preference_list(key)
+            .into_iter()
+            .filter(|node| *node != self.addr)
+            .filter(|node| self.is_alive(node))
+            .collect()
+    }
+
+    /// Number of copies of `key` that must answer a request, or `None` if its
+    /// consistency level is not recognised. Requests default to ONE.
+    fn get_required_replies(&self, consistency: Option<u32>, key: &[u8]) -> Option<usize> {
+        let consistency: Consistency = match consistency {
+            Some(level) => level.try_into().ok()?,
+            None => Consistency::One,
+        };
+        let num_copies: usize = self.get_preference_list(key).len().max(1);
+        Some(consistency.required_replies(num_copies))
+    }
+
+    /// Sends `request` to every replica at once on the peer pool, returning
+    /// how many acknowledged it as soon as `required_acks` have, or else once
+    /// every replica has answered or been given up on. Replicas that have yet
+    /// to answer by then are still sent the write in the background.
+    fn replicate(&self, replicas: &[SocketAddr], request: Request, required_acks: usize) -> usize {
+        log::trace!("Entering replicate");
+
+        let (sender, receiver): (Sender<bool>, Receiver<bool>) = mpsc::channel();
+        for replica in replicas.iter().copied() {
+            let peer_interfaces: PeerInterfaces<T> = self.peer_interfaces.clone();
+            let (ip, request, sender) = (self.addr.ip(), request.clone(), sender.clone());
+            self.peer_pool.execute(move || {
+                let is_acked: bool = replicate_to(&peer_interfaces, ip, request, replica);
+                let _ = sender.send(is_acked);
+            });
+        }
+        drop(sender);
+
+        let mut num_acks: usize = 0;
+        if required_acks > 0 {
+            for is_acked in receiver.iter() {
+                if is_acked {
+                    num_acks += 1;
+                }
+                if num_acks >= required_acks {
+                    break;
+                }
+            }
+        }
+
+        log::trace!("Exiting replicate");
+        num_acks
+    }
+
+    /// Reads `key` from every replica, returning how many answered, the
+    /// newest value found and the latest delete of the key they know of.
+    fn read_replicas(&self, replicas: &[SocketAddr], key: &[u8]) -> (usize, Option<StoredValue>, Option<Version>) {
+        log::trace!("Entering read_replicas");
+
+        let mut request: Request = Request::new();
+        request.operation = Operation::ReplicaGet as u32;
+        request.key = Some(key.to_vec());
+
+        let mut num_replies: usize = 0;
+        let mut newest: Option<StoredValue> = None;
+        let mut deleted: Option<Version> = None;
+        for replica in replicas {
+            let reply: Reply = match self.send_and_recv_peer(request.clone(), *replica) {
+                Ok((reply_msg, _)) => match extract_reply(&reply_msg) {
+                    Ok(reply) => reply,
+                    Err(e) => {
+                        log::warn!("Invalid reply from replica {}: {}", replica, e);
+                        continue;
+                    },
+                },
+                Err(e) => {
+                    log::warn!("Failed to read from replica {}: {}", replica, e);
+                    continue;
+                },
+            };
+
+            match reply.status.try_into() {
+                Ok(Status::Success) => {
+                    num_replies += 1;
+                    let stored: StoredValue = StoredValue {
+                        value: reply.value.unwrap_or_default(),
+                        version: reply.version.unwrap_or_default(),
+                        expires_at: reply.ttl_ms.map(|ttl_ms| Instant::now() + Duration::from_millis(ttl_ms)),
+                    };
+                    newest = newer_value(newest, Some(stored));
+                },
+                Ok(Status::KeyNotFound) => {
+                    num_replies += 1;
+                    deleted = newer_version(deleted, reply.version.into_option());
+                },
+                _ => log::warn!("Replica {} failed read with status {}", replica, reply.status),
+            }
+        }
+
+        log::trace!("Exiting read_replicas");
+        (num_replies, newest, deleted)
+    }
+
+    fn forward_message(&self, msg: &UDPMessage, sender_addr: SocketAddr, owner: SocketAddr) -> Result<()> {
+        log::trace!("Entering forward_message");
+        log::debug!("Forwarding message from {} to owner {}", sender_addr, owner);
+
+        // The message keeps its Id so the owner's reply cache still
+        // deduplicates client retries that arrive through this node
+        let mut forwarded_msg: UDPMessage = msg.clone();
+        proto::set_reply_addr(&mut forwarded_msg, sender_addr);
+        self.proto_interface.forward(&forwarded_msg, owner)?;
+
+        log::trace!("Exiting forward_message");
+        Ok(())
+    }
+
+    /// Sends `request` to `owner` and returns its reply, for clients that
+    /// only this node can answer.
+    fn relay_to_owner(&self, request: Request, owner: SocketAddr) -> Result<Reply> {
+        log::trace!("Entering relay_to_owner");
+        log::debug!("Relaying request to owner {}", owner);
+
+        let (reply_msg, _) = self.send_and_recv_peer(request, owner)?;
+        let reply: Reply = extract_reply(&reply_msg)?;
+
+        log::trace!("Exiting relay_to_owner");
         Ok(reply)
     }
 
-    fn handle_put(&mut self, request: Request) -> Reply {
+    /// Whether `request` is a GET that any single copy of its key can answer
+    fn is_read_from_one_copy(&self, request: &Request) -> bool {
+        let is_get: bool = matches!(request.operation.try_into(), Ok(Operation::Get));
+        is_get && request.key.as_deref().and_then(|key| self.get_required_replies(request.consistency, key)) == Some(1)
+    }
+
+    /// Asks `owner` for a key on behalf of a client reading it at ONE.
+    /// Should the owner not answer in time the key is read from the other
+    /// replicas right away, rather than leaving the client waiting until the
+    /// owner is suspected.
+    ///
+    /// Reads needing more copies are still forwarded, as the owner reads them
+    /// from the replicas, this node among them, while this one waits.
+    fn get_from_owner(&self, request: Request, owner: SocketAddr) -> Reply {
+        log::trace!("Entering get_from_owner");
+
+        let key: Vec<u8> = request.key.clone().unwrap_or_default();
+        let result: Result<Reply> = self
+            .with_peer_interface(|peer_interface| peer_interface.send_and_recv_once(request, owner, OWNER_READ_TIMEOUT))
+            .and_then(|(reply_msg, _)| extract_reply(&reply_msg));
+        match result {
+            Ok(reply) => {
+                log::trace!("Exiting get_from_owner");
+                return reply;
+            },
+            Err(e) => log::info!("Owner {} did not answer GET, reading from the replicas: {}", owner, e),
+        }
+
+        let replicas: Vec<SocketAddr> = self.get_replicas(&key).into_iter().filter(|node| *node != owner).collect();
+        let (mut num_replies, mut stored_opt, mut deleted_opt) = self.read_replicas(&replicas, &key);
+        if self.get_preference_list(&key).contains(&self.addr) {
+            let shard: ShardGuard<S> = self.store.lock(&key);
+            if let Ok(local_opt) = shard.data_store.get(&key) {
+                num_replies += 1;
+                stored_opt = newer_value(stored_opt, local_opt.filter(|stored| !stored.is_expired(Instant::now())));
+                deleted_opt = newer_version(deleted_opt, shard.get_deleted_version(&key).cloned());
+            }
+        }
+
+        let stored_opt: Option<StoredValue> = drop_if_deleted(stored_opt, deleted_opt.as_ref());
+        let reply: Reply = create_read_reply(num_replies, 1, stored_opt, &key, "GET");
+        log::trace!("Exiting get_from_owner");
+        reply
+    }
+
+    fn handle_put(&self, request: Request) -> Reply {
         log::debug!("Entering handle_put");
 
         let mut reply: Reply = Reply::new();
@@ -185,27 +1126,654 @@ impl Node {
             }
         };
 
-        if value.len() > MAX_VALUE_PAYLOAD_SIZE_BYTES {
+        if value.len() > self.max_value_size {
             log::debug!("PUT request InvalidValueSize. Value with size {} B exceeds the maximum of {} B",
                 value.len(),
-                MAX_VALUE_PAYLOAD_SIZE_BYTES);
+                self.max_value_size);
             reply.status = Status::InvalidValueSize as u32;
             log::trace!("Exiting handle_put");
             return reply;
         }
 
-        let key_value_mem_usage: u64 = (key.len() as u64) + (value.len() as u64);
-        if self.data_store_mem_usage + key_value_mem_usage <= self.max_mem {
-            log::debug!("PUT request Success (key size: {}, value size: {})", key.len(), value.len());
-            self.data_store.insert(key, value);
-            self.data_store_mem_usage += key_value_mem_usage;
-            reply.status = Status::Success as u32;
+        let required_acks: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_acks) => required_acks,
+            None => {
+                log::debug!("PUT request InvalidValue, unknown consistency level");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_put");
+                return reply;
+            }
+        };
+
+        // A version sent by the client is one it has already seen, so the
+        // write is ordered after it
+        if let Some(version) = request.version.as_ref() {
+            if let Err(e) = self.clock.lock().unwrap().observe(version) {
+                log::debug!("PUT request InvalidValue. {}", e);
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_put");
+                return reply;
+            }
+        }
+
+        let reply: Reply = self.write_key_value(self.store.lock(&key), key, value, request.ttl_ms, required_acks, "PUT");
+        log::trace!("Exiting handle_put");
+        reply
+    }
+
+    fn handle_put_if_absent(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_put_if_absent");
+
+        let mut reply: Reply = Reply::new();
+
+        let key: Vec<u8> = match request.key {
+            Some(key) => key,
+            None => {
+                log::debug!("PUTIFABSENT request MissingKey");
+                reply.status = Status::MissingKey as u32;
+                log::trace!("Exiting handle_put_if_absent");
+                return reply;
+            }
+        };
+
+        let value: Vec<u8> = match request.value {
+            Some(value) => value,
+            None => {
+                log::debug!("PUTIFABSENT request MissingValue");
+                reply.status = Status::MissingValue as u32;
+                log::trace!("Exiting handle_put_if_absent");
+                return reply;
+            }
+        };
+
+        if value.len() > self.max_value_size {
+            log::debug!("PUTIFABSENT request InvalidValueSize. Value with size {} B exceeds the maximum of {} B",
+                value.len(),
+                self.max_value_size);
+            reply.status = Status::InvalidValueSize as u32;
+            log::trace!("Exiting handle_put_if_absent");
+            return reply;
+        }
+
+        let required_acks: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_acks) => required_acks,
+            None => {
+                log::debug!("PUTIFABSENT request InvalidValue, unknown consistency level");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_put_if_absent");
+                return reply;
+            }
+        };
+
+        let shard: ShardGuard<S> = self.store.lock(&key);
+        let stored_opt: Option<StoredValue> = match shard.data_store.get(&key) {
+            Ok(stored_opt) => stored_opt,
+            Err(_) => {
+                log::info!("PUTIFABSENT request InternalError, failed to read the stored value");
+                reply.status = Status::InternalError as u32;
+                log::trace!("Exiting handle_put_if_absent");
+                return reply;
+            },
+        };
+        if let Some(stored) = stored_opt {
+            log::debug!("PUTIFABSENT request KeyExists");
+            reply.status = Status::KeyExists as u32;
+            reply.value = Some(stored.value);
+            reply.version = MessageField::some(stored.version);
+            log::trace!("Exiting handle_put_if_absent");
+            return reply;
+        }
+
+        let reply: Reply = self.write_key_value(shard, key, value, request.ttl_ms, required_acks, "PUTIFABSENT");
+        log::trace!("Exiting handle_put_if_absent");
+        reply
+    }
+
+    fn handle_compare_and_swap(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_compare_and_swap");
+
+        let mut reply: Reply = Reply::new();
+
+        let key: Vec<u8> = match request.key {
+            Some(key) => key,
+            None => {
+                log::debug!("COMPAREANDSWAP request MissingKey");
+                reply.status = Status::MissingKey as u32;
+                log::trace!("Exiting handle_compare_and_swap");
+                return reply;
+            }
+        };
+
+        let value: Vec<u8> = match request.value {
+            Some(value) => value,
+            None => {
+                log::debug!("COMPAREANDSWAP request MissingValue");
+                reply.status = Status::MissingValue as u32;
+                log::trace!("Exiting handle_compare_and_swap");
+                return reply;
+            }
+        };
+
+        if value.len() > self.max_value_size {
+            log::debug!("COMPAREANDSWAP request InvalidValueSize. Value with size {} B exceeds the maximum of {} B",
+                value.len(),
+                self.max_value_size);
+            reply.status = Status::InvalidValueSize as u32;
+            log::trace!("Exiting handle_compare_and_swap");
+            return reply;
+        }
+
+        let required_acks: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_acks) => required_acks,
+            None => {
+                log::debug!("COMPAREANDSWAP request InvalidValue, unknown consistency level");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_compare_and_swap");
+                return reply;
+            }
+        };
+
+        // The expected version takes precedence over the expected value
+        let shard: ShardGuard<S> = self.store.lock(&key);
+        let stored_opt: Option<StoredValue> = match shard.data_store.get(&key) {
+            Ok(stored_opt) => stored_opt,
+            Err(_) => {
+                log::info!("COMPAREANDSWAP request InternalError, failed to read the stored value");
+                reply.status = Status::InternalError as u32;
+                log::trace!("Exiting handle_compare_and_swap");
+                return reply;
+            },
+        };
+        let precondition_holds: bool = match (request.version.as_ref(), request.expected_value.as_ref(), stored_opt.as_ref()) {
+            (Some(version), _, Some(stored)) => compare_versions(&stored.version, version) == Ordering::Equal,
+            (None, Some(expected_value), Some(stored)) => stored.value == *expected_value,
+            (None, None, _) => {
+                log::debug!("COMPAREANDSWAP request MissingValue, no expected value or version");
+                reply.status = Status::MissingValue as u32;
+                log::trace!("Exiting handle_compare_and_swap");
+                return reply;
+            },
+            (_, _, None) => false,
+        };
+
+        if !precondition_holds {
+            log::debug!("COMPAREANDSWAP request PreconditionFailed");
+            reply.status = Status::PreconditionFailed as u32;
+            if let Some(stored) = stored_opt {
+                reply.value = Some(stored.value.clone());
+                reply.version = MessageField::some(stored.version.clone());
+            }
+            log::trace!("Exiting handle_compare_and_swap");
+            return reply;
+        }
+
+        let reply: Reply = self.write_key_value(shard, key, value, request.ttl_ms, required_acks, "COMPAREANDSWAP");
+        log::trace!("Exiting handle_compare_and_swap");
+        reply
+    }
+
+    /// Adds `delta`, or 1 if it is not given, to the counter held in `key`,
+    /// which starts from 0 if missing. Decrement subtracts it instead. The
+    /// counter is kept as a decimal string and the reply holds its new value.
+    /// `name` is the operation being handled, for logging.
+    fn handle_increment(&self, request: Request, name: &str) -> Reply {
+        log::trace!("Entering handle_increment");
+
+        let mut reply: Reply = Reply::new();
+
+        let key: Vec<u8> = match request.key {
+            Some(key) => key,
+            None => {
+                log::debug!("{} request MissingKey", name);
+                reply.status = Status::MissingKey as u32;
+                log::trace!("Exiting handle_increment");
+                return reply;
+            }
+        };
+
+        let required_acks: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_acks) => required_acks,
+            None => {
+                log::debug!("{} request InvalidValue, unknown consistency level", name);
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_increment");
+                return reply;
+            }
+        };
+
+        // The counter goes on from the newest of as many copies as the write
+        // needs, in case this one missed an update made through another node.
+        // The replicas are read before the shard is locked so that other keys
+        // of the shard are not held up by the round trips, and the local copy
+        // read once locked covers any update made here meanwhile.
+        let mut replica_read: Option<(Option<StoredValue>, Option<Version>)> = None;
+        if required_acks > 1 {
+            let (num_replica_replies, replica_value, replica_deleted) = self.read_replicas(&self.get_replicas(&key), &key);
+            let num_replies: usize = 1 + num_replica_replies;
+            if num_replies < required_acks {
+                log::info!("{} request QuorumNotReached ({} of {} replies)", name, num_replies, required_acks);
+                reply.status = Status::QuorumNotReached as u32;
+                log::trace!("Exiting handle_increment");
+                return reply;
+            }
+            replica_read = Some((replica_value, replica_deleted));
+        }
+
+        let shard: ShardGuard<S> = self.store.lock(&key);
+        let mut stored_opt: Option<StoredValue> = match shard.data_store.get(&key) {
+            Ok(stored_opt) => stored_opt,
+            Err(_) => {
+                log::info!("{} request InternalError, failed to read the stored value", name);
+                reply.status = Status::InternalError as u32;
+                log::trace!("Exiting handle_increment");
+                return reply;
+            },
+        };
+        if let Some((replica_value, replica_deleted)) = replica_read {
+            let deleted_opt: Option<Version> = newer_version(shard.get_deleted_version(&key).cloned(), replica_deleted);
+            stored_opt = drop_if_deleted(newer_value(stored_opt, replica_value), deleted_opt.as_ref());
+        }
+        let current: i64 = match stored_opt.as_ref() {
+            Some(stored) => match parse_counter(&stored.value) {
+                Some(current) => current,
+                None => {
+                    log::debug!("{} request InvalidValue, value is not a 64-bit integer", name);
+                    reply.status = Status::InvalidValue as u32;
+                    log::trace!("Exiting handle_increment");
+                    return reply;
+                },
+            },
+            None => 0,
+        };
+
+        let delta: i64 = request.delta.unwrap_or(1);
+        let counter_opt: Option<i64> = match request.operation.try_into() {
+            Ok(Operation::Decrement) => current.checked_sub(delta),
+            _ => current.checked_add(delta),
+        };
+        let counter: i64 = match counter_opt {
+            Some(counter) => counter,
+            None => {
+                log::debug!("{} request InvalidValue, counter would overflow", name);
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_increment");
+                return reply;
+            },
+        };
+
+        let value: Vec<u8> = counter.to_string().into_bytes();
+        let mut reply: Reply = self.rewrite_key_value(shard, key, value.clone(), request.ttl_ms, required_acks, name);
+        if reply.status == Status::Success as u32 {
+            reply.value = Some(value);
+            reply.counter = Some(counter);
+        }
+
+        log::trace!("Exiting handle_increment");
+        reply
+    }
+
+    /// Adds `value` to the end of the value held in `key`, storing it as is
+    /// if the key is missing.
+    fn handle_append(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_append");
+
+        let mut reply: Reply = Reply::new();
+
+        let (key, value): (Vec<u8>, Vec<u8>) = match (request.key, request.value) {
+            (Some(key), Some(value)) => (key, value),
+            (None, _) => {
+                log::debug!("APPEND request MissingKey");
+                reply.status = Status::MissingKey as u32;
+                log::trace!("Exiting handle_append");
+                return reply;
+            },
+            (_, None) => {
+                log::debug!("APPEND request MissingValue");
+                reply.status = Status::MissingValue as u32;
+                log::trace!("Exiting handle_append");
+                return reply;
+            },
+        };
+
+        let required_acks: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_acks) => required_acks,
+            None => {
+                log::debug!("APPEND request InvalidValue, unknown consistency level");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_append");
+                return reply;
+            }
+        };
+
+        let shard: ShardGuard<S> = self.store.lock(&key);
+        let stored_opt: Option<StoredValue> = match shard.data_store.get(&key) {
+            Ok(stored_opt) => stored_opt,
+            Err(_) => {
+                log::info!("APPEND request InternalError, failed to read the stored value");
+                reply.status = Status::InternalError as u32;
+                log::trace!("Exiting handle_append");
+                return reply;
+            },
+        };
+        let mut new_value: Vec<u8> = stored_opt.as_ref().map(|stored| stored.value.clone()).unwrap_or_default();
+        new_value.extend_from_slice(&value);
+
+        let reply: Reply = self.rewrite_key_value(shard, key, new_value, request.ttl_ms, required_acks, "APPEND");
+        log::trace!("Exiting handle_append");
+        reply
+    }
+
+    /// Overwrites the bytes of the value held in `key` from `offset` onwards
+    /// with `value`. A value too short to reach `offset`, or a missing key,
+    /// is padded with zero bytes first.
+    fn handle_set_range(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_set_range");
+
+        let mut reply: Reply = Reply::new();
+
+        let (key, value): (Vec<u8>, Vec<u8>) = match (request.key, request.value) {
+            (Some(key), Some(value)) => (key, value),
+            (None, _) => {
+                log::debug!("SETRANGE request MissingKey");
+                reply.status = Status::MissingKey as u32;
+                log::trace!("Exiting handle_set_range");
+                return reply;
+            },
+            (_, None) => {
+                log::debug!("SETRANGE request MissingValue");
+                reply.status = Status::MissingValue as u32;
+                log::trace!("Exiting handle_set_range");
+                return reply;
+            },
+        };
+
+        // Checked before anything is allocated for the range
+        let offset: u64 = request.offset.unwrap_or(0);
+        let range_end: Option<u64> = offset.checked_add(value.len() as u64);
+        if range_end.is_none_or(|range_end| range_end > self.max_value_size as u64) {
+            log::debug!("SETRANGE request InvalidValueSize. Range ends past the maximum of {} B",
+                self.max_value_size);
+            reply.status = Status::InvalidValueSize as u32;
+            log::trace!("Exiting handle_set_range");
+            return reply;
+        }
+        let offset: usize = offset as usize;
+
+        let required_acks: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_acks) => required_acks,
+            None => {
+                log::debug!("SETRANGE request InvalidValue, unknown consistency level");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_set_range");
+                return reply;
+            }
+        };
+
+        let shard: ShardGuard<S> = self.store.lock(&key);
+        let stored_opt: Option<StoredValue> = match shard.data_store.get(&key) {
+            Ok(stored_opt) => stored_opt,
+            Err(_) => {
+                log::info!("SETRANGE request InternalError, failed to read the stored value");
+                reply.status = Status::InternalError as u32;
+                log::trace!("Exiting handle_set_range");
+                return reply;
+            },
+        };
+        let mut new_value: Vec<u8> = stored_opt.as_ref().map(|stored| stored.value.clone()).unwrap_or_default();
+        if new_value.len() < offset + value.len() {
+            new_value.resize(offset + value.len(), 0);
+        }
+        new_value[offset..offset + value.len()].copy_from_slice(&value);
+
+        let reply: Reply = self.rewrite_key_value(shard, key, new_value, request.ttl_ms, required_acks, "SETRANGE");
+        log::trace!("Exiting handle_set_range");
+        reply
+    }
+
+    /// Reads `length` bytes, or every byte if it is not given, of the value
+    /// held in `key` from `offset` onwards. The range is cut short at the end
+    /// of the value.
+    fn handle_get_range(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_get_range");
+
+        let mut reply: Reply = Reply::new();
+
+        let key: Vec<u8> = match request.key {
+            Some(key) => key,
+            None => {
+                log::debug!("GETRANGE request MissingKey");
+                reply.status = Status::MissingKey as u32;
+                log::trace!("Exiting handle_get_range");
+                return reply;
+            }
+        };
+
+        let required_replies: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_replies) => required_replies,
+            None => {
+                log::debug!("GETRANGE request InvalidValue, unknown consistency level");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_get_range");
+                return reply;
+            }
+        };
+
+        let mut reply: Reply = self.read_key_value(&key, required_replies, "GETRANGE");
+        if let Some(value) = reply.value.take() {
+            let value_len: u64 = value.len() as u64;
+            let start: u64 = request.offset.unwrap_or(0).min(value_len);
+            let end: u64 = match request.length {
+                Some(length) => start.saturating_add(length).min(value_len),
+                None => value_len,
+            };
+            let (start, end): (usize, usize) = (start as usize, end as usize);
+            reply.value = Some(value[start..end].to_vec());
+        }
+
+        log::trace!("Exiting handle_get_range");
+        reply
+    }
+
+    /// Stores `value` as the new version of `key`, held in `shard`. The key
+    /// keeps its expiry unless a new TTL is given. `name` is the operation
+    /// being handled, for logging.
+    fn rewrite_key_value(
+        &self,
+        shard: ShardGuard<S>,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl_ms: Option<u64>,
+        required_acks: usize,
+        name: &str
+    ) -> Reply {
+        if value.len() > self.max_value_size {
+            log::debug!("{} request InvalidValueSize. Value with size {} B exceeds the maximum of {} B",
+                name,
+                value.len(),
+                self.max_value_size);
+            let mut reply: Reply = Reply::new();
+            reply.status = Status::InvalidValueSize as u32;
+            return reply;
+        }
+
+        // A key on the verge of expiring still gets a positive TTL
+        let remaining_ttl_ms: Option<u64> = match shard.data_store.get(&key) {
+            Ok(stored_opt) => stored_opt
+                .and_then(|stored| stored.remaining_ttl_ms(Instant::now()))
+                .map(|ttl_ms| ttl_ms.max(1)),
+            Err(_) => {
+                log::info!("{} request InternalError, failed to read the stored value", name);
+                let mut reply: Reply = Reply::new();
+                reply.status = Status::InternalError as u32;
+                return reply;
+            },
+        };
+        self.write_key_value(shard, key, value, ttl_ms.or(remaining_ttl_ms), required_acks, name)
+    }
+
+    /// Stores a new version of `key` in `shard`, which is let go of before
+    /// the write is copied to the replicas. `name` is the operation being
+    /// handled, for logging.
+    fn write_key_value(
+        &self,
+        mut shard: ShardGuard<S>,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl_ms: Option<u64>,
+        required_acks: usize,
+        name: &str
+    ) -> Reply {
+        let mut reply: Reply = Reply::new();
+
+        if ttl_ms == Some(0) {
+            log::debug!("{} request InvalidValue, TTL must be positive", name);
+            reply.status = Status::InvalidValue as u32;
+            return reply;
+        }
+        let version: Version = self.clock.lock().unwrap().now();
+        let expires_at: Option<Instant> = ttl_ms.map(|ttl_ms| Instant::now() + Duration::from_millis(ttl_ms));
+
+        let (key_len, value_len) = (key.len(), value.len());
+        let replicas: Vec<SocketAddr> = self.get_replicas(&key);
+        let mut replica_request: Request = Request::new();
+        if !replicas.is_empty() {
+            replica_request.operation = Operation::ReplicaPut as u32;
+            replica_request.key = Some(key.clone());
+            replica_request.value = Some(value.clone());
+            replica_request.version = MessageField::some(version.clone());
+            replica_request.ttl_ms = ttl_ms;
+        }
+
+        let stored: StoredValue = StoredValue { value, version: version.clone(), expires_at };
+        let is_inserted: bool = match self.insert_key_value(&mut shard, key, stored) {
+            Ok(is_inserted) => is_inserted,
+            Err(_) => {
+                log::info!("{} request InternalError, failed to log the write", name);
+                reply.status = Status::InternalError as u32;
+                return reply;
+            }
+        };
+        drop(shard);
+        if is_inserted {
+            reply.version = MessageField::some(version);
+            let num_acks: usize = 1 + self.replicate(&replicas, replica_request, required_acks.saturating_sub(1));
+            if num_acks < required_acks {
+                // The write is not undone, and its version still goes back
+                // so that the client can tell whether a later read sees it
+                log::warn!("{} request QuorumNotReached, write left on {} of {} copies", name, num_acks, required_acks);
+                reply.status = Status::QuorumNotReached as u32;
+            } else {
+                log::debug!("{} request Success (key size: {}, value size: {})", name, key_len, value_len);
+                reply.status = Status::Success as u32;
+            }
         } else {
-            log::info!("PUT request unsuccessful, hit memory limit");
+            log::info!("{} request unsuccessful, hit memory limit", name);
             reply.status = Status::OutOfMemory as u32;
         }
 
-        log::trace!("Exiting handle_put");
+        reply
+    }
+
+    fn handle_replica_put(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_replica_put");
+        let mut reply: Reply = Reply::new();
+
+        match (request.key, request.value, request.version.into_option()) {
+            (Some(key), Some(value), Some(version)) => {
+                let mut entry: KeyValue = KeyValue::new();
+                entry.key = key;
+                entry.value = value;
+                entry.version = MessageField::some(version);
+                entry.ttl_ms = request.ttl_ms;
+                match self.apply_key_value(&mut self.store.lock(&entry.key), entry) {
+                    Ok(true) => {
+                        log::debug!("REPLICAPUT request Success");
+                        reply.status = Status::Success as u32;
+                    },
+                    Ok(false) => {
+                        log::info!("REPLICAPUT request unsuccessful, hit memory limit");
+                        reply.status = Status::OutOfMemory as u32;
+                    },
+                    Err(e) if e.kind() == ErrorKind::InvalidData => {
+                        log::debug!("REPLICAPUT request InvalidValue. {}", e);
+                        reply.status = Status::InvalidValue as u32;
+                    },
+                    Err(_) => reply.status = Status::InternalError as u32,
+                }
+            },
+            (None, _, _) => reply.status = Status::MissingKey as u32,
+            (_, None, _) => reply.status = Status::MissingValue as u32,
+            (_, _, None) => reply.status = Status::InvalidValue as u32,
+        }
+
+        log::trace!("Exiting handle_replica_put");
+        reply
+    }
+
+    fn handle_replica_delete(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_replica_delete");
+        let mut reply: Reply = Reply::new();
+
+        match (request.key, request.version.into_option()) {
+            (Some(key), Some(version)) => {
+                if let Err(e) = self.clock.lock().unwrap().observe(&version) {
+                    log::debug!("REPLICADELETE request InvalidValue. {}", e);
+                    reply.status = Status::InvalidValue as u32;
+                    log::trace!("Exiting handle_replica_delete");
+                    return reply;
+                }
+                // The replica may have missed the write, which is fine here,
+                // as the tombstone keeps the write out should it arrive later
+                let mut shard: ShardGuard<S> = self.store.lock(&key);
+                match self.delete_at_version(&mut shard, &key, version) {
+                    Ok(_) => {
+                        log::debug!("REPLICADELETE request Success");
+                        reply.status = Status::Success as u32;
+                    },
+                    Err(e) if e.kind() == ErrorKind::OutOfMemory => {
+                        log::debug!("REPLICADELETE request OutOfMemory");
+                        reply.status = Status::OutOfMemory as u32;
+                    },
+                    Err(_) => reply.status = Status::InternalError as u32,
+                }
+            },
+            (None, _) => reply.status = Status::MissingKey as u32,
+            (_, None) => reply.status = Status::InvalidValue as u32,
+        }
+
+        log::trace!("Exiting handle_replica_delete");
+        reply
+    }
+
+    fn handle_replica_get(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_replica_get");
+        let mut reply: Reply = Reply::new();
+
+        match request.key {
+            Some(key) => {
+                let shard: ShardGuard<S> = self.store.lock(&key);
+                match shard.data_store.get(&key) {
+                    Ok(Some(stored)) => {
+                        log::debug!("REPLICAGET request Success");
+                        reply.status = Status::Success as u32;
+                        reply.ttl_ms = stored.remaining_ttl_ms(Instant::now());
+                        reply.value = Some(stored.value);
+                        reply.version = MessageField::some(stored.version);
+                    },
+                    Ok(None) => {
+                        // The version of the delete, if the key was deleted
+                        // recently, so that older copies read elsewhere lose
+                        log::debug!("REPLICAGET request KeyNotFound");
+                        reply.status = Status::KeyNotFound as u32;
+                        reply.version = MessageField::from_option(shard.get_deleted_version(&key).cloned());
+                    },
+                    Err(_) => reply.status = Status::InternalError as u32,
+                }
+            },
+            None => reply.status = Status::MissingKey as u32,
+        }
+
+        log::trace!("Exiting handle_replica_get");
         reply
     }
 
@@ -224,72 +1792,642 @@ impl Node {
             }
         };
 
-        let value: Vec<u8> = match self.data_store.get(&key) {
-            Some(value) => {
+        let required_replies: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_replies) => required_replies,
+            None => {
+                log::debug!("GET request InvalidValue, unknown consistency level");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_get");
+                return reply;
+            }
+        };
+
+        let reply: Reply = self.read_key_value(&key, required_replies, "GET");
+        log::trace!("Exiting handle_get");
+        reply
+    }
+
+    /// Reads `key` here and, when more than one copy must answer, from the
+    /// replicas. `name` is the operation being handled, for logging.
+    fn read_key_value(&self, key: &[u8], required_replies: usize, name: &str) -> Reply {
+        let mut reply: Reply = Reply::new();
+
+        let (mut stored_opt, mut deleted_opt) = {
+            let mut shard: ShardGuard<S> = self.store.lock(key);
+            let stored_opt: Option<StoredValue> = match shard.data_store.get(key) {
+                Ok(stored_opt) => stored_opt,
+                Err(_) => {
+                    log::info!("{} request InternalError, failed to read the stored value", name);
+                    reply.status = Status::InternalError as u32;
+                    return reply;
+                },
+            };
+            shard.evictor.on_access(key);
+            (stored_opt, shard.get_deleted_version(key).cloned())
+        };
+        if required_replies > 1 {
+            let (num_replica_replies, replica_value, replica_deleted) = self.read_replicas(&self.get_replicas(key), key);
+            let num_replies: usize = 1 + num_replica_replies;
+            if num_replies < required_replies {
+                log::info!("{} request QuorumNotReached ({} of {} replies)", name, num_replies, required_replies);
+                reply.status = Status::QuorumNotReached as u32;
+                return reply;
+            }
+            stored_opt = newer_value(stored_opt, replica_value);
+            deleted_opt = newer_version(deleted_opt, replica_deleted);
+        }
+
+        let value: Vec<u8> = match drop_if_deleted(stored_opt, deleted_opt.as_ref()) {
+            Some(stored) => {
                 reply.status = Status::Success as u32;
-                value.to_vec()
+                reply.ttl_ms = stored.remaining_ttl_ms(Instant::now());
+                reply.version = MessageField::some(stored.version);
+                stored.value
             },
             None => {
-                log::debug!("GET request KeyNotFound");
+                log::debug!("{} request KeyNotFound", name);
                 reply.status = Status::KeyNotFound as u32;
-                log::trace!("Exiting handle_get");
                 return reply;
             },
         };
 
-        log::debug!("GET request Success (key size: {}, value size: {})", key.len(), value.len());
-        reply.value = Some(value);
+        log::debug!("{} request Success (key size: {}, value size: {})", name, key.len(), value.len());
+        reply.value = Some(value);
+        reply
+    }
+
+    fn handle_delete(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_delete");
+
+        let mut reply: Reply = Reply::new();
+
+        let key: Vec<u8> = match request.key {
+            Some(key) => key,
+            None => {
+                log::debug!("DELETE request MissingKey");
+                reply.status = Status::MissingKey as u32;
+                log::trace!("Exiting handle_delete");
+                return reply;
+            }
+        };
+
+        let required_acks: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_acks) => required_acks,
+            None => {
+                log::debug!("DELETE request InvalidValue, unknown consistency level");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_delete");
+                return reply;
+            }
+        };
+
+        let reply: Reply = self.delete_key_value(self.store.lock(&key), key, required_acks, "DELETE");
+        log::trace!("Exiting handle_delete");
+        reply
+    }
+
+    fn handle_delete_if_value(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_delete_if_value");
+
+        let mut reply: Reply = Reply::new();
+
+        let key: Vec<u8> = match request.key {
+            Some(key) => key,
+            None => {
+                log::debug!("DELETEIFVALUE request MissingKey");
+                reply.status = Status::MissingKey as u32;
+                log::trace!("Exiting handle_delete_if_value");
+                return reply;
+            }
+        };
+
+        let expected_value: Vec<u8> = match request.expected_value {
+            Some(expected_value) => expected_value,
+            None => {
+                log::debug!("DELETEIFVALUE request MissingValue");
+                reply.status = Status::MissingValue as u32;
+                log::trace!("Exiting handle_delete_if_value");
+                return reply;
+            }
+        };
+
+        let required_acks: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_acks) => required_acks,
+            None => {
+                log::debug!("DELETEIFVALUE request InvalidValue, unknown consistency level");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_delete_if_value");
+                return reply;
+            }
+        };
+
+        let shard: ShardGuard<S> = self.store.lock(&key);
+        match shard.data_store.get(&key) {
+            Ok(Some(stored)) if stored.value != expected_value => {
+                log::debug!("DELETEIFVALUE request PreconditionFailed");
+                reply.status = Status::PreconditionFailed as u32;
+                reply.value = Some(stored.value);
+                reply.version = MessageField::some(stored.version);
+                log::trace!("Exiting handle_delete_if_value");
+                return reply;
+            },
+            Err(_) => {
+                log::info!("DELETEIFVALUE request InternalError, failed to read the stored value");
+                reply.status = Status::InternalError as u32;
+                log::trace!("Exiting handle_delete_if_value");
+                return reply;
+            },
+            _ => (),
+        }
+
+        let reply: Reply = self.delete_key_value(shard, key, required_acks, "DELETEIFVALUE");
+        log::trace!("Exiting handle_delete_if_value");
+        reply
+    }
+
+    /// Removes `key` from `shard`, which is let go of before the delete is
+    /// copied to the replicas, replying with the value it held. `name` is
+    /// the operation being handled, for logging.
+    fn delete_key_value(&self, mut shard: ShardGuard<S>, key: Vec<u8>, required_acks: usize, name: &str) -> Reply {
+        let mut reply: Reply = Reply::new();
+
+        if !shard.data_store.contains_key(&key) {
+            log::debug!("{} request KeyNotFound", name);
+            reply.status = Status::KeyNotFound as u32;
+            return reply;
+        }
+        let version: Version = self.clock.lock().unwrap().now();
+        let stored: StoredValue = match self.delete_at_version(&mut shard, &key, version.clone()) {
+            Ok(Some(stored)) => stored,
+            Ok(None) => {
+                log::debug!("{} request KeyNotFound", name);
+                reply.status = Status::KeyNotFound as u32;
+                return reply;
+            },
+            Err(e) if e.kind() == ErrorKind::OutOfMemory => {
+                log::debug!("{} request OutOfMemory", name);
+                reply.status = Status::OutOfMemory as u32;
+                return reply;
+            },
+            Err(_) => {
+                log::info!("{} request InternalError, failed to log the delete", name);
+                reply.status = Status::InternalError as u32;
+                return reply;
+            },
+        };
+
+        drop(shard);
+
+        let mut replica_request: Request = Request::new();
+        replica_request.operation = Operation::ReplicaDelete as u32;
+        replica_request.key = Some(key.clone());
+        replica_request.version = MessageField::some(version);
+        let num_acks: usize = 1 + self.replicate(&self.get_replicas(&key), replica_request, required_acks.saturating_sub(1));
+        if num_acks < required_acks {
+            log::warn!("{} request QuorumNotReached, delete left on {} of {} copies", name, num_acks, required_acks);
+            reply.status = Status::QuorumNotReached as u32;
+            return reply;
+        }
+
+        log::debug!("{} request Success (key size: {})", name, key.len());
+        reply.status = Status::Success as u32;
+        reply.value = Some(stored.value);
+        reply.version = MessageField::some(stored.version);
+        reply
+    }
+
+    /// Gets, puts or deletes every key in `entries`, replying with an entry
+    /// per key that holds its status. `name` is the operation being handled,
+    /// for logging.
+    ///
+    /// Keys this node has a copy of are handled here. The others are grouped
+    /// by owner and each group is sent to its owner in a single batch, every
+    /// group at once. A node with a single worker handles requests on the
+    /// thread receiving them, so it could not answer the owners copying keys
+    /// back to it while it waits on them; it coordinates every key through
+    /// the replicas instead.
+    fn handle_multi(&self, request: Request, sender_addr: SocketAddr, name: &str) -> Reply {
+        log::trace!("Entering handle_multi");
+
+        let mut reply: Reply = Reply::new();
+
+        if request.entries.is_empty() {
+            log::debug!("{} request MissingKey", name);
+            reply.status = Status::MissingKey as u32;
+            log::trace!("Exiting handle_multi");
+            return reply;
+        }
+
+        let is_handled_whole: bool = self.num_workers == 1 || self.is_batch_from_peer(&request, sender_addr);
+        let mut local: Vec<usize> = Vec::new();
+        let mut groups: HashMap<SocketAddr, Vec<usize>> = HashMap::new();
+        for (index, entry) in request.entries.iter().enumerate() {
+            match self.get_batch_owner(is_handled_whole, &entry.key) {
+                Some(owner) => groups.entry(owner).or_default().push(index),
+                None => local.push(index),
+            }
+        }
+
+        let mut results: Vec<Option<KeyValue>> = vec![None; request.entries.len()];
+        thread::scope(|scope| {
+            let sent_groups: Vec<_> = groups
+                .into_iter()
+                .map(|(owner, indices)| {
+                    let request: &Request = &request;
+                    scope.spawn(move || {
+                        let entries: Vec<KeyValue> = indices.iter().map(|index| request.entries[*index].clone()).collect();
+                        (indices, self.apply_batch_group(request, entries, owner, name))
+                    })
+                })
+                .collect();
+
+            for index in local {
+                results[index] = Some(self.apply_batch_entry(&request, request.entries[index].clone(), name));
+            }
+            for sent_group in sent_groups {
+                let (indices, group_results) = sent_group.join().unwrap();
+                for (index, result) in indices.into_iter().zip(group_results) {
+                    results[index] = Some(result);
+                }
+            }
+        });
+
+        let mut entries: Vec<KeyValue> = results.into_iter().flatten().collect();
+        fit_batch_reply(&mut entries, self.max_page_size);
+        log::debug!("{} request Success ({} keys)", name, entries.len());
+        reply.status = Status::Success as u32;
+        reply.entries = entries;
+
+        log::trace!("Exiting handle_multi");
+        reply
+    }
+
+    /// Whether a batch is a group sent by another node, which names itself in
+    /// `node_addr`. Nodes send groups from ports of their own, so the node
+    /// named has to be in the ring and on the host the batch came from;
+    /// anyone else naming a node is ignored rather than letting them skip the
+    /// owners of their keys.
+    fn is_batch_from_peer(&self, request: &Request, sender_addr: SocketAddr) -> bool {
+        let node_addr: SocketAddr = match request.node_addr.as_deref().map(str::parse::<SocketAddr>) {
+            Some(Ok(node_addr)) => node_addr,
+            Some(Err(e)) => {
+                log::warn!("Invalid node address from {}: {}", sender_addr, e);
+                return false;
+            },
+            None => return false,
+        };
+        if node_addr.ip() != sender_addr.ip() || !self.ring.read().unwrap().contains(&node_addr) {
+            log::warn!("Ignoring node address {} from {}, which is not in the ring", node_addr, sender_addr);
+            return false;
+        }
+        true
+    }
+
+    /// The owner to send `key` to as part of a group, or `None` if this node
+    /// handles it, as it does every key of a batch handled whole. A group
+    /// sent by another node is handled whole so that no key is passed on
+    /// twice.
+    fn get_batch_owner(&self, is_handled_whole: bool, key: &[u8]) -> Option<SocketAddr> {
+        if is_handled_whole {
+            return None;
+        }
+        if self.get_preference_list(key).contains(&self.addr) {
+            return None;
+        }
+
+        match self.get_coordinator(key) {
+            Some(owner) if owner != self.addr => Some(owner),
+            _ => None,
+        }
+    }
+
+    /// Sends `entries`, which this node holds no copy of, to their `owner`
+    /// in a single batch, returning a result per entry in the same order.
+    /// Should the owner not answer, the entries are handled here through the
+    /// replicas instead.
+    fn apply_batch_group(&self, request: &Request, entries: Vec<KeyValue>, owner: SocketAddr, name: &str) -> Vec<KeyValue> {
+        let mut group_request: Request = Request::new();
+        group_request.operation = request.operation;
+        group_request.consistency = request.consistency;
+        group_request.entries = entries.clone();
+        group_request.node_addr = Some(self.addr.to_string());
+
+        let result: Result<Reply> = self
+            .send_and_recv_peer(group_request, owner)
+            .and_then(|(reply_msg, _)| extract_reply(&reply_msg));
+        match result {
+            Ok(reply) if reply.status == Status::Success as u32 && reply.entries.len() == entries.len() => {
+                return reply.entries;
+            },
+            Ok(reply) => log::warn!("Owner {} failed {} batch with status {}", owner, name, reply.status),
+            Err(e) => log::warn!("Failed to send {} batch to owner {}: {}", name, owner, e),
+        }
+
+        entries
+            .into_iter()
+            .map(|entry| self.apply_batch_entry(request, entry, name))
+            .collect()
+    }
+
+    /// Runs the single key operation that a batch `request` holds for `entry`.
+    ///
+    /// A key this node has no copy of, when its owner could not be reached
+    /// or sent it here as part of a group, is coordinated through the
+    /// replicas.
+    fn apply_batch_entry(&self, request: &Request, entry: KeyValue, name: &str) -> KeyValue {
+        let key: Vec<u8> = entry.key;
+        self.expire_key(&mut self.store.lock(&key), &key);
+
+        let required_replies: usize = match self.get_required_replies(request.consistency, &key) {
+            Some(required_replies) => required_replies,
+            None => {
+                log::debug!("{} request InvalidValue, unknown consistency level", name);
+                return create_batch_result(key, Status::InvalidValue);
+            }
+        };
+        let is_holder: bool = self.get_preference_list(&key).contains(&self.addr);
+
+        let reply: Reply = match request.operation.try_into() {
+            Ok(Operation::MultiGet) if is_holder => self.read_key_value(&key, required_replies, name),
+            Ok(Operation::MultiGet) => self.read_remote_key_value(&key, required_replies, name),
+            Ok(Operation::MultiPut) => {
+                if entry.value.len() > self.max_value_size {
+                    log::debug!("{} request InvalidValueSize. Value with size {} B exceeds the maximum of {} B",
+                        name,
+                        entry.value.len(),
+                        self.max_value_size);
+                    return create_batch_result(key, Status::InvalidValueSize);
+                }
+                if let Some(version) = entry.version.as_ref() {
+                    if let Err(e) = self.clock.lock().unwrap().observe(version) {
+                        log::debug!("{} request InvalidValue. {}", name, e);
+                        return create_batch_result(key, Status::InvalidValue);
+                    }
+                }
+                match is_holder {
+                    true => self.write_key_value(self.store.lock(&key), key.clone(), entry.value, entry.ttl_ms, required_replies, name),
+                    false => self.write_remote_key_value(&key, entry.value, entry.ttl_ms, required_replies, name),
+                }
+            },
+            Ok(Operation::MultiDelete) => {
+                let mut reply: Reply = match is_holder {
+                    true => self.delete_key_value(self.store.lock(&key), key.clone(), required_replies, name),
+                    false => self.delete_remote_key_value(&key, required_replies, name),
+                };
+                // Deleted values are left out to keep the reply small
+                reply.value = None;
+                reply
+            },
+            _ => self.handle_undefined_operation(request.operation),
+        };
+
+        let mut result: KeyValue = KeyValue::new();
+        result.key = key;
+        result.status = Some(reply.status);
+        result.value = reply.value.unwrap_or_default();
+        result.version = reply.version;
+        result.ttl_ms = reply.ttl_ms;
+        result
+    }
+
+    /// Reads `key`, which this node holds no copy of, from the replicas.
+    fn read_remote_key_value(&self, key: &[u8], required_replies: usize, name: &str) -> Reply {
+        let (num_replies, stored_opt, deleted_opt) = self.read_replicas(&self.get_replicas(key), key);
+        create_read_reply(num_replies, required_replies, drop_if_deleted(stored_opt, deleted_opt.as_ref()), key, name)
+    }
+
+    /// Stores a new version of `key`, which this node holds no copy of, on
+    /// the replicas.
+    fn write_remote_key_value(
+        &self,
+        key: &[u8],
+        value: Vec<u8>,
+        ttl_ms: Option<u64>,
+        required_acks: usize,
+        name: &str
+    ) -> Reply {
+        let mut reply: Reply = Reply::new();
+
+        if ttl_ms == Some(0) {
+            log::debug!("{} request InvalidValue, TTL must be positive", name);
+            reply.status = Status::InvalidValue as u32;
+            return reply;
+        }
+        let version: Version = self.clock.lock().unwrap().now();
+        let value_len: usize = value.len();
+
+        let mut replica_request: Request = Request::new();
+        replica_request.operation = Operation::ReplicaPut as u32;
+        replica_request.key = Some(key.to_vec());
+        replica_request.value = Some(value);
+        replica_request.version = MessageField::some(version.clone());
+        replica_request.ttl_ms = ttl_ms;
+        let num_acks: usize = self.replicate(&self.get_replicas(key), replica_request, required_acks.max(1));
+        if num_acks < required_acks.max(1) {
+            log::warn!("{} request QuorumNotReached, write left on {} of {} copies", name, num_acks, required_acks);
+            reply.status = Status::QuorumNotReached as u32;
+            reply.version = MessageField::some(version);
+            return reply;
+        }
+
+        log::debug!("{} request Success (key size: {}, value size: {})", name, key.len(), value_len);
+        reply.status = Status::Success as u32;
+        reply.version = MessageField::some(version);
+        reply
+    }
+
+    /// Removes `key`, which this node holds no copy of, from the replicas.
+    fn delete_remote_key_value(&self, key: &[u8], required_acks: usize, name: &str) -> Reply {
+        let replicas: Vec<SocketAddr> = self.get_replicas(key);
+        let reply: Reply = self.read_remote_key_value(key, required_acks, name);
+        if reply.status != Status::Success as u32 {
+            return reply;
+        }
+
+        let mut replica_request: Request = Request::new();
+        replica_request.operation = Operation::ReplicaDelete as u32;
+        replica_request.key = Some(key.to_vec());
+        replica_request.version = MessageField::some(self.clock.lock().unwrap().now());
+        let num_acks: usize = self.replicate(&replicas, replica_request, required_acks.max(1));
+        if num_acks < required_acks.max(1) {
+            log::warn!("{} request QuorumNotReached, delete left on {} of {} copies", name, num_acks, required_acks);
+            let mut reply: Reply = Reply::new();
+            reply.status = Status::QuorumNotReached as u32;
+            return reply;
+        }
+
+        log::debug!("{} request Success (key size: {})", name, key.len());
+        reply
+    }
+
+    /// Lists keys in order across the cluster. Scan covers the keys from
+    /// `key` up to `end_key`, PrefixScan those starting with `prefix` from
+    /// `key` onwards. A page cut short to fit in one message holds the key the
+    /// next page starts from in `next_key`. `name` is the operation being
+    /// handled, for logging.
+    fn handle_scan(&self, request: Request, name: &str) -> Reply {
+        log::trace!("Entering handle_scan");
+        let mut reply: Reply = Reply::new();
+
+        let mut start: Vec<u8> = request.key.unwrap_or_default();
+        let mut end: Option<Vec<u8>> = request.end_key;
+        if request.operation == Operation::PrefixScan as u32 {
+            let prefix: Vec<u8> = match request.prefix {
+                Some(prefix) => prefix,
+                None => {
+                    log::debug!("{} request MissingKey, no prefix", name);
+                    reply.status = Status::MissingKey as u32;
+                    log::trace!("Exiting handle_scan");
+                    return reply;
+                }
+            };
+            end = match (end, get_prefix_end(&prefix)) {
+                (Some(end), Some(prefix_end)) => Some(end.min(prefix_end)),
+                (end, prefix_end) => end.or(prefix_end),
+            };
+            start = start.max(prefix);
+        }
+        if request.limit == Some(0) {
+            log::debug!("{} request InvalidValue, limit must be positive", name);
+            reply.status = Status::InvalidValue as u32;
+            log::trace!("Exiting handle_scan");
+            return reply;
+        }
+        let limit: usize = request.limit.map_or(usize::MAX, |limit| limit as usize);
+        let include_values: bool = request.include_values.unwrap_or(false);
+
+        let mut pages: Vec<ScanPage> = vec![self.scan_local(&start, end.as_deref(), limit, include_values)];
 
+        let mut peer_request: Request = Request::new();
+        peer_request.operation = Operation::LocalScan as u32;
+        peer_request.key = Some(start);
+        peer_request.end_key = end;
+        peer_request.limit = request.limit;
+        peer_request.include_values = Some(include_values);
+        let nodes: Vec<SocketAddr> = self.ring.read().unwrap().nodes().copied().collect();
+        let peers: Vec<SocketAddr> = nodes
+            .into_iter()
+            .filter(|node| *node != self.addr)
+            .filter(|node| self.is_alive(node))
+            .collect();
+        // Every node returns the copies it holds, so the keys of a peer that
+        // fails are still scanned if another of their replicas answers
+        let mut answered: HashSet<SocketAddr> = HashSet::from([self.addr]);
+        for (peer, page) in self.scan_peers(&peers, peer_request) {
+            pages.push(page);
+            answered.insert(peer);
+        }
 
-        log::trace!("Exiting handle_get");
+        if !self.ring.read().unwrap().is_covered_by(&answered, self.replication_factor) {
+            log::info!("{} request QuorumNotReached, no replica left for some keys", name);
+            reply.status = Status::QuorumNotReached as u32;
+            log::trace!("Exiting handle_scan");
+            return reply;
+        }
+
+        let page: ScanPage = merge_pages(pages, limit, self.max_page_size);
+        log::debug!("{} request Success ({} keys)", name, page.entries.len());
+        reply.status = Status::Success as u32;
+        reply.entries = page.entries;
+        reply.next_key = page.next_key;
+        log::trace!("Exiting handle_scan");
         reply
     }
 
-    fn handle_delete(&mut self, request: Request) -> Reply {
-        log::trace!("Entering handle_delete");
+    /// Sends the LocalScan `request` to every peer at once on the peer pool,
+    /// returning the pages of those that answered in time.
+    fn scan_peers(&self, peers: &[SocketAddr], request: Request) -> Vec<PeerPage> {
+        log::trace!("Entering scan_peers");
 
-        let mut reply: Reply = Reply::new();
+        let (sender, receiver): (Sender<PeerPage>, Receiver<PeerPage>) = mpsc::channel();
+        for peer in peers.iter().copied() {
+            let peer_interfaces: PeerInterfaces<T> = self.peer_interfaces.clone();
+            let (ip, request, sender) = (self.addr.ip(), request.clone(), sender.clone());
+            self.peer_pool.execute(move || {
+                if let Some(page) = scan_peer(&peer_interfaces, ip, request, peer) {
+                    let _ = sender.send((peer, page));
+                }
+            });
+        }
+        drop(sender);
 
-        let key: Vec<u8> = match request.key {
-            Some(key) => key,
-            None => {
-                log::debug!("DELETE request MissingKey");
-                reply.status = Status::MissingKey as u32;
-                log::trace!("Exiting handle_delete");
-                return reply;
-            }
-        };
+        let pages: Vec<PeerPage> = receiver.iter().collect();
+        log::trace!("Exiting scan_peers");
+        pages
+    }
 
-        let value: Vec<u8> = match self.data_store.remove(&key) {
-            Some(value) => value,
-            None => {
-                log::debug!("DELETE request KeyNotFound");
-                reply.status = Status::KeyNotFound as u32;
-                log::trace!("Exiting handle_delete");
-                return reply;
-            },
-        };
-        self.data_store_mem_usage -= (key.len() as u64) + (value.len() as u64);
+    fn handle_local_scan(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_local_scan");
+        let mut reply: Reply = Reply::new();
 
-        log::debug!("DELETE request Success (key size: {})", key.len());
-        reply.status = Status::Success as u32;
-        reply.value = Some(value);
+        let start: Vec<u8> = request.key.unwrap_or_default();
+        let limit: usize = request.limit.map_or(usize::MAX, |limit| limit as usize);
+        let include_values: bool = request.include_values.unwrap_or(false);
+        let page: ScanPage = self.scan_local(&start, request.end_key.as_deref(), limit, include_values);
 
-        log::trace!("Exiting handle_delete");
+        log::debug!("LOCALSCAN request Success ({} keys)", page.entries.len());
+        reply.status = Status::Success as u32;
+        reply.entries = page.entries;
+        reply.next_key = page.next_key;
+        log::trace!("Exiting handle_local_scan");
         reply
     }
 
-    fn handle_wipe(&mut self) -> Reply {
+    /// The first page of the keys this node holds from `start` up to `end`,
+    /// merged from the first page of each shard.
+    fn scan_local(&self, start: &[u8], end: Option<&[u8]>, limit: usize, include_values: bool) -> ScanPage {
+        let now: Instant = Instant::now();
+        let mut pages: Vec<ScanPage> = Vec::new();
+        for index in 0..self.store.num_shards() {
+            let shard: ShardGuard<S> = self.store.lock_index(index);
+            let mut page: ScanPage = ScanPage::with_max_size(self.max_page_size);
+            for key in shard.data_store.range(start, end) {
+                if page.entries.len() >= limit {
+                    break;
+                }
+                let stored: StoredValue = match shard.data_store.get(key) {
+                    Ok(Some(stored)) if !stored.is_expired(now) => stored,
+                    _ => continue,
+                };
+
+                let mut entry: KeyValue = create_entry(key.clone(), &stored);
+                if !include_values {
+                    entry.value.clear();
+                }
+                if !page.push(entry) {
+                    break;
+                }
+            }
+            pages.push(page);
+        }
+        merge_pages(pages, limit, self.max_page_size)
+    }
+
+    fn handle_wipe(&self) -> Reply {
         log::trace!("Entering handle_wipe");
         let mut reply: Reply = Reply::new();
-        self.data_store = HashMap::new();
-        self.data_store_mem_usage = 0;
+        let mut shards: Vec<ShardGuard<S>> = self.store.lock_all();
+        if self.log_change(Operation::Wipe, &[], None).is_err() || shards.iter_mut().any(|shard| self.wipe(shard).is_err()) {
+            reply.status = Status::InternalError as u32;
+            log::trace!("Exiting handle_wipe");
+            return reply;
+        }
         reply.status = Status::Success as u32;
         log::debug!("WIPE request Success");
         log::trace!("Exiting handle_wipe");
         reply
     }
 
+    fn wipe(&self, shard: &mut Shard<S>) -> Result<()> {
+        let mem_usage: u64 = shard.mem_usage();
+        shard.data_store.wipe().inspect_err(|e| log::error!("Failed to wipe the data store: {}", e))?;
+        shard.evictor.clear();
+        shard.evictor_mem_usage = 0;
+        shard.clear_tombstones();
+        self.store.update_mem_usage(mem_usage, shard.mem_usage());
+        Ok(())
+    }
+
     fn handle_ping(&self) -> Reply {
         log::trace!("Entering handle_ping");
         let mut reply: Reply = Reply::new();
@@ -299,16 +2437,207 @@ impl Node {
         reply
     }
 
-    fn handle_shutdown(&mut self) -> Reply {
+    /// Hands every key off and leaves the cluster, replying Success only
+    /// once the keys are acknowledged. A node that fails to leave stays up
+    /// and replies InternalError.
+    fn handle_shutdown(&self) -> Reply {
         log::trace!("Entering handle_shutdown");
         let mut reply: Reply = Reply::new();
-        self.should_keep_running = false;
-        reply.status = Status::Success as u32;
-        log::debug!("SHUTDOWN request Success");
+        match self.leave() {
+            Ok(()) => {
+                self.should_keep_running.store(false, atomic::Ordering::SeqCst);
+                reply.status = Status::Success as u32;
+                log::debug!("SHUTDOWN request Success");
+            },
+            Err(e) => {
+                log::error!("Failed to leave the cluster, staying up: {}", e);
+                reply.status = Status::InternalError as u32;
+            },
+        }
         log::trace!("Exiting handle_shutdown");
         reply
     }
 
+    fn handle_hard_shutdown(&self) -> Reply {
+        log::trace!("Entering handle_hard_shutdown");
+        let mut reply: Reply = Reply::new();
+        self.should_keep_running.store(false, atomic::Ordering::SeqCst);
+        reply.status = Status::Success as u32;
+        log::debug!("HARDSHUTDOWN request Success");
+        log::trace!("Exiting handle_hard_shutdown");
+        reply
+    }
+
+    /// Streams every key to the node that owns it once this one is gone,
+    /// then announces the departure to the rest of the cluster.
+    fn leave(&self) -> Result<()> {
+        log::trace!("Entering leave");
+
+        let mut old_preference_lists: HashMap<Vec<u8>, Vec<SocketAddr>> = HashMap::new();
+        for index in 0..self.store.num_shards() {
+            let shard: ShardGuard<S> = self.store.lock_index(index);
+            for key in shard.data_store.keys().chain(shard.tombstones().map(|(key, _)| key)) {
+                old_preference_lists.insert(key.clone(), self.get_preference_list(key));
+            }
+        }
+
+        self.ring.write().unwrap().remove_node(&self.addr);
+        if self.ring.read().unwrap().is_empty() {
+            log::info!("No peers to hand keys off to");
+            self.ring.write().unwrap().add_node(self.addr);
+            log::trace!("Exiting leave");
+            return Ok(());
+        }
+
+        // Each key only goes to the nodes that take over a copy of it, the
+        // other replicas already hold one
+        let mut batches: HashMap<SocketAddr, Vec<Vec<u8>>> = HashMap::new();
+        for (key, old_preference_list) in old_preference_lists {
+            for node in self.get_preference_list(&key) {
+                if !old_preference_list.contains(&node) {
+                    batches.entry(node).or_default().push(key.clone());
+                }
+            }
+        }
+
+        for (owner, keys) in batches {
+            if let Err(e) = self.push_keys(owner, keys) {
+                // Keep serving the keys that could not be handed off
+                self.ring.write().unwrap().add_node(self.addr);
+                log::trace!("Exiting leave");
+                return Err(e);
+            }
+        }
+
+        let (peers, members) = {
+            let mut membership = self.membership.lock().unwrap();
+            membership.leave();
+            (membership.live_members(), membership.to_proto())
+        };
+        for peer in peers.into_iter().filter(|peer| *peer != self.addr) {
+            let mut request: Request = Request::new();
+            request.operation = Operation::Gossip as u32;
+            request.members = members.clone();
+            if let Err(e) = self.proto_interface.send(request, peer) {
+                log::debug!("Failed to announce departure to peer {}: {}", peer, e);
+            }
+        }
+
+        log::info!("Server N{} left the cluster", self.id);
+        log::trace!("Exiting leave");
+        Ok(())
+    }
+
+    /// Hands `keys` off to `owner`, sending the keys it rejects again up to
+    /// `MAX_HANDOFF_ATTEMPTS` times in all. Fails if any is still rejected,
+    /// so that this node keeps serving them.
+    fn push_keys(&self, owner: SocketAddr, keys: Vec<Vec<u8>>) -> Result<()> {
+        log::trace!("Entering push_keys");
+
+        let num_keys: usize = keys.len();
+        let mut keys: Vec<Vec<u8>> = keys;
+        for attempt in 1..=MAX_HANDOFF_ATTEMPTS {
+            keys = self.push_batches(owner, keys)?;
+            if keys.is_empty() {
+                log::info!("Handed off {} keys to {}", num_keys, owner);
+                log::trace!("Exiting push_keys");
+                return Ok(());
+            }
+            log::warn!("{} of {} keys rejected by {} on attempt {}", keys.len(), num_keys, owner, attempt);
+        }
+
+        log::trace!("Exiting push_keys");
+        Err(Error::other(format!("{} of {} keys rejected by {}", keys.len(), num_keys, owner)))
+    }
+
+    /// Sends `keys` to `owner` in batches, returning the keys it rejected.
+    fn push_batches(&self, owner: SocketAddr, keys: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>> {
+        // A batch is sent before the next entry would take it past the
+        // batch size, so an entry larger than that goes on its own
+        let mut rejected: Vec<Vec<u8>> = Vec::new();
+        let mut batch: Vec<KeyValue> = Vec::new();
+        let mut batch_size: usize = 0;
+        for key in keys {
+            let entry_opt: Option<KeyValue> = create_handoff_entry(&self.store.lock(&key), &key, Instant::now())?;
+            let entry: KeyValue = match entry_opt {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            let entry_size: usize = entry.key.len() + entry.value.len();
+            if !batch.is_empty() && batch_size + entry_size > HANDOFF_BATCH_SIZE_BYTES {
+                rejected.extend(self.transfer_batch(owner, std::mem::take(&mut batch))?);
+                batch_size = 0;
+            }
+            batch.push(entry);
+            batch_size += entry_size;
+        }
+        if !batch.is_empty() {
+            rejected.extend(self.transfer_batch(owner, batch)?);
+        }
+        Ok(rejected)
+    }
+
+    /// Sends `batch` to `owner`, returning the keys it did not accept.
+    fn transfer_batch(&self, owner: SocketAddr, batch: Vec<KeyValue>) -> Result<Vec<Vec<u8>>> {
+        let keys: Vec<Vec<u8>> = batch.iter().map(|entry| entry.key.clone()).collect();
+        let mut request: Request = Request::new();
+        request.operation = Operation::Transfer as u32;
+        request.entries = batch;
+
+        let (reply_msg, _) = self.send_and_recv_peer(request, owner)?;
+        let reply: Reply = extract_reply(&reply_msg)?;
+        if reply.status == Status::Success as u32 {
+            return Ok(Vec::new());
+        }
+        if reply.entries.len() != keys.len() {
+            log::warn!("Transfer to {} failed with status {}", owner, reply.status);
+            return Ok(keys);
+        }
+
+        let rejected: Vec<Vec<u8>> = reply.entries
+            .into_iter()
+            .filter(|result| result.status != Some(Status::Success as u32))
+            .map(|result| result.key)
+            .collect();
+        log::warn!("Transfer to {} failed with status {}, {} of {} keys rejected", owner, reply.status, rejected.len(), keys.len());
+        Ok(rejected)
+    }
+
+    fn handle_get_memory_usage(&self) -> Reply {
+        log::trace!("Entering handle_get_memory_usage");
+        let mut reply: Reply = Reply::new();
+        reply.mem_usage = Some(self.get_data_store_mem_usage());
+        self.request_cache.sync();
+        reply.cache_mem_usage = Some(self.request_cache.weighted_size());
+        reply.max_mem = Some(self.max_mem);
+        match get_resident_memory() {
+            Ok(resident_mem) => reply.resident_mem = Some(resident_mem),
+            Err(e) => log::debug!("Failed to read resident memory: {}", e),
+        }
+        reply.status = Status::Success as u32;
+        log::debug!("GETMEMORYUSAGE request Success");
+        log::trace!("Exiting handle_get_memory_usage");
+        reply
+    }
+
+    fn handle_snapshot(&self) -> Reply {
+        log::trace!("Entering handle_snapshot");
+        let mut reply: Reply = Reply::new();
+        match self.snapshot() {
+            Ok(num_keys) => {
+                log::debug!("SNAPSHOT request Success ({} keys)", num_keys);
+                reply.status = Status::Success as u32;
+            },
+            Err(e) => {
+                log::info!("SNAPSHOT request unsuccessful: {}", e);
+                reply.status = Status::InternalError as u32;
+            },
+        }
+        log::trace!("Exiting handle_snapshot");
+        reply
+    }
+
     fn handle_getpid(&self) -> Reply {
         log::trace!("Entering handle_getpid");
         let mut reply: Reply = Reply::new();
@@ -319,6 +2648,154 @@ impl Node {
         reply
     }
 
+    fn handle_join(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_join");
+        let mut reply: Reply = Reply::new();
+
+        let changes: Vec<(SocketAddr, MemberState)> = self.membership.lock().unwrap().merge(&request.members, Instant::now());
+        self.update_ring(changes);
+        reply.members = self.membership.lock().unwrap().to_proto();
+        reply.status = Status::Success as u32;
+
+        log::debug!("JOIN request Success");
+        log::trace!("Exiting handle_join");
+        reply
+    }
+
+    fn handle_handoff(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_handoff");
+        let mut reply: Reply = Reply::new();
+
+        let joiner: SocketAddr = match request.node_addr.as_deref().map(str::parse::<SocketAddr>) {
+            Some(Ok(addr)) => addr,
+            _ => {
+                log::debug!("HANDOFF request InvalidValue, missing or invalid node address");
+                reply.status = Status::InvalidValue as u32;
+                log::trace!("Exiting handle_handoff");
+                return reply;
+            }
+        };
+
+        // The joiner may not have reached this node through gossip yet
+        let changes: Vec<(SocketAddr, MemberState)> = self.membership.lock().unwrap().merge(&request.members, Instant::now());
+        self.update_ring(changes);
+
+        self.remove_handed_off_keys(&request.entries);
+        reply.status = Status::Success as u32;
+        if request.limit == Some(0) {
+            log::debug!("HANDOFF request Success ({} keys acknowledged, no more wanted)", request.entries.len());
+            log::trace!("Exiting handle_handoff");
+            return reply;
+        }
+
+        // Pages are ordered by key so the joiner can resume after the last
+        // key it received
+        let now: Instant = Instant::now();
+        let mut keys: Vec<Vec<u8>> = Vec::new();
+        for index in 0..self.store.num_shards() {
+            let shard: ShardGuard<S> = self.store.lock_index(index);
+            keys.extend(shard.data_store
+                .keys()
+                .chain(shard.tombstones().map(|(key, _)| key))
+                .filter(|key| request.key.as_ref().is_none_or(|cursor| *key > cursor))
+                .filter(|key| self.get_preference_list(key).contains(&joiner))
+                .cloned());
+        }
+        keys.sort();
+        keys.dedup();
+
+        let mut batch_size: usize = 0;
+        for key in keys {
+            // A key that cannot be read stays here, as the joiner never acks it
+            let entry: KeyValue = match create_handoff_entry(&self.store.lock(&key), &key, now) {
+                Ok(Some(entry)) => entry,
+                Ok(None) | Err(_) => continue,
+            };
+            let entry_size: usize = entry.key.len() + entry.value.len();
+            if !reply.entries.is_empty() && batch_size + entry_size > HANDOFF_BATCH_SIZE_BYTES {
+                break;
+            }
+
+            reply.entries.push(entry);
+            batch_size += entry_size;
+        }
+
+        log::debug!("HANDOFF request Success ({} keys to {})", reply.entries.len(), joiner);
+        log::trace!("Exiting handle_handoff");
+        reply
+    }
+
+    /// Removes the keys a joiner acknowledged storing that this node no
+    /// longer holds a copy of, unless they were written again since.
+    fn remove_handed_off_keys(&self, acked: &[KeyValue]) {
+        for ack in acked {
+            if self.get_preference_list(&ack.key).contains(&self.addr) {
+                continue;
+            }
+
+            let mut shard: ShardGuard<S> = self.store.lock(&ack.key);
+            // A key that cannot be read is kept rather than removed unseen
+            let is_newer: bool = match shard.data_store.get(&ack.key) {
+                Ok(Some(stored)) => compare_versions(&stored.version, &ack.version) == Ordering::Greater,
+                Ok(None) | Err(_) => continue,
+            };
+            if is_newer {
+                continue;
+            }
+            if let Err(e) = self.remove_key_value(&mut shard, &ack.key) {
+                log::error!("Failed to remove handed off key: {}", e);
+                break;
+            }
+        }
+    }
+
+    /// Stores the keys handed off by a leaving node, replying with whether
+    /// each one was accepted so that the sender knows which it still holds
+    /// the only copy of.
+    fn handle_transfer(&self, request: Request) -> Reply {
+        log::trace!("Entering handle_transfer");
+        let mut reply: Reply = Reply::new();
+        reply.status = Status::Success as u32;
+
+        let num_keys: usize = request.entries.len();
+        for entry in request.entries {
+            let key: Vec<u8> = entry.key.clone();
+            let status: Status = match self.apply_key_value(&mut self.store.lock(&key), entry) {
+                Ok(true) => Status::Success,
+                Ok(false) => {
+                    reply.status = Status::OutOfMemory as u32;
+                    Status::OutOfMemory
+                },
+                Err(e) if e.kind() == ErrorKind::InvalidData => {
+                    log::warn!("Refused transferred key: {}", e);
+                    reply.status = Status::InvalidValue as u32;
+                    Status::InvalidValue
+                },
+                Err(e) => {
+                    log::error!("Failed to store transferred key: {}", e);
+                    reply.status = Status::InternalError as u32;
+                    Status::InternalError
+                },
+            };
+            reply.entries.push(create_batch_result(key, status));
+        }
+
+        if reply.status == Status::Success as u32 {
+            log::debug!("TRANSFER request Success ({} keys)", num_keys);
+        } else {
+            log::info!("TRANSFER request unsuccessful with status {}", reply.status);
+        }
+        log::trace!("Exiting handle_transfer");
+        reply
+    }
+
+    fn handle_gossip(&self, request: Request) {
+        log::trace!("Entering handle_gossip");
+        let changes: Vec<(SocketAddr, MemberState)> = self.membership.lock().unwrap().merge(&request.members, Instant::now());
+        self.update_ring(changes);
+        log::trace!("Exiting handle_gossip");
+    }
+
     fn handle_undefined_operation(&self, bad_error_code: u32) -> Reply {
         log::trace!("Entering handle_undefined_operation");
         let mut reply: Reply = Reply::new();
@@ -337,7 +2814,7 @@ impl Node {
         reply
     }
 
-    fn cache_reply(&mut self, msg_id: &Vec<u8>, reply: &Reply) -> Result<()> {
+    fn cache_reply(&self, msg_id: &[u8], reply: &Reply) -> Result<()> {
         log::trace!("Entering cache_reply");
         match reply.write_to_bytes() {
             Ok(reply_bytes) => {
@@ -357,7 +2834,373 @@ impl Node {
         Ok(())
     }
 
+    /// Stores `key` in `shard`, returning false if it does not fit in memory
+    /// and an error if the write could not be logged or stored. The bytes the
+    /// write adds are reserved first, so that writes to other shards meanwhile
+    /// cannot take them too.
+    fn insert_key_value(&self, shard: &mut Shard<S>, key: Vec<u8>, stored: StoredValue) -> Result<bool> {
+        let mem_usage: u64 = shard.get_entry_mem_usage(key.len(), stored.value.len());
+        let growth: u64 = mem_usage.saturating_sub(shard.get_stored_mem_usage(&key));
+        if !self.reserve_mem(growth) && !self.evict_for(shard, &key, growth)? {
+            return Ok(false);
+        }
+        if let Err(e) = self.log_change(Operation::Put, &key, Some(&stored)) {
+            self.store.update_mem_usage(growth, 0);
+            return Err(e);
+        }
+
+        let shard_mem_usage: u64 = shard.mem_usage();
+        let is_new_key: bool = !shard.data_store.contains_key(&key);
+        let expires_at: Option<Instant> = stored.expires_at;
+        if let Err(e) = shard.data_store.put(key.clone(), stored) {
+            log::error!("Failed to store key: {}", e);
+            self.store.update_mem_usage(growth, 0);
+            return Err(e);
+        }
+        shard.evictor.on_insert(&key, expires_at);
+        if is_new_key {
+            shard.evictor_mem_usage += shard.evictor.get_key_mem_usage(key.len());
+        }
+        // What was reserved already counts towards the total
+        self.store.update_mem_usage(shard_mem_usage + growth, shard.mem_usage());
+        Ok(true)
+    }
+
+    /// Reserves `bytes` if the data store and the reply cache together stay
+    /// within the memory limit, returning whether it did.
+    fn reserve_mem(&self, bytes: u64) -> bool {
+        let limit: u64 = self.max_mem.saturating_sub(self.request_cache.weighted_size());
+        self.store.try_reserve(bytes, limit)
+    }
+
+    /// Stores a copy of a key written elsewhere in `shard` unless it already
+    /// holds a newer version of it or the key was deleted since. An entry
+    /// with status KeyNotFound is the tombstone of a delete made elsewhere,
+    /// which is applied instead. Returns false only if memory ran out.
+    fn apply_key_value(&self, shard: &mut Shard<S>, entry: KeyValue) -> Result<bool> {
+        let version: Version = entry.version.unwrap_or_default();
+        self.clock.lock().unwrap().observe(&version)?;
+        if entry.status == Some(Status::KeyNotFound as u32) {
+            return match self.delete_at_version(shard, &entry.key, version) {
+                Err(e) if e.kind() == ErrorKind::OutOfMemory => Ok(false),
+                result => result.map(|_| true),
+            };
+        }
+        if shard.is_deleted_since(&entry.key, &version) {
+            log::debug!("Ignoring copy of deleted key (key size: {})", entry.key.len());
+            return Ok(true);
+        }
+
+        let now: Instant = Instant::now();
+        let stored: StoredValue = StoredValue {
+            value: entry.value,
+            version,
+            expires_at: entry.ttl_ms.map(|ttl_ms| now + Duration::from_millis(ttl_ms)),
+        };
+        if stored.is_expired(now) {
+            log::debug!("Ignoring expired copy of key (key size: {})", entry.key.len());
+            return Ok(true);
+        }
+
+        if let Some(existing) = shard.data_store.get(&entry.key)? {
+            if compare_versions(&existing.version, &stored.version) != Ordering::Less {
+                log::debug!("Ignoring stale copy of key (key size: {})", entry.key.len());
+                return Ok(true);
+            }
+        }
+        self.insert_key_value(shard, entry.key, stored)
+    }
+
+    /// Evicts keys until `bytes` are reserved for a write to `key`, returning
+    /// false if the eviction policy ran out of keys to evict. Keys are evicted
+    /// from `shard` first, then from the other shards not in use, which are
+    /// only tried so that no two writers wait on each other.
+    fn evict_for(&self, shard: &mut Shard<S>, key: &[u8], bytes: u64) -> Result<bool> {
+        if !shard.evictor.is_enabled() {
+            return Ok(false);
+        }
+
+        let mut num_evicted: usize = 0;
+        let mut is_reserved: bool = self.evict_from(shard, bytes, &mut num_evicted)?;
+        let index: usize = self.store.get_shard_index(key);
+        let mut other_indices = (0..self.store.num_shards()).filter(|other_index| *other_index != index);
+        while !is_reserved {
+            let other_index: usize = match other_indices.next() {
+                Some(other_index) => other_index,
+                None => break,
+            };
+            if let Some(mut other_shard) = self.store.try_lock_index(other_index) {
+                is_reserved = self.evict_from(&mut other_shard, bytes, &mut num_evicted)?;
+            }
+        }
+
+        log::debug!("Evicted {} keys, {} B in use", num_evicted, self.get_data_store_mem_usage());
+        Ok(is_reserved)
+    }
+
+    /// Evicts keys from `shard` until `bytes` are reserved, returning false
+    /// if it ran out of keys to evict first.
+    fn evict_from(&self, shard: &mut Shard<S>, bytes: u64, num_evicted: &mut usize) -> Result<bool> {
+        while !self.reserve_mem(bytes) {
+            let victim: Vec<u8> = match shard.evictor.next_victim() {
+                Some(victim) => victim,
+                None => return Ok(false),
+            };
+            self.remove_key_value(shard, &victim)?;
+            *num_evicted += 1;
+        }
+        Ok(true)
+    }
+
+    /// Removes `key` from `shard`, returning the value it held and an error
+    /// if the delete could not be logged or made.
+    fn remove_key_value(&self, shard: &mut Shard<S>, key: &[u8]) -> Result<Option<StoredValue>> {
+        if !shard.data_store.contains_key(key) {
+            shard.evictor.on_remove(key);
+            return Ok(None);
+        }
+        self.log_change(Operation::Delete, key, None)?;
+        self.drop_key_value(shard, key)
+    }
+
+    /// Deletes `key` from `shard` as of `version`, leaving a tombstone so that
+    /// older copies of the key arriving later are ignored. A value written
+    /// after the delete is kept. Returns the value removed, if any, and an
+    /// error of kind OutOfMemory if the tombstone does not fit in memory.
+    ///
+    /// The tombstone counts towards the memory limit like any other write,
+    /// less the value it replaces.
+    fn delete_at_version(&self, shard: &mut Shard<S>, key: &[u8], version: Version) -> Result<Option<StoredValue>> {
+        let is_newer_kept: bool = shard.data_store
+            .get(key)?
+            .is_some_and(|stored| compare_versions(&stored.version, &version) != Ordering::Less);
+        let freed: u64 = match is_newer_kept {
+            true => 0,
+            false => shard.get_stored_mem_usage(key),
+        };
+        let growth: u64 = shard.get_tombstone_growth(key).saturating_sub(freed);
+        if !self.reserve_mem(growth) && !self.evict_for(shard, key, growth)? {
+            return Err(Error::new(ErrorKind::OutOfMemory, "No room for the tombstone"));
+        }
+        if let Err(e) = self.append_to_log(&create_delete_record(key.to_vec(), version.clone())) {
+            self.store.update_mem_usage(growth, 0);
+            return Err(e);
+        }
+
+        let shard_mem_usage: u64 = shard.mem_usage();
+        shard.add_tombstone(key.to_vec(), version, Instant::now());
+        // What was reserved already counts towards the total
+        self.store.update_mem_usage(shard_mem_usage + growth, shard.mem_usage());
+        match is_newer_kept {
+            true => Ok(None),
+            false => self.drop_key_value(shard, key),
+        }
+    }
+
+    /// Removes `key` from `shard` once the delete is logged.
+    fn drop_key_value(&self, shard: &mut Shard<S>, key: &[u8]) -> Result<Option<StoredValue>> {
+        if !shard.data_store.contains_key(key) {
+            shard.evictor.on_remove(key);
+            return Ok(None);
+        }
+
+        let shard_mem_usage: u64 = shard.mem_usage();
+        let stored: Option<StoredValue> = shard.data_store
+            .delete(key)
+            .inspect_err(|e| log::error!("Failed to delete key: {}", e))?;
+        shard.evictor.on_remove(key);
+        shard.evictor_mem_usage -= shard.evictor.get_key_mem_usage(key.len());
+        self.store.update_mem_usage(shard_mem_usage, shard.mem_usage());
+        Ok(stored)
+    }
+
+    /// Memory used by the data store and by the evictors to track its keys
+    fn get_data_store_mem_usage(&self) -> u64 {
+        self.store.mem_usage()
+    }
+
     fn get_current_memory_usage(&self) -> u64 {
-        self.data_store_mem_usage + self.request_cache.weighted_size()
+        self.get_data_store_mem_usage() + self.request_cache.weighted_size()
+    }
+}
+
+fn create_entry(key: Vec<u8>, stored: &StoredValue) -> KeyValue {
+    let mut entry: KeyValue = KeyValue::new();
+    entry.key = key;
+    entry.value = stored.value.clone();
+    entry.version = MessageField::some(stored.version.clone());
+    entry.ttl_ms = stored.remaining_ttl_ms(Instant::now());
+    entry
+}
+
+/// The newest of the value and the tombstone `shard` holds for `key`, as
+/// handed to another node. A tombstone goes as an entry with status
+/// KeyNotFound and the version of the delete.
+fn create_handoff_entry<S: StorageEngine>(shard: &Shard<S>, key: &[u8], now: Instant) -> Result<Option<KeyValue>> {
+    let stored_opt: Option<StoredValue> = shard.data_store.get(key)?.filter(|stored| !stored.is_expired(now));
+    let deleted_opt: Option<&Version> = shard.get_deleted_version(key);
+    match (drop_if_deleted(stored_opt, deleted_opt), deleted_opt) {
+        (Some(stored), _) => Ok(Some(create_entry(key.to_vec(), &stored))),
+        (None, Some(deleted)) => {
+            let mut entry: KeyValue = KeyValue::new();
+            entry.key = key.to_vec();
+            entry.version = MessageField::some(deleted.clone());
+            entry.status = Some(Status::KeyNotFound as u32);
+            Ok(Some(entry))
+        },
+        (None, None) => Ok(None),
+    }
+}
+
+/// A `Delete` record for `key` that leaves a tombstone at `version`
+fn create_delete_record(key: Vec<u8>, version: Version) -> LogRecord {
+    let mut record: LogRecord = LogRecord::new();
+    record.operation = Operation::Delete as u32;
+    record.key = key;
+    record.version = MessageField::some(version);
+    record
+}
+
+/// Tells the node a key came from which version of it was stored
+fn create_ack(entry: &KeyValue) -> KeyValue {
+    let mut ack: KeyValue = KeyValue::new();
+    ack.key = entry.key.clone();
+    ack.version = entry.version.clone();
+    ack
+}
+
+/// The value of a counter, which is kept as a decimal string.
+fn parse_counter(value: &[u8]) -> Option<i64> {
+    std::str::from_utf8(value).ok()?.parse::<i64>().ok()
+}
+
+/// Runs `exchange` over an interface taken from `peer_interfaces`, or a new
+/// one bound to `ip` if every kept interface is in use.
+fn with_peer_interface<T: Transport, R>(
+    peer_interfaces: &Mutex<Vec<ProtoInterface<T>>>,
+    ip: IpAddr,
+    exchange: impl FnOnce(&ProtoInterface<T>) -> Result<R>
+) -> Result<R> {
+    let peer_interface_opt: Option<ProtoInterface<T>> = peer_interfaces.lock().unwrap().pop();
+    let peer_interface: ProtoInterface<T> = match peer_interface_opt {
+        Some(peer_interface) => peer_interface,
+        None => ProtoInterface::bind(SocketAddr::new(ip, 0))?,
+    };
+    let result: Result<R> = exchange(&peer_interface);
+    peer_interfaces.lock().unwrap().push(peer_interface);
+    result
+}
+
+/// Sends a write to `replica`, returning whether it acknowledged it
+fn replicate_to<T: Transport>(peer_interfaces: &Mutex<Vec<ProtoInterface<T>>>, ip: IpAddr, request: Request, replica: SocketAddr) -> bool {
+    let result: Result<Reply> = with_peer_interface(peer_interfaces, ip, |peer_interface| peer_interface.send_and_recv(request, replica))
+        .and_then(|(reply_msg, _)| extract_reply(&reply_msg));
+    match result {
+        Ok(reply) if reply.status == Status::Success as u32 => true,
+        Ok(reply) => {
+            log::warn!("Replica {} rejected write with status {}", replica, reply.status);
+            false
+        },
+        Err(e) => {
+            log::warn!("Failed to replicate to {}: {}", replica, e);
+            false
+        },
+    }
+}
+
+fn scan_peer<T: Transport>(peer_interfaces: &Mutex<Vec<ProtoInterface<T>>>, ip: IpAddr, request: Request, peer: SocketAddr) -> Option<ScanPage> {
+    let result: Result<Reply> = with_peer_interface(peer_interfaces, ip, |peer_interface| {
+        peer_interface.send_and_recv_once(request, peer, SCAN_PEER_TIMEOUT)
+    })
+    .and_then(|(reply_msg, _)| extract_reply(&reply_msg));
+    match result {
+        Ok(reply) if reply.status == Status::Success as u32 => Some(ScanPage::new(reply.entries, reply.next_key)),
+        Ok(reply) => {
+            log::warn!("Peer {} failed scan with status {}", peer, reply.status);
+            None
+        },
+        Err(e) => {
+            log::warn!("Failed to scan peer {}: {}", peer, e);
+            None
+        },
+    }
+}
+
+/// The reply to a read of `key` that `num_replies` copies answered, the
+/// newest of which was `stored_opt`. `name` is the operation, for logging.
+fn create_read_reply(
+    num_replies: usize,
+    required_replies: usize,
+    stored_opt: Option<StoredValue>,
+    key: &[u8],
+    name: &str
+) -> Reply {
+    let mut reply: Reply = Reply::new();
+    if num_replies < required_replies {
+        log::info!("{} request QuorumNotReached ({} of {} replies)", name, num_replies, required_replies);
+        reply.status = Status::QuorumNotReached as u32;
+        return reply;
+    }
+
+    match stored_opt {
+        Some(stored) => {
+            log::debug!("{} request Success (key size: {}, value size: {})", name, key.len(), stored.value.len());
+            reply.status = Status::Success as u32;
+            reply.ttl_ms = stored.remaining_ttl_ms(Instant::now());
+            reply.value = Some(stored.value);
+            reply.version = MessageField::some(stored.version);
+        },
+        None => {
+            log::debug!("{} request KeyNotFound", name);
+            reply.status = Status::KeyNotFound as u32;
+        },
+    }
+    reply
+}
+
+fn create_batch_result(key: Vec<u8>, status: Status) -> KeyValue {
+    let mut result: KeyValue = KeyValue::new();
+    result.key = key;
+    result.status = Some(status as u32);
+    result
+}
+
+/// Drops the values that would take a batch reply past `max_size` bytes,
+/// marking their keys InvalidValueSize so that they can be asked for again in
+/// a smaller batch.
+fn fit_batch_reply(entries: &mut [KeyValue], max_size: usize) {
+    let mut size: usize = 0;
+    for entry in entries.iter_mut() {
+        if size + get_entry_size(entry) > max_size && !entry.value.is_empty() {
+            entry.value.clear();
+            entry.status = Some(Status::InvalidValueSize as u32);
+        }
+        size += get_entry_size(entry);
+    }
+}
+
+fn newer_version(a: Option<Version>, b: Option<Version>) -> Option<Version> {
+    match (a, b) {
+        (Some(a), Some(b)) => match compare_versions(&a, &b) {
+            Ordering::Less => Some(b),
+            _ => Some(a),
+        },
+        (a, b) => a.or(b),
+    }
+}
+
+/// `stored_opt`, unless the key was deleted at `deleted_opt` after it was
+/// written
+fn drop_if_deleted(stored_opt: Option<StoredValue>, deleted_opt: Option<&Version>) -> Option<StoredValue> {
+    stored_opt.filter(|stored| deleted_opt.is_none_or(|deleted| compare_versions(deleted, &stored.version) == Ordering::Less))
+}
+
+fn newer_value(a: Option<StoredValue>, b: Option<StoredValue>) -> Option<StoredValue> {
+    match (a, b) {
+        (Some(a), Some(b)) => match compare_versions(&a.version, &b.version) {
+            Ordering::Less => Some(b),
+            _ => Some(a),
+        },
+        (a, b) => a.or(b),
     }
 }
@@ -0,0 +1,255 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Error, ErrorKind};
+use std::mem::size_of;
+use std::str::FromStr;
+use std::time::Instant;
+use rand::Rng;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    NoEviction,
+    Lru,
+    Lfu,
+    Random,
+    Ttl,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "noeviction" => Ok(EvictionPolicy::NoEviction),
+            "lru" => Ok(EvictionPolicy::Lru),
+            "lfu" => Ok(EvictionPolicy::Lfu),
+            "random" => Ok(EvictionPolicy::Random),
+            "ttl" => Ok(EvictionPolicy::Ttl),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("Invalid eviction policy: {}", value))),
+        }
+    }
+}
+
+/// Position of a key in the eviction order, lowest goes first.
+type Rank = (u64, u64);
+
+struct KeyInfo {
+    rank: Rank,
+    frequency: u64,
+    expires_at: Option<Instant>,
+    index: usize,
+}
+
+/// Keeps track of how keys are used so that the next one to evict can be
+/// picked without scanning the data store.
+///
+/// LRU and LFU order keys by last access and access count. TTL evicts the
+/// keys closest to expiring first, then the least recently used keys that
+/// never expire. Random picks any key.
+pub struct Evictor {
+    policy: EvictionPolicy,
+    tick: u64,
+    start: Instant,
+    keys: HashMap<Vec<u8>, KeyInfo>,
+    order: BTreeSet<(Rank, Vec<u8>)>,
+    random_keys: Vec<Vec<u8>>,
+}
+
+impl Evictor {
+    pub fn new(policy: EvictionPolicy) -> Self {
+        Evictor {
+            policy,
+            tick: 0,
+            start: Instant::now(),
+            keys: HashMap::new(),
+            order: BTreeSet::new(),
+            random_keys: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.policy != EvictionPolicy::NoEviction
+    }
+
+    /// Estimated memory used to track a key of `key_len` bytes, which is
+    /// copied into each of the structures ordering the keys.
+    pub fn get_key_mem_usage(&self, key_len: usize) -> u64 {
+        if !self.is_enabled() {
+            return 0;
+        }
+
+        let table_entries_size: usize = size_of::<(Vec<u8>, KeyInfo)>()
+            + size_of::<(Rank, Vec<u8>)>()
+            + size_of::<Vec<u8>>();
+        (3 * key_len + table_entries_size) as u64
+    }
+
+    pub fn on_insert(&mut self, key: &[u8], expires_at: Option<Instant>) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        self.tick += 1;
+        match self.keys.get_mut(key) {
+            Some(info) => {
+                // Overwriting counts as a use of the key
+                info.frequency += 1;
+                info.expires_at = expires_at;
+            },
+            None => {
+                let index: usize = self.random_keys.len();
+                self.random_keys.push(key.to_vec());
+                self.keys.insert(key.to_vec(), KeyInfo { rank: (0, 0), frequency: 1, expires_at, index });
+            },
+        }
+        self.update_rank(key);
+    }
+
+    pub fn on_access(&mut self, key: &[u8]) {
+        if !self.is_enabled() || !self.keys.contains_key(key) {
+            return;
+        }
+
+        self.tick += 1;
+        if let Some(info) = self.keys.get_mut(key) {
+            info.frequency += 1;
+        }
+        self.update_rank(key);
+    }
+
+    pub fn on_remove(&mut self, key: &[u8]) {
+        let info: KeyInfo = match self.keys.remove(key) {
+            Some(info) => info,
+            None => return,
+        };
+
+        self.order.remove(&(info.rank, key.to_vec()));
+        self.random_keys.swap_remove(info.index);
+        if let Some(moved_key) = self.random_keys.get(info.index) {
+            if let Some(moved_info) = self.keys.get_mut(moved_key) {
+                moved_info.index = info.index;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.order.clear();
+        self.random_keys.clear();
+    }
+
+    /// The key that should be evicted next, if the policy allows evicting.
+    pub fn next_victim(&self) -> Option<Vec<u8>> {
+        match self.policy {
+            EvictionPolicy::NoEviction => None,
+            EvictionPolicy::Random if self.random_keys.is_empty() => None,
+            EvictionPolicy::Random => {
+                let index: usize = rand::thread_rng().gen_range(0..self.random_keys.len());
+                Some(self.random_keys[index].clone())
+            },
+            _ => self.order.first().map(|(_, key)| key.clone()),
+        }
+    }
+
+    fn update_rank(&mut self, key: &[u8]) {
+        let (tick, start, policy) = (self.tick, self.start, self.policy);
+        let info: &mut KeyInfo = match self.keys.get_mut(key) {
+            Some(info) => info,
+            None => return,
+        };
+
+        let rank: Rank = match policy {
+            EvictionPolicy::Lfu => (info.frequency, tick),
+            EvictionPolicy::Ttl => match info.expires_at {
+                Some(expires_at) => (expires_at.saturating_duration_since(start).as_millis() as u64, tick),
+                None => (u64::MAX, tick),
+            },
+            _ => (tick, 0),
+        };
+
+        let old_rank: Rank = std::mem::replace(&mut info.rank, rank);
+        self.order.remove(&(old_rank, key.to_vec()));
+        self.order.insert((rank, key.to_vec()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn create_evictor(policy: EvictionPolicy, keys: &[&[u8]]) -> Evictor {
+        let mut evictor: Evictor = Evictor::new(policy);
+        for key in keys {
+            evictor.on_insert(key, None);
+        }
+        evictor
+    }
+
+    #[test]
+    fn test_parse_policies() {
+        assert_eq!("noeviction".parse::<EvictionPolicy>().unwrap(), EvictionPolicy::NoEviction);
+        assert_eq!("lru".parse::<EvictionPolicy>().unwrap(), EvictionPolicy::Lru);
+        assert_eq!("lfu".parse::<EvictionPolicy>().unwrap(), EvictionPolicy::Lfu);
+        assert_eq!("random".parse::<EvictionPolicy>().unwrap(), EvictionPolicy::Random);
+        assert_eq!("ttl".parse::<EvictionPolicy>().unwrap(), EvictionPolicy::Ttl);
+        assert!("fifo".parse::<EvictionPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_noeviction_has_no_victim() {
+        let evictor: Evictor = create_evictor(EvictionPolicy::NoEviction, &[b"a", b"b"]);
+        assert_eq!(evictor.next_victim(), None);
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let mut evictor: Evictor = create_evictor(EvictionPolicy::Lru, &[b"a", b"b", b"c"]);
+        assert_eq!(evictor.next_victim(), Some(b"a".to_vec()));
+
+        evictor.on_access(b"a");
+        assert_eq!(evictor.next_victim(), Some(b"b".to_vec()));
+
+        evictor.on_remove(b"b");
+        assert_eq!(evictor.next_victim(), Some(b"c".to_vec()));
+    }
+
+    #[test]
+    fn test_lfu_evicts_least_frequently_used() {
+        let mut evictor: Evictor = create_evictor(EvictionPolicy::Lfu, &[b"a", b"b", b"c"]);
+        evictor.on_access(b"a");
+        evictor.on_access(b"a");
+        evictor.on_access(b"c");
+        assert_eq!(evictor.next_victim(), Some(b"b".to_vec()));
+
+        evictor.on_access(b"b");
+        evictor.on_access(b"b");
+        assert_eq!(evictor.next_victim(), Some(b"c".to_vec()));
+    }
+
+    #[test]
+    fn test_ttl_evicts_soonest_expiring_first() {
+        let now: Instant = Instant::now();
+        let mut evictor: Evictor = Evictor::new(EvictionPolicy::Ttl);
+        evictor.on_insert(b"forever", None);
+        evictor.on_insert(b"late", Some(now + Duration::from_secs(60)));
+        evictor.on_insert(b"soon", Some(now + Duration::from_secs(1)));
+        assert_eq!(evictor.next_victim(), Some(b"soon".to_vec()));
+
+        evictor.on_remove(b"soon");
+        evictor.on_remove(b"late");
+        assert_eq!(evictor.next_victim(), Some(b"forever".to_vec()));
+    }
+
+    #[test]
+    fn test_random_evicts_tracked_keys() {
+        let mut evictor: Evictor = create_evictor(EvictionPolicy::Random, &[b"a", b"b", b"c"]);
+        evictor.on_remove(b"a");
+        for _ in 0..20 {
+            let victim: Vec<u8> = evictor.next_victim().unwrap();
+            assert!(victim == b"b".to_vec() || victim == b"c".to_vec());
+        }
+
+        evictor.clear();
+        assert_eq!(evictor.next_victim(), None);
+    }
+}
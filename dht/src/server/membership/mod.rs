@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use rand::seq::IteratorRandom;
+
+use crate::comm::protogen::api::Member;
+
+const SUSPECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberState {
+    Alive,
+    Suspect,
+    Dead,
+    Left,
+}
+
+struct MemberInfo {
+    heartbeat: u64,
+    state: MemberState,
+    last_updated: Instant,
+}
+
+/// Heartbeat-based view of the cluster built from gossip.
+///
+/// A peer is suspected once its heartbeat stops increasing for
+/// `SUSPECT_TIMEOUT` and declared dead after `DEAD_TIMEOUT`. Dead peers are
+/// left out of outgoing gossip so that they eventually vanish everywhere,
+/// while peers that announced their departure are gossiped as having left.
+pub struct Membership {
+    addr: SocketAddr,
+    heartbeat: u64,
+    has_left: bool,
+    members: HashMap<SocketAddr, MemberInfo>,
+}
+
+impl Membership {
+    pub fn new(addr: SocketAddr, peers: &[SocketAddr]) -> Self {
+        let now: Instant = Instant::now();
+        let members: HashMap<SocketAddr, MemberInfo> = peers
+            .iter()
+            .filter(|peer| **peer != addr)
+            .map(|peer| (*peer, MemberInfo { heartbeat: 0, state: MemberState::Alive, last_updated: now }))
+            .collect();
+
+        // Starting from the wall clock keeps the heartbeat of a restarted
+        // node above the one its peers last saw
+        let heartbeat: u64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        Membership { addr, heartbeat, has_left: false, members }
+    }
+
+    pub fn tick(&mut self) {
+        self.heartbeat += 1;
+    }
+
+    pub fn leave(&mut self) {
+        self.has_left = true;
+        self.heartbeat += 1;
+    }
+
+    /// Merges a gossiped member list, returning the peers that joined or
+    /// left the ring along with their new state.
+    pub fn merge(&mut self, gossip: &[Member], now: Instant) -> Vec<(SocketAddr, MemberState)> {
+        let mut changes: Vec<(SocketAddr, MemberState)> = Vec::new();
+
+        for member in gossip {
+            let addr: SocketAddr = match member.addr.parse() {
+                Ok(addr) => addr,
+                Err(e) => {
+                    log::warn!("Ignoring gossip for invalid address {}: {}", member.addr, e);
+                    continue;
+                }
+            };
+            if addr == self.addr {
+                continue;
+            }
+
+            let state: MemberState = if member.left { MemberState::Left } else { MemberState::Alive };
+            match self.members.get_mut(&addr) {
+                Some(info) if member.heartbeat > info.heartbeat => {
+                    if is_on_ring(info.state) != is_on_ring(state) {
+                        changes.push((addr, state));
+                    }
+                    info.heartbeat = member.heartbeat;
+                    info.state = state;
+                    info.last_updated = now;
+                },
+                Some(_) => (),
+                None => {
+                    self.members.insert(addr, MemberInfo { heartbeat: member.heartbeat, state, last_updated: now });
+                    if state == MemberState::Alive {
+                        changes.push((addr, state));
+                    }
+                },
+            }
+        }
+
+        for (addr, state) in changes.iter() {
+            match state {
+                MemberState::Left => log::info!("Peer {} left the cluster", addr),
+                _ => log::info!("Peer {} is alive", addr),
+            }
+        }
+
+        changes
+    }
+
+    /// Advances the state of peers whose heartbeat has not increased in time,
+    /// returning the peers that were just declared dead.
+    pub fn detect_failures(&mut self, now: Instant) -> Vec<(SocketAddr, MemberState)> {
+        let mut dead: Vec<(SocketAddr, MemberState)> = Vec::new();
+
+        for (addr, info) in self.members.iter_mut() {
+            let silence: Duration = now.saturating_duration_since(info.last_updated);
+            match info.state {
+                MemberState::Alive if silence >= SUSPECT_TIMEOUT => {
+                    log::info!("Peer {} is suspected to have failed", addr);
+                    info.state = MemberState::Suspect;
+                },
+                MemberState::Suspect if silence >= DEAD_TIMEOUT => {
+                    log::warn!("Peer {} is dead", addr);
+                    info.state = MemberState::Dead;
+                    dead.push((*addr, MemberState::Dead));
+                },
+                _ => (),
+            }
+        }
+
+        dead
+    }
+
+    pub fn get_state(&self, addr: &SocketAddr) -> Option<MemberState> {
+        if *addr == self.addr {
+            return Some(MemberState::Alive);
+        }
+        self.members.get(addr).map(|info| info.state)
+    }
+
+    /// Addresses of every member that still belongs on the ring, including
+    /// this node.
+    pub fn live_members(&self) -> Vec<SocketAddr> {
+        let mut live: Vec<SocketAddr> = self.live_peers().collect();
+        live.push(self.addr);
+        live
+    }
+
+    pub fn random_peers(&self, count: usize) -> Vec<SocketAddr> {
+        self.live_peers().choose_multiple(&mut rand::thread_rng(), count)
+    }
+
+    pub fn to_proto(&self) -> Vec<Member> {
+        let mut gossip: Vec<Member> = vec![create_member(self.addr, self.heartbeat, self.has_left)];
+        for (addr, info) in self.members.iter() {
+            if info.state != MemberState::Dead {
+                gossip.push(create_member(*addr, info.heartbeat, info.state == MemberState::Left));
+            }
+        }
+        gossip
+    }
+
+    fn live_peers(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.members
+            .iter()
+            .filter(|(_, info)| is_on_ring(info.state))
+            .map(|(addr, _)| *addr)
+    }
+}
+
+fn is_on_ring(state: MemberState) -> bool {
+    state == MemberState::Alive || state == MemberState::Suspect
+}
+
+fn create_member(addr: SocketAddr, heartbeat: u64, left: bool) -> Member {
+    let mut member: Member = Member::new();
+    member.addr = addr.to_string();
+    member.heartbeat = heartbeat;
+    member.left = left;
+    member
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn create_addr(port: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port)
+    }
+
+    #[test]
+    fn test_new_excludes_self() {
+        let membership: Membership = Membership::new(create_addr(8080), &[create_addr(8080), create_addr(8081)]);
+        let mut live: Vec<SocketAddr> = membership.live_members();
+        live.sort();
+        assert_eq!(live, vec![create_addr(8080), create_addr(8081)]);
+    }
+
+    #[test]
+    fn test_silent_peer_is_suspected_then_dead() {
+        let peer: SocketAddr = create_addr(8081);
+        let mut membership: Membership = Membership::new(create_addr(8080), &[peer]);
+        let start: Instant = Instant::now();
+
+        assert!(membership.detect_failures(start + SUSPECT_TIMEOUT).is_empty());
+        assert_eq!(membership.get_state(&peer), Some(MemberState::Suspect));
+
+        assert_eq!(membership.detect_failures(start + DEAD_TIMEOUT), vec![(peer, MemberState::Dead)]);
+        assert_eq!(membership.get_state(&peer), Some(MemberState::Dead));
+        assert!(!membership.live_members().contains(&peer));
+        assert!(membership.to_proto().iter().all(|member| member.addr != peer.to_string()));
+    }
+
+    #[test]
+    fn test_higher_heartbeat_revives_peer() {
+        let peer: SocketAddr = create_addr(8081);
+        let mut membership: Membership = Membership::new(create_addr(8080), &[peer]);
+        let start: Instant = Instant::now();
+        membership.detect_failures(start + SUSPECT_TIMEOUT);
+        membership.detect_failures(start + DEAD_TIMEOUT);
+
+        let stale: Vec<Member> = vec![create_member(peer, 0, false)];
+        assert!(membership.merge(&stale, start + DEAD_TIMEOUT).is_empty());
+        assert_eq!(membership.get_state(&peer), Some(MemberState::Dead));
+
+        let fresh: Vec<Member> = vec![create_member(peer, 1, false)];
+        assert_eq!(membership.merge(&fresh, start + DEAD_TIMEOUT), vec![(peer, MemberState::Alive)]);
+        assert_eq!(membership.get_state(&peer), Some(MemberState::Alive));
+    }
+
+    #[test]
+    fn test_merge_discovers_new_peers() {
+        let mut membership: Membership = Membership::new(create_addr(8080), &[create_addr(8081)]);
+        let gossip: Vec<Member> = vec![
+            create_member(create_addr(8080), 7, false),
+            create_member(create_addr(8081), 1, false),
+            create_member(create_addr(8082), 3, false),
+        ];
+
+        assert_eq!(membership.merge(&gossip, Instant::now()), vec![(create_addr(8082), MemberState::Alive)]);
+        assert_eq!(membership.get_state(&create_addr(8082)), Some(MemberState::Alive));
+        assert_eq!(membership.live_members().len(), 3);
+    }
+
+    #[test]
+    fn test_gossip_includes_own_heartbeat() {
+        let mut membership: Membership = Membership::new(create_addr(8080), &[]);
+        let start_heartbeat: u64 = membership.to_proto()[0].heartbeat;
+        membership.tick();
+        membership.tick();
+
+        let gossip: Vec<Member> = membership.to_proto();
+        assert_eq!(gossip.len(), 1);
+        assert_eq!(gossip[0].addr, create_addr(8080).to_string());
+        assert_eq!(gossip[0].heartbeat, start_heartbeat + 2);
+        assert!(!gossip[0].left);
+    }
+
+    #[test]
+    fn test_departed_peer_leaves_ring() {
+        let peer: SocketAddr = create_addr(8081);
+        let mut membership: Membership = Membership::new(create_addr(8080), &[peer]);
+
+        let departure: Vec<Member> = vec![create_member(peer, 1, true)];
+        assert_eq!(membership.merge(&departure, Instant::now()), vec![(peer, MemberState::Left)]);
+        assert_eq!(membership.get_state(&peer), Some(MemberState::Left));
+        assert!(!membership.live_members().contains(&peer));
+        assert!(membership.random_peers(1).is_empty());
+
+        // Other peers still gossiping the old heartbeat do not revive it
+        let stale: Vec<Member> = vec![create_member(peer, 1, false)];
+        assert!(membership.merge(&stale, Instant::now()).is_empty());
+        assert!(membership.to_proto().iter().any(|member| member.addr == peer.to_string() && member.left));
+    }
+
+    #[test]
+    fn test_leave_is_gossiped() {
+        let mut membership: Membership = Membership::new(create_addr(8080), &[create_addr(8081)]);
+        let start_heartbeat: u64 = membership.to_proto()[0].heartbeat;
+        membership.leave();
+
+        let gossip: Vec<Member> = membership.to_proto();
+        assert!(gossip[0].left);
+        assert_eq!(gossip[0].heartbeat, start_heartbeat + 1);
+    }
+}
@@ -1 +1,10 @@
+pub mod clock;
 pub mod data;
+pub mod eviction;
+pub mod membership;
+pub mod pool;
+pub mod ring;
+pub mod scan;
+pub mod shard;
+pub mod storage;
+pub mod wal;
@@ -0,0 +1,85 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+/// Work handed to the pool
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed set of threads taking jobs in turn, so that exchanges with other
+/// nodes never take more threads than that however many are asked for at
+/// once. Jobs past those waiting in the queue wait for room before being
+/// handed over.
+pub struct ThreadPool {
+    sender: Option<SyncSender<Job>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub fn new(num_threads: usize, max_queued: usize) -> Self {
+        let (sender, receiver): (SyncSender<Job>, Receiver<Job>) = mpsc::sync_channel(max_queued);
+        let receiver: Arc<Mutex<Receiver<Job>>> = Arc::new(Mutex::new(receiver));
+        let threads: Vec<JoinHandle<()>> = (0..num_threads.max(1))
+            .map(|_| {
+                let receiver: Arc<Mutex<Receiver<Job>>> = receiver.clone();
+                thread::spawn(move || loop {
+                    let next = receiver.lock().unwrap().recv();
+                    match next {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+            })
+            .collect();
+        ThreadPool { sender: Some(sender), threads }
+    }
+
+    /// Runs `job` on the next thread free, waiting for room in the queue if
+    /// it is full.
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        if let Some(sender) = self.sender.as_ref() {
+            if sender.send(Box::new(job)).is_err() {
+                log::error!("Thread pool stopped taking jobs");
+            }
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Lets the threads finish what is queued and stop
+        drop(self.sender.take());
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_thread_pool_bounds_concurrency() {
+        let pool: ThreadPool = ThreadPool::new(2, 4);
+        let running: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        let max_running: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..16 {
+            let (running, max_running, sender) = (running.clone(), max_running.clone(), sender.clone());
+            pool.execute(move || {
+                let num_running: usize = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(num_running, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(5));
+                running.fetch_sub(1, Ordering::SeqCst);
+                sender.send(()).unwrap();
+            });
+        }
+        drop(sender);
+
+        assert_eq!(receiver.iter().count(), 16);
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::net::SocketAddr;
+use crc::{Crc, CRC_64_XZ};
+
+const RING_HASH: Crc<u64> = Crc::<u64>::new(&CRC_64_XZ);
+
+/// Consistent-hash ring mapping keys to the nodes that own them.
+///
+/// Every node is placed on the ring `virtual_nodes` times so that keys are
+/// spread evenly and only the keys of a joining or leaving node move.
+pub struct HashRing {
+    ring: BTreeMap<u64, SocketAddr>,
+    nodes: BTreeSet<SocketAddr>,
+    virtual_nodes: u32,
+}
+
+impl HashRing {
+    pub fn new(virtual_nodes: u32) -> Self {
+        HashRing {
+            ring: BTreeMap::new(),
+            nodes: BTreeSet::new(),
+            virtual_nodes: virtual_nodes.max(1),
+        }
+    }
+
+    pub fn add_node(&mut self, node: SocketAddr) {
+        if !self.nodes.insert(node) {
+            return;
+        }
+
+        for i in 0..self.virtual_nodes {
+            self.ring.insert(hash_virtual_node(&node, i), node);
+        }
+    }
+
+    pub fn remove_node(&mut self, node: &SocketAddr) {
+        if !self.nodes.remove(node) {
+            return;
+        }
+
+        for i in 0..self.virtual_nodes {
+            let position: u64 = hash_virtual_node(node, i);
+            if self.ring.get(&position) == Some(node) {
+                self.ring.remove(&position);
+            }
+        }
+    }
+
+    pub fn get_node(&self, key: &[u8]) -> Option<SocketAddr> {
+        self.walk(key).next()
+    }
+
+    /// Returns up to `count` distinct nodes responsible for `key`, starting
+    /// with its owner and followed by its successors on the ring.
+    pub fn get_nodes(&self, key: &[u8], count: usize) -> Vec<SocketAddr> {
+        self.get_nodes_from(RING_HASH.checksum(key), count)
+    }
+
+    /// Whether `nodes` together hold a copy of every key, that is whether
+    /// each key has one of them among its first `count` nodes.
+    pub fn is_covered_by(&self, nodes: &HashSet<SocketAddr>, count: usize) -> bool {
+        // Keys between two positions all go to the nodes walked from the
+        // later one, so checking every position checks every key
+        self.ring
+            .keys()
+            .all(|position| self.get_nodes_from(*position, count).iter().any(|node| nodes.contains(node)))
+    }
+
+    fn get_nodes_from(&self, position: u64, count: usize) -> Vec<SocketAddr> {
+        let mut nodes: Vec<SocketAddr> = Vec::new();
+        for node in self.walk_from(position) {
+            if nodes.len() >= count.min(self.nodes.len()) {
+                break;
+            }
+            if !nodes.contains(&node) {
+                nodes.push(node);
+            }
+        }
+        nodes
+    }
+
+    fn walk(&self, key: &[u8]) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.walk_from(RING_HASH.checksum(key))
+    }
+
+    fn walk_from(&self, position: u64) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.ring
+            .range(position..)
+            .chain(self.ring.range(..position))
+            .map(|(_, node)| *node)
+    }
+
+    pub fn contains(&self, node: &SocketAddr) -> bool {
+        self.nodes.contains(node)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &SocketAddr> {
+        self.nodes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+fn hash_virtual_node(node: &SocketAddr, index: u32) -> u64 {
+    RING_HASH.checksum(format!("{}#{}", node, index).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    const VIRTUAL_NODES: u32 = 64;
+
+    fn create_nodes(count: u16) -> Vec<SocketAddr> {
+        (0..count)
+            .map(|i| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080 + i))
+            .collect()
+    }
+
+    fn create_ring(nodes: &[SocketAddr]) -> HashRing {
+        let mut ring: HashRing = HashRing::new(VIRTUAL_NODES);
+        for node in nodes {
+            ring.add_node(*node);
+        }
+        ring
+    }
+
+    fn create_keys(count: u32) -> Vec<Vec<u8>> {
+        (0..count).map(|i| format!("key-{}", i).into_bytes()).collect()
+    }
+
+    #[test]
+    fn test_empty_ring_has_no_owner() {
+        let ring: HashRing = HashRing::new(VIRTUAL_NODES);
+        assert!(ring.is_empty());
+        assert_eq!(ring.get_node(b"key"), None);
+    }
+
+    #[test]
+    fn test_single_node_owns_every_key() {
+        let nodes: Vec<SocketAddr> = create_nodes(1);
+        let ring: HashRing = create_ring(&nodes);
+        for key in create_keys(100) {
+            assert_eq!(ring.get_node(&key), Some(nodes[0]));
+        }
+    }
+
+    #[test]
+    fn test_every_node_owns_some_keys() {
+        let nodes: Vec<SocketAddr> = create_nodes(4);
+        let ring: HashRing = create_ring(&nodes);
+
+        let mut key_counts: HashMap<SocketAddr, u32> = HashMap::new();
+        for key in create_keys(1000) {
+            *key_counts.entry(ring.get_node(&key).unwrap()).or_insert(0) += 1;
+        }
+
+        for node in &nodes {
+            assert!(key_counts.get(node).copied().unwrap_or(0) > 0);
+        }
+    }
+
+    #[test]
+    fn test_get_nodes_starts_with_owner() {
+        let nodes: Vec<SocketAddr> = create_nodes(4);
+        let ring: HashRing = create_ring(&nodes);
+
+        for key in create_keys(100) {
+            let preference_list: Vec<SocketAddr> = ring.get_nodes(&key, 3);
+            assert_eq!(preference_list.len(), 3);
+            assert_eq!(preference_list[0], ring.get_node(&key).unwrap());
+
+            let mut distinct: Vec<SocketAddr> = preference_list.clone();
+            distinct.sort();
+            distinct.dedup();
+            assert_eq!(distinct.len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_get_nodes_is_capped_by_ring_size() {
+        let nodes: Vec<SocketAddr> = create_nodes(2);
+        let ring: HashRing = create_ring(&nodes);
+        assert_eq!(ring.get_nodes(b"key", 5).len(), 2);
+        assert!(HashRing::new(VIRTUAL_NODES).get_nodes(b"key", 5).is_empty());
+    }
+
+    #[test]
+    fn test_is_covered_by_needs_a_copy_of_every_key() {
+        let nodes: Vec<SocketAddr> = create_nodes(4);
+        let ring: HashRing = create_ring(&nodes);
+
+        let all_but_one: HashSet<SocketAddr> = nodes[1..].iter().copied().collect();
+        assert!(!ring.is_covered_by(&all_but_one, 1));
+        assert!(ring.is_covered_by(&all_but_one, 2));
+
+        let two: HashSet<SocketAddr> = nodes[2..].iter().copied().collect();
+        assert!(!ring.is_covered_by(&two, 2));
+        assert!(ring.is_covered_by(&two, 3));
+        assert!(!ring.is_covered_by(&HashSet::new(), 4));
+    }
+
+    #[test]
+    fn test_add_node_is_idempotent() {
+        let nodes: Vec<SocketAddr> = create_nodes(2);
+        let mut ring: HashRing = create_ring(&nodes);
+        ring.add_node(nodes[0]);
+        assert_eq!(ring.len(), 2);
+    }
+
+    #[test]
+    fn test_remove_node_only_moves_its_keys() {
+        let nodes: Vec<SocketAddr> = create_nodes(4);
+        let mut ring: HashRing = create_ring(&nodes);
+        let keys: Vec<Vec<u8>> = create_keys(1000);
+        let owners_before: Vec<SocketAddr> = keys.iter().map(|k| ring.get_node(k).unwrap()).collect();
+
+        ring.remove_node(&nodes[1]);
+        assert!(!ring.contains(&nodes[1]));
+
+        for (key, owner_before) in keys.iter().zip(owners_before) {
+            let owner_after: SocketAddr = ring.get_node(key).unwrap();
+            assert_ne!(owner_after, nodes[1]);
+            if owner_before != nodes[1] {
+                assert_eq!(owner_after, owner_before);
+            }
+        }
+    }
+}
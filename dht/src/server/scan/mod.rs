@@ -0,0 +1,212 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use protobuf::Message;
+
+use crate::comm::MAX_BUFFER_SIZE_BYTES;
+use crate::comm::protogen::api::{KeyValue, Version};
+use crate::server::clock::compare_versions;
+
+/// Room left in a message for everything but the entries of a page
+const PAGE_HEADROOM_BYTES: usize = 1024;
+/// Smallest limit on the size of a page, which fits in a single datagram
+pub const MIN_PAGE_SIZE_BYTES: usize = MAX_BUFFER_SIZE_BYTES - PAGE_HEADROOM_BYTES;
+/// Field tag and length prefix of an entry within a reply
+const ENTRY_FRAMING_BYTES: usize = 4;
+
+/// Entries of a scan in key order, along with the key the next page starts
+/// from if there is more to the scan than fits in one message.
+pub struct ScanPage {
+    pub entries: Vec<KeyValue>,
+    pub next_key: Option<Vec<u8>>,
+    size: usize,
+    max_size: usize,
+}
+
+impl ScanPage {
+    pub fn new(entries: Vec<KeyValue>, next_key: Option<Vec<u8>>) -> Self {
+        let size: usize = entries.iter().map(get_entry_size).sum();
+        ScanPage { entries, next_key, size, max_size: MIN_PAGE_SIZE_BYTES }
+    }
+
+    /// An empty page taking entries up to `max_size` bytes.
+    pub fn with_max_size(max_size: usize) -> Self {
+        ScanPage { entries: Vec::new(), next_key: None, size: 0, max_size }
+    }
+
+    /// Adds `entry` unless the page is full, in which case `entry` becomes
+    /// the start of the next page. A page always takes at least one entry.
+    pub fn push(&mut self, entry: KeyValue) -> bool {
+        let entry_size: usize = get_entry_size(&entry);
+        if !self.entries.is_empty() && self.size + entry_size > self.max_size {
+            self.next_key = Some(entry.key);
+            return false;
+        }
+
+        self.size += entry_size;
+        self.entries.push(entry);
+        true
+    }
+}
+
+/// Largest page for a node storing values of up to `max_value_size`, so that
+/// any value it holds comes back whole within a message.
+pub fn get_max_page_size(max_value_size: usize) -> usize {
+    MIN_PAGE_SIZE_BYTES.max(max_value_size + PAGE_HEADROOM_BYTES)
+}
+
+pub fn get_entry_size(entry: &KeyValue) -> usize {
+    entry.compute_size() as usize + ENTRY_FRAMING_BYTES
+}
+
+/// Merges the pages that several nodes returned for the same scan into the
+/// first page of the whole scan, holding at most `limit` entries and
+/// `max_size` bytes.
+///
+/// Keys held by several nodes keep their newest version. A node that could
+/// not fit all of its keys only vouches for the keys before its next key, so
+/// the merged page stops there too.
+pub fn merge_pages(pages: Vec<ScanPage>, limit: usize, max_size: usize) -> ScanPage {
+    let cutoff: Option<Vec<u8>> = pages.iter().filter_map(|page| page.next_key.clone()).min();
+
+    let mut newest: BTreeMap<Vec<u8>, KeyValue> = BTreeMap::new();
+    for entry in pages.into_iter().flat_map(|page| page.entries) {
+        if cutoff.as_ref().is_some_and(|cutoff| entry.key >= *cutoff) {
+            continue;
+        }
+        if let Some(existing) = newest.get(&entry.key) {
+            let existing_version: &Version = existing.version.as_ref().unwrap_or(Version::default_instance());
+            let version: &Version = entry.version.as_ref().unwrap_or(Version::default_instance());
+            if compare_versions(existing_version, version) != Ordering::Less {
+                continue;
+            }
+        }
+        newest.insert(entry.key.clone(), entry);
+    }
+
+    let mut page: ScanPage = ScanPage::with_max_size(max_size);
+    for entry in newest.into_values() {
+        if page.entries.len() >= limit {
+            return page;
+        }
+        if !page.push(entry) {
+            return page;
+        }
+    }
+
+    if page.entries.len() < limit {
+        page.next_key = cutoff;
+    }
+    page
+}
+
+/// The first key after every key starting with `prefix`, or `None` if no
+/// such key exists because the prefix is empty or all `0xFF` bytes.
+pub fn get_prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end: Vec<u8> = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protobuf::MessageField;
+
+    fn create_entry(key: &[u8], value_len: usize, timestamp: u64) -> KeyValue {
+        let mut version: Version = Version::new();
+        version.timestamp = timestamp;
+        let mut entry: KeyValue = KeyValue::new();
+        entry.key = key.to_vec();
+        entry.value = vec![0; value_len];
+        entry.version = MessageField::some(version);
+        entry
+    }
+
+    fn get_keys(page: &ScanPage) -> Vec<Vec<u8>> {
+        page.entries.iter().map(|entry| entry.key.clone()).collect()
+    }
+
+    #[test]
+    fn test_push_stops_at_page_size() {
+        let mut page: ScanPage = ScanPage::with_max_size(MIN_PAGE_SIZE_BYTES);
+        let value_len: usize = MIN_PAGE_SIZE_BYTES / 3;
+        assert!(page.push(create_entry(b"a", value_len, 1)));
+        assert!(page.push(create_entry(b"b", value_len, 1)));
+        assert!(!page.push(create_entry(b"c", value_len, 1)));
+        assert_eq!(get_keys(&page), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(page.next_key, Some(b"c".to_vec()));
+
+        // A single entry always fits so that scans make progress
+        let mut page: ScanPage = ScanPage::with_max_size(MIN_PAGE_SIZE_BYTES);
+        assert!(page.push(create_entry(b"a", MIN_PAGE_SIZE_BYTES, 1)));
+    }
+
+    #[test]
+    fn test_page_size_fits_largest_value() {
+        assert_eq!(get_max_page_size(1024), MIN_PAGE_SIZE_BYTES);
+
+        let max_value_size: usize = 1024 * 1024;
+        let mut page: ScanPage = ScanPage::with_max_size(get_max_page_size(max_value_size));
+        assert!(page.push(create_entry(b"a", 1, 1)));
+        assert!(page.push(create_entry(b"b", max_value_size, 1)));
+    }
+
+    #[test]
+    fn test_merge_keeps_newest_version_in_order() {
+        let pages: Vec<ScanPage> = vec![
+            ScanPage::new(vec![create_entry(b"a", 1, 1), create_entry(b"c", 1, 2)], None),
+            ScanPage::new(vec![create_entry(b"b", 1, 1), create_entry(b"c", 2, 3)], None),
+        ];
+        let page: ScanPage = merge_pages(pages, usize::MAX, MIN_PAGE_SIZE_BYTES);
+        assert_eq!(get_keys(&page), vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(page.entries[2].value.len(), 2);
+        assert_eq!(page.next_key, None);
+    }
+
+    #[test]
+    fn test_merge_stops_at_earliest_next_key() {
+        let pages: Vec<ScanPage> = vec![
+            ScanPage::new(vec![create_entry(b"a", 1, 1), create_entry(b"d", 1, 1)], None),
+            ScanPage::new(vec![create_entry(b"b", 1, 1)], Some(b"c".to_vec())),
+        ];
+        let page: ScanPage = merge_pages(pages, usize::MAX, MIN_PAGE_SIZE_BYTES);
+        assert_eq!(get_keys(&page), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(page.next_key, Some(b"c".to_vec()));
+    }
+
+    #[test]
+    fn test_merge_applies_limit() {
+        let pages: Vec<ScanPage> = vec![
+            ScanPage::new(vec![create_entry(b"a", 1, 1), create_entry(b"c", 1, 1)], None),
+            ScanPage::new(vec![create_entry(b"b", 1, 1)], Some(b"d".to_vec())),
+        ];
+        let page: ScanPage = merge_pages(pages, 2, MIN_PAGE_SIZE_BYTES);
+        assert_eq!(get_keys(&page), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(page.next_key, None);
+    }
+
+    #[test]
+    fn test_merge_paginates_large_entries() {
+        let value_len: usize = MIN_PAGE_SIZE_BYTES / 3;
+        let pages: Vec<ScanPage> = vec![
+            ScanPage::new(vec![create_entry(b"a", value_len, 1), create_entry(b"c", value_len, 1)], None),
+            ScanPage::new(vec![create_entry(b"b", value_len, 1)], None),
+        ];
+        let page: ScanPage = merge_pages(pages, usize::MAX, MIN_PAGE_SIZE_BYTES);
+        assert_eq!(get_keys(&page), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(page.next_key, Some(b"c".to_vec()));
+    }
+
+    #[test]
+    fn test_get_prefix_end() {
+        assert_eq!(get_prefix_end(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(get_prefix_end(&[b'a', 0xFF]), Some(b"b".to_vec()));
+        assert_eq!(get_prefix_end(&[0xFF, 0xFF]), None);
+        assert_eq!(get_prefix_end(b""), None);
+    }
+}
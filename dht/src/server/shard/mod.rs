@@ -0,0 +1,346 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use crc::{Crc, CRC_32_ISCSI};
+use mini_moka::sync::{Cache, ConcurrentCacheExt};
+
+use crate::comm::protogen::api::Version;
+use crate::server::clock::compare_versions;
+use crate::server::eviction::{EvictionPolicy, Evictor};
+use crate::server::storage::StorageEngine;
+
+const SHARD_HASH: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+const REPLY_CACHE_TIME_TO_IDLE: Duration = Duration::from_secs(1);
+
+pub type ShardGuard<'a, S> = MutexGuard<'a, Shard<S>>;
+
+/// A key deleted at `version`, kept for a while so that older copies of
+/// it still held elsewhere are not brought back.
+pub struct Tombstone {
+    pub version: Version,
+    pub deleted_at: Instant,
+}
+
+/// The keys of one shard, along with the evictor tracking how they are used
+/// and the tombstones of the keys deleted recently.
+pub struct Shard<S: StorageEngine> {
+    pub data_store: S,
+    pub evictor: Evictor,
+    pub evictor_mem_usage: u64,
+    tombstones: HashMap<Vec<u8>, Tombstone>,
+    tombstones_mem_usage: u64,
+}
+
+impl<S: StorageEngine> Shard<S> {
+    fn new(data_store: S, eviction_policy: EvictionPolicy) -> Self {
+        Shard {
+            data_store,
+            evictor: Evictor::new(eviction_policy),
+            evictor_mem_usage: 0,
+            tombstones: HashMap::new(),
+            tombstones_mem_usage: 0,
+        }
+    }
+
+    /// Memory used by the stored pairs, by the evictor to track them and by
+    /// the tombstones
+    pub fn mem_usage(&self) -> u64 {
+        self.data_store.memory_usage() + self.evictor_mem_usage + self.tombstones_mem_usage
+    }
+
+    /// Memory a pair with a key of `key_len` bytes and a value of
+    /// `value_len` bytes would use.
+    pub fn get_entry_mem_usage(&self, key_len: usize, value_len: usize) -> u64 {
+        self.data_store.get_entry_mem_usage(key_len, value_len) + self.evictor.get_key_mem_usage(key_len)
+    }
+
+    /// Memory used by the pair stored under `key`, if any.
+    pub fn get_stored_mem_usage(&self, key: &[u8]) -> u64 {
+        match self.data_store.contains_key(key) {
+            true => self.data_store.get_stored_mem_usage(key) + self.evictor.get_key_mem_usage(key.len()),
+            false => 0,
+        }
+    }
+
+    /// Version `key` was last deleted at, if it was deleted recently
+    pub fn get_deleted_version(&self, key: &[u8]) -> Option<&Version> {
+        self.tombstones.get(key).map(|tombstone| &tombstone.version)
+    }
+
+    /// Whether `key` was deleted at `version` or later
+    pub fn is_deleted_since(&self, key: &[u8], version: &Version) -> bool {
+        self.get_deleted_version(key)
+            .is_some_and(|deleted| compare_versions(deleted, version) != std::cmp::Ordering::Less)
+    }
+
+    /// Memory a tombstone for `key` would add, which is none if the key
+    /// already has one.
+    pub fn get_tombstone_growth(&self, key: &[u8]) -> u64 {
+        match self.tombstones.contains_key(key) {
+            true => 0,
+            false => get_tombstone_mem_usage(key.len()),
+        }
+    }
+
+    /// Records that `key` was deleted at `version`, unless it is already
+    /// known to be deleted later.
+    pub fn add_tombstone(&mut self, key: Vec<u8>, version: Version, now: Instant) {
+        if self.is_deleted_since(&key, &version) {
+            return;
+        }
+        if !self.tombstones.contains_key(&key) {
+            self.tombstones_mem_usage += get_tombstone_mem_usage(key.len());
+        }
+        self.tombstones.insert(key, Tombstone { version, deleted_at: now });
+    }
+
+    pub fn tombstones(&self) -> impl Iterator<Item = (&Vec<u8>, &Tombstone)> {
+        self.tombstones.iter()
+    }
+
+    /// Drops the tombstones of the keys deleted before `cutoff`, returning
+    /// how many were dropped.
+    pub fn drop_tombstones(&mut self, cutoff: Instant) -> usize {
+        let num_tombstones: usize = self.tombstones.len();
+        self.tombstones.retain(|_, tombstone| tombstone.deleted_at >= cutoff);
+        self.tombstones_mem_usage = self.tombstones
+            .keys()
+            .map(|key| get_tombstone_mem_usage(key.len()))
+            .sum();
+        num_tombstones - self.tombstones.len()
+    }
+
+    pub fn clear_tombstones(&mut self) {
+        self.tombstones.clear();
+        self.tombstones_mem_usage = 0;
+    }
+}
+
+/// Memory a tombstone for a key of `key_len` bytes uses
+fn get_tombstone_mem_usage(key_len: usize) -> u64 {
+    (key_len + std::mem::size_of::<Vec<u8>>() + std::mem::size_of::<Tombstone>()) as u64
+}
+
+/// Data store split by key into shards, each behind a lock of its own, so
+/// that workers handling different keys seldom wait on each other.
+///
+/// Whoever needs several shards at once locks them in order of their index,
+/// or only tries to lock the others while holding one. The memory used by
+/// every shard is kept as a running total, which writes reserve their bytes
+/// on before storing anything, so that the memory limit holds while holding
+/// a single shard.
+pub struct ShardedStore<S: StorageEngine> {
+    shards: Vec<Mutex<Shard<S>>>,
+    mem_usage: AtomicU64,
+}
+
+impl<S: StorageEngine> ShardedStore<S> {
+    pub fn new(data_stores: Vec<S>, eviction_policy: EvictionPolicy) -> Self {
+        let shards: Vec<Mutex<Shard<S>>> = data_stores
+            .into_iter()
+            .map(|data_store| Mutex::new(Shard::new(data_store, eviction_policy)))
+            .collect();
+        ShardedStore { shards, mem_usage: AtomicU64::new(0) }
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn get_shard_index(&self, key: &[u8]) -> usize {
+        get_shard_index(key, self.shards.len())
+    }
+
+    /// Locks the shard holding `key`.
+    pub fn lock(&self, key: &[u8]) -> ShardGuard<'_, S> {
+        self.lock_index(self.get_shard_index(key))
+    }
+
+    pub fn lock_index(&self, index: usize) -> ShardGuard<'_, S> {
+        self.shards[index].lock().unwrap()
+    }
+
+    /// Locks the shard at `index` unless it is in use.
+    pub fn try_lock_index(&self, index: usize) -> Option<ShardGuard<'_, S>> {
+        self.shards[index].try_lock().ok()
+    }
+
+    /// Locks every shard, for changes that have to see the whole store.
+    pub fn lock_all(&self) -> Vec<ShardGuard<'_, S>> {
+        (0..self.shards.len()).map(|index| self.lock_index(index)).collect()
+    }
+
+    /// Memory used by every shard
+    pub fn mem_usage(&self) -> u64 {
+        self.mem_usage.load(Ordering::SeqCst)
+    }
+
+    /// Adds `bytes` to the memory in use if that keeps it within `limit`,
+    /// returning whether it did.
+    pub fn try_reserve(&self, bytes: u64, limit: u64) -> bool {
+        self.mem_usage
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used + bytes <= limit).then_some(used + bytes)
+            })
+            .is_ok()
+    }
+
+    /// Accounts for a shard going from `before` to `after` bytes in use.
+    pub fn update_mem_usage(&self, before: u64, after: u64) {
+        match after >= before {
+            true => self.mem_usage.fetch_add(after - before, Ordering::SeqCst),
+            false => self.mem_usage.fetch_sub(before - after, Ordering::SeqCst),
+        };
+    }
+}
+
+/// Shard out of `num_shards` that `key` belongs to. The hash is fixed so
+/// that keys kept on disk are found in the same shard after a restart.
+pub fn get_shard_index(key: &[u8], num_shards: usize) -> usize {
+    (SHARD_HASH.checksum(key) as usize) % num_shards.max(1)
+}
+
+/// Replies to recent requests by message Id, so that retried requests are
+/// answered without running them twice. The cache shards its entries and
+/// locks each shard on its own, like the data store.
+///
+/// It also tracks the requests still being handled, so that a retry
+/// arriving meanwhile is not handled by a second worker.
+pub struct ReplyCache {
+    cache: Cache<Vec<u8>, Vec<u8>>,
+    in_flight: Mutex<HashSet<Vec<u8>>>,
+}
+
+impl ReplyCache {
+    /// A cache holding up to `max_capacity` bytes
+    pub fn new(max_capacity: u64) -> Self {
+        let cache: Cache<Vec<u8>, Vec<u8>> = Cache::builder()
+            .max_capacity(max_capacity)
+            .time_to_idle(REPLY_CACHE_TIME_TO_IDLE)
+            .weigher(|k: &Vec<u8>, v: &Vec<u8>| (k.len() + v.len()) as u32)
+            .build();
+        ReplyCache { cache, in_flight: Mutex::new(HashSet::new()) }
+    }
+
+    pub fn get(&self, msg_id: &[u8]) -> Option<Vec<u8>> {
+        self.cache.get(&msg_id.to_vec())
+    }
+
+    pub fn insert(&self, msg_id: Vec<u8>, reply_bytes: Vec<u8>) {
+        self.cache.insert(msg_id, reply_bytes);
+    }
+
+    /// Bytes held, leaving out the writes still pending within the cache
+    pub fn weighted_size(&self) -> u64 {
+        self.cache.weighted_size()
+    }
+
+    /// Applies the writes still pending within the cache, for an exact size.
+    pub fn sync(&self) {
+        self.cache.sync();
+    }
+
+    /// Marks the request `msg_id` as being handled, returning false if it
+    /// already is.
+    pub fn start(&self, msg_id: &[u8]) -> bool {
+        self.in_flight.lock().unwrap().insert(msg_id.to_vec())
+    }
+
+    pub fn finish(&self, msg_id: &[u8]) {
+        self.in_flight.lock().unwrap().remove(msg_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_shard_index() {
+        let key: &[u8] = b"key";
+        assert_eq!(get_shard_index(key, 1), 0);
+        assert_eq!(get_shard_index(key, 0), 0);
+        assert_eq!(get_shard_index(key, 8), get_shard_index(key, 8));
+
+        let mut is_used: [bool; 4] = [false; 4];
+        for i in 0..64u32 {
+            let index: usize = get_shard_index(&i.to_be_bytes(), 4);
+            assert!(index < 4);
+            is_used[index] = true;
+        }
+        assert!(is_used.iter().all(|is_used| *is_used));
+    }
+
+    #[test]
+    fn test_reply_cache() {
+        let cache: ReplyCache = ReplyCache::new(1024 * 1024);
+        assert!(cache.get(b"id").is_none());
+        cache.insert(b"id".to_vec(), b"reply".to_vec());
+        assert_eq!(cache.get(b"id").unwrap(), b"reply");
+        cache.sync();
+        assert!(cache.weighted_size() > 0);
+    }
+
+    #[test]
+    fn test_reply_cache_in_flight() {
+        let cache: ReplyCache = ReplyCache::new(1024 * 1024);
+        assert!(cache.start(b"id"));
+        assert!(!cache.start(b"id"));
+        assert!(cache.start(b"other"));
+        cache.finish(b"id");
+        assert!(cache.start(b"id"));
+    }
+
+    #[test]
+    fn test_update_mem_usage() {
+        let store: ShardedStore<crate::server::storage::HashMapEngine> = ShardedStore::new(
+            vec![Default::default(), Default::default()],
+            EvictionPolicy::NoEviction
+        );
+        store.update_mem_usage(0, 100);
+        store.update_mem_usage(100, 40);
+        assert_eq!(store.mem_usage(), 40);
+        assert_eq!(store.num_shards(), 2);
+
+        assert!(store.try_reserve(60, 100));
+        assert!(!store.try_reserve(1, 100));
+        assert_eq!(store.mem_usage(), 100);
+    }
+
+    #[test]
+    fn test_try_lock_index() {
+        let store: ShardedStore<crate::server::storage::HashMapEngine> = ShardedStore::new(
+            vec![Default::default(), Default::default()],
+            EvictionPolicy::NoEviction
+        );
+        let _shard = store.lock_index(0);
+        assert!(store.try_lock_index(0).is_none());
+        assert!(store.try_lock_index(1).is_some());
+    }
+
+    #[test]
+    fn test_tombstones() {
+        let mut shard: Shard<crate::server::storage::HashMapEngine> = Shard::new(Default::default(), EvictionPolicy::NoEviction);
+        let mut older: Version = Version::new();
+        older.timestamp = 100;
+        let mut newer: Version = Version::new();
+        newer.timestamp = 200;
+
+        let deleted_at: Instant = Instant::now();
+        let growth: u64 = shard.get_tombstone_growth(b"key");
+        shard.add_tombstone(b"key".to_vec(), newer.clone(), deleted_at);
+        assert_eq!(shard.mem_usage(), growth);
+        assert_eq!(shard.get_tombstone_growth(b"key"), 0);
+        shard.add_tombstone(b"key".to_vec(), older.clone(), deleted_at);
+        assert_eq!(shard.get_deleted_version(b"key"), Some(&newer));
+        assert!(shard.is_deleted_since(b"key", &older));
+        assert!(shard.is_deleted_since(b"key", &newer));
+        assert!(!shard.is_deleted_since(b"other", &older));
+
+        assert_eq!(shard.drop_tombstones(deleted_at), 0);
+        assert_eq!(shard.drop_tombstones(deleted_at + Duration::from_millis(1)), 1);
+        assert!(shard.get_deleted_version(b"key").is_none());
+        assert_eq!(shard.mem_usage(), 0);
+    }
+}
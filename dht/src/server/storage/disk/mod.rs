@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::mem::size_of;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::comm::proto::Operation;
+use crate::comm::protogen::api::LogRecord;
+use crate::server::storage::{ALLOCATION_OVERHEAD_BYTES, StorageEngine, StoredValue};
+use crate::server::wal::{decode_record, encode_record};
+
+const DATA_FILE_NAME: &str = "data.log";
+const COMPACTION_TEMP_FILE_NAME: &str = "data.log.tmp";
+/// The data file is compacted once at least this much of it, and at least
+/// half of it, is taken up by overwritten or deleted pairs
+const MIN_COMPACTION_GARBAGE_BYTES: u64 = 1024 * 1024;
+/// Memory a key in the index uses beyond its bytes: its hash table slot and
+/// control byte, plus the allocation holding the key
+const INDEX_ENTRY_OVERHEAD_BYTES: u64 = (size_of::<(Vec<u8>, IndexEntry)>() as u64) + 1 + ALLOCATION_OVERHEAD_BYTES;
+
+/// Where the latest record of a key lives in the data file
+struct IndexEntry {
+    offset: u64,
+    len: u64,
+    expires_at: Option<Instant>,
+}
+
+/// Log-structured engine keeping values on disk and only an index of the
+/// keys in memory.
+///
+/// Every `put` and `delete` appends a record to the data file, framed the
+/// same way as the write-ahead log, and the index points each key at its
+/// latest record. Records that no longer hold the latest value of a key are
+/// garbage which compaction drops by rewriting the live records into a new
+/// file. The index is rebuilt by reading the file back on startup.
+pub struct DiskEngine {
+    file: File,
+    path: PathBuf,
+    index: HashMap<Vec<u8>, IndexEntry>,
+    len: u64,
+    garbage_len: u64,
+    mem_usage: u64,
+}
+
+impl DiskEngine {
+    /// Opens the data file in `data_dir`, creating it if needed.
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        fs::create_dir_all(data_dir)?;
+        let path: PathBuf = data_dir.join(DATA_FILE_NAME);
+        let mut file: File = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+
+        let mut bytes: Vec<u8> = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut engine: DiskEngine = DiskEngine {
+            file,
+            path,
+            index: HashMap::new(),
+            len: 0,
+            garbage_len: 0,
+            mem_usage: 0,
+        };
+        let mut offset: usize = 0;
+        while let Some((record, record_len)) = decode_record(&bytes[offset..]) {
+            engine.len = (offset + record_len) as u64;
+            engine.load_record(record, offset as u64, record_len as u64);
+            offset += record_len;
+        }
+
+        if offset < bytes.len() {
+            log::warn!("Dropping {} B of torn or corrupt records from {}", bytes.len() - offset, engine.path.display());
+            engine.file.set_len(offset as u64)?;
+        }
+
+        log::info!("Loaded {} keys from {}", engine.index.len(), engine.path.display());
+        Ok(engine)
+    }
+
+    fn load_record(&mut self, record: LogRecord, offset: u64, len: u64) {
+        let key: Vec<u8> = record.key.clone();
+        match Operation::try_from(record.operation) {
+            Ok(Operation::Put) => match StoredValue::from_log_record(record) {
+                Some(stored) => self.set_index_entry(key, IndexEntry { offset, len, expires_at: stored.expires_at }),
+                // Expired while the node was down
+                None => {
+                    self.remove_index_entry(&key);
+                    self.garbage_len += len;
+                },
+            },
+            _ => {
+                self.remove_index_entry(&key);
+                self.garbage_len += len;
+            },
+        }
+    }
+
+    fn set_index_entry(&mut self, key: Vec<u8>, entry: IndexEntry) {
+        self.remove_index_entry(&key);
+        self.mem_usage += self.get_entry_mem_usage(key.len(), 0);
+        self.index.insert(key, entry);
+    }
+
+    fn remove_index_entry(&mut self, key: &[u8]) -> Option<IndexEntry> {
+        let entry: IndexEntry = self.index.remove(key)?;
+        self.mem_usage -= self.get_entry_mem_usage(key.len(), 0);
+        self.garbage_len += entry.len;
+        Some(entry)
+    }
+
+    fn read_record(&self, entry: &IndexEntry) -> Result<LogRecord> {
+        let mut bytes: Vec<u8> = vec![0; entry.len as usize];
+        self.file.read_exact_at(&mut bytes, entry.offset)?;
+        match decode_record(&bytes) {
+            Some((record, _)) => Ok(record),
+            None => Err(Error::new(ErrorKind::InvalidData, format!("Corrupt record at offset {}", entry.offset))),
+        }
+    }
+
+    fn read_value(&self, entry: &IndexEntry) -> Result<StoredValue> {
+        let record: LogRecord = self.read_record(entry)?;
+        Ok(StoredValue { value: record.value, version: record.version.unwrap_or_default(), expires_at: entry.expires_at })
+    }
+
+    /// Appends `record` and waits for it to reach the disk, returning its
+    /// offset and length.
+    fn append(&mut self, record: &LogRecord) -> Result<(u64, u64)> {
+        let bytes: Vec<u8> = encode_record(record)?;
+        self.file.write_all(&bytes)?;
+        self.file.sync_data()?;
+
+        let offset: u64 = self.len;
+        self.len += bytes.len() as u64;
+        Ok((offset, bytes.len() as u64))
+    }
+
+    /// Compacts the data file once enough of it is garbage. The write that
+    /// made it so is already on disk, so a failed compaction is only logged
+    /// and tried again after the next write.
+    fn compact_if_needed(&mut self) {
+        if self.garbage_len >= MIN_COMPACTION_GARBAGE_BYTES && 2 * self.garbage_len >= self.len {
+            if let Err(e) = self.compact() {
+                log::error!("Failed to compact {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    /// Rewrites the live records into a new data file which then replaces
+    /// the current one, so a crash part way through leaves the old file.
+    fn compact(&mut self) -> Result<()> {
+        let temp_path: PathBuf = self.path.with_file_name(COMPACTION_TEMP_FILE_NAME);
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(&temp_path)?);
+        let mut offsets: Vec<(Vec<u8>, u64)> = Vec::with_capacity(self.index.len());
+        let mut len: u64 = 0;
+        for (key, entry) in self.index.iter() {
+            let mut bytes: Vec<u8> = vec![0; entry.len as usize];
+            self.file.read_exact_at(&mut bytes, entry.offset)?;
+            writer.write_all(&bytes)?;
+            offsets.push((key.clone(), len));
+            len += entry.len;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        fs::rename(&temp_path, &self.path)?;
+        if let Some(data_dir) = self.path.parent() {
+            File::open(data_dir)?.sync_all()?;
+        }
+        self.file = OpenOptions::new().read(true).append(true).open(&self.path)?;
+
+        for (key, offset) in offsets {
+            if let Some(entry) = self.index.get_mut(&key) {
+                entry.offset = offset;
+            }
+        }
+        log::info!("Compacted {} from {} B to {} B", self.path.display(), self.len, len);
+        self.len = len;
+        self.garbage_len = 0;
+        Ok(())
+    }
+}
+
+impl StorageEngine for DiskEngine {
+    fn get(&self, key: &[u8]) -> Result<Option<StoredValue>> {
+        match self.index.get(key) {
+            Some(entry) => self.read_value(entry)
+                .map(Some)
+                .inspect_err(|e| log::error!("Failed to read value from {}: {}", self.path.display(), e)),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&mut self, key: Vec<u8>, value: StoredValue) -> Result<()> {
+        let (offset, len) = self.append(&value.to_log_record(key.clone()))?;
+        self.set_index_entry(key, IndexEntry { offset, len, expires_at: value.expires_at });
+        self.compact_if_needed();
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Option<StoredValue>> {
+        let stored: StoredValue = match self.index.get(key) {
+            Some(entry) => self.read_value(entry)?,
+            None => return Ok(None),
+        };
+
+        let mut record: LogRecord = LogRecord::new();
+        record.operation = Operation::Delete as u32;
+        record.key = key.to_vec();
+        let (_, len) = self.append(&record)?;
+        self.remove_index_entry(key);
+        self.garbage_len += len;
+
+        self.compact_if_needed();
+        Ok(Some(stored))
+    }
+
+    fn wipe(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.index = HashMap::new();
+        self.len = 0;
+        self.garbage_len = 0;
+        self.mem_usage = 0;
+        Ok(())
+    }
+
+    /// Values that cannot be read are skipped, after logging the error.
+    fn iter(&self) -> Box<dyn Iterator<Item = (Vec<u8>, StoredValue)> + '_> {
+        Box::new(self.index.keys().filter_map(|key| match self.get(key) {
+            Ok(stored_opt) => stored_opt.map(|stored| (key.clone(), stored)),
+            Err(_) => None,
+        }))
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &Vec<u8>> + '_> {
+        Box::new(self.index.keys())
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.index.contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn memory_usage(&self) -> u64 {
+        self.mem_usage
+    }
+
+    /// Only the key is held in memory, the value stays on disk.
+    fn get_entry_mem_usage(&self, key_len: usize, _value_len: usize) -> u64 {
+        key_len as u64 + INDEX_ENTRY_OVERHEAD_BYTES
+    }
+
+    fn get_stored_mem_usage(&self, key: &[u8]) -> u64 {
+        match self.index.contains_key(key) {
+            true => self.get_entry_mem_usage(key.len(), 0),
+            false => 0,
+        }
+    }
+
+    fn get_expired_keys(&self, now: Instant) -> Vec<Vec<u8>> {
+        self.index
+            .iter()
+            .filter(|(_, entry)| entry.expires_at.is_some_and(|expires_at| expires_at <= now))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    fn is_persistent(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::storage::tests::{check_engine, create_value};
+
+    fn create_data_dir(name: &str) -> PathBuf {
+        let data_dir: PathBuf = std::env::temp_dir().join(format!("dht-disk-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        data_dir
+    }
+
+    #[test]
+    fn test_disk_engine() {
+        let data_dir: PathBuf = create_data_dir("engine");
+        check_engine(&mut DiskEngine::open(&data_dir).unwrap());
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_reopen_recovers_latest_values() {
+        let data_dir: PathBuf = create_data_dir("reopen");
+        let mut engine: DiskEngine = DiskEngine::open(&data_dir).unwrap();
+        engine.put(b"a".to_vec(), create_value(b"1", 1)).unwrap();
+        engine.put(b"a".to_vec(), create_value(b"2", 2)).unwrap();
+        engine.put(b"b".to_vec(), create_value(b"3", 3)).unwrap();
+        engine.delete(b"b").unwrap();
+        let mem_usage: u64 = engine.memory_usage();
+        drop(engine);
+
+        let engine: DiskEngine = DiskEngine::open(&data_dir).unwrap();
+        assert_eq!(engine.len(), 1);
+        assert_eq!(engine.get(b"a").unwrap(), Some(create_value(b"2", 2)));
+        assert_eq!(engine.get(b"b").unwrap(), None);
+        assert_eq!(engine.memory_usage(), mem_usage);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_compaction_drops_garbage() {
+        let data_dir: PathBuf = create_data_dir("compaction");
+        let mut engine: DiskEngine = DiskEngine::open(&data_dir).unwrap();
+        let value: Vec<u8> = vec![0; 64 * 1024];
+        for i in 0..64 {
+            engine.put(b"a".to_vec(), create_value(&value, i)).unwrap();
+        }
+        engine.put(b"b".to_vec(), create_value(b"1", 1)).unwrap();
+
+        // Everything but the latest value of each key is gone
+        assert!(engine.garbage_len < MIN_COMPACTION_GARBAGE_BYTES);
+        assert!(engine.len < 2 * MIN_COMPACTION_GARBAGE_BYTES);
+        assert_eq!(fs::metadata(&engine.path).unwrap().len(), engine.len);
+        assert_eq!(engine.get(b"a").unwrap(), Some(create_value(&value, 63)));
+        assert_eq!(engine.get(b"b").unwrap(), Some(create_value(b"1", 1)));
+
+        // Appends after compaction land at the end of the new file
+        engine.put(b"c".to_vec(), create_value(b"2", 2)).unwrap();
+        drop(engine);
+        let engine: DiskEngine = DiskEngine::open(&data_dir).unwrap();
+        assert_eq!(engine.len(), 3);
+        assert_eq!(engine.get(b"a").unwrap(), Some(create_value(&value, 63)));
+        assert_eq!(engine.get(b"c").unwrap(), Some(create_value(b"2", 2)));
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_record_is_dropped() {
+        let data_dir: PathBuf = create_data_dir("torn");
+        let mut engine: DiskEngine = DiskEngine::open(&data_dir).unwrap();
+        engine.put(b"a".to_vec(), create_value(b"1", 1)).unwrap();
+        let path: PathBuf = engine.path.clone();
+        drop(engine);
+
+        let mut file: File = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0, 0, 0, 9, 1, 2]).unwrap();
+
+        let mut engine: DiskEngine = DiskEngine::open(&data_dir).unwrap();
+        engine.put(b"b".to_vec(), create_value(b"2", 2)).unwrap();
+        drop(engine);
+        let engine: DiskEngine = DiskEngine::open(&data_dir).unwrap();
+        assert_eq!(engine.get(b"a").unwrap(), Some(create_value(b"1", 1)));
+        assert_eq!(engine.get(b"b").unwrap(), Some(create_value(b"2", 2)));
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_value_fails_get() {
+        let data_dir: PathBuf = create_data_dir("corrupt");
+        let mut engine: DiskEngine = DiskEngine::open(&data_dir).unwrap();
+        engine.put(b"a".to_vec(), create_value(b"1", 1)).unwrap();
+
+        // Flip the last byte of the record behind the index's back
+        let file: File = OpenOptions::new().write(true).open(&engine.path).unwrap();
+        let mut last: [u8; 1] = [0];
+        engine.file.read_exact_at(&mut last, engine.len - 1).unwrap();
+        file.write_all_at(&[last[0] ^ 0xFF], engine.len - 1).unwrap();
+
+        assert_eq!(engine.get(b"a").unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(engine.get(b"b").unwrap(), None);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -0,0 +1,409 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::io::{Error, ErrorKind, Result};
+use std::mem::size_of;
+use std::ops::Bound;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use protobuf::MessageField;
+
+use crate::comm::proto::Operation;
+use crate::comm::protogen::api::{LogRecord, Version};
+use crate::server::clock::physical_time;
+
+pub mod disk;
+
+/// Rough cost of a heap allocation beyond the bytes requested
+pub const ALLOCATION_OVERHEAD_BYTES: u64 = 16;
+/// Memory a pair in `HashMapEngine` uses beyond its key and value: its hash
+/// table slot and control byte, plus the allocations holding the key and value
+const HASH_MAP_ENTRY_OVERHEAD_BYTES: u64 = (size_of::<(Vec<u8>, StoredValue)>() as u64) + 1 + 2 * ALLOCATION_OVERHEAD_BYTES;
+/// Memory a pair in `BTreeMapEngine` uses beyond its key and value: B-tree
+/// nodes are about two thirds full on average, plus the allocations holding
+/// the key and value
+const BTREE_MAP_ENTRY_OVERHEAD_BYTES: u64 = (size_of::<(Vec<u8>, StoredValue)>() as u64) * 3 / 2 + 2 * ALLOCATION_OVERHEAD_BYTES;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredValue {
+    pub value: Vec<u8>,
+    pub version: Version,
+    pub expires_at: Option<Instant>,
+}
+
+impl StoredValue {
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// TTLs travel between nodes as the time left rather than a deadline so
+    /// that clock differences between nodes do not matter.
+    pub fn remaining_ttl_ms(&self, now: Instant) -> Option<u64> {
+        self.expires_at.map(|expires_at| expires_at.saturating_duration_since(now).as_millis() as u64)
+    }
+
+    /// A `Put` record for `key` holding this value. Instants do not survive
+    /// a restart, so the deadline is recorded against the wall clock.
+    pub fn to_log_record(&self, key: Vec<u8>) -> LogRecord {
+        let mut record: LogRecord = LogRecord::new();
+        record.operation = Operation::Put as u32;
+        record.key = key;
+        record.value = self.value.clone();
+        record.version = MessageField::some(self.version.clone());
+        record.expires_at_ms = self.remaining_ttl_ms(Instant::now()).map(|ttl_ms| physical_time() + ttl_ms);
+        record
+    }
+
+    /// The value held by a `Put` record, or `None` if it has expired since.
+    pub fn from_log_record(record: LogRecord) -> Option<Self> {
+        let expires_at: Option<Instant> = match record.expires_at_ms {
+            Some(expires_at_ms) => {
+                let now_ms: u64 = physical_time();
+                if expires_at_ms <= now_ms {
+                    return None;
+                }
+                Some(Instant::now() + Duration::from_millis(expires_at_ms - now_ms))
+            },
+            None => None,
+        };
+        Some(StoredValue { value: record.value, version: record.version.unwrap_or_default(), expires_at })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageEngineKind {
+    HashMap,
+    BTreeMap,
+    Disk,
+}
+
+impl FromStr for StorageEngineKind {
+    type Err = Error;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "hashmap" => Ok(StorageEngineKind::HashMap),
+            "btreemap" => Ok(StorageEngineKind::BTreeMap),
+            "disk" => Ok(StorageEngineKind::Disk),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("Invalid storage engine: {}", value))),
+        }
+    }
+}
+
+/// Where a node keeps its key-value pairs.
+///
+/// Engines account for the memory they use so that the node can keep it
+/// within its limit; engines keeping values on disk only count what they
+/// hold in memory.
+pub trait StorageEngine: Send {
+    /// The value stored under `key`, failing if it could not be read.
+    fn get(&self, key: &[u8]) -> Result<Option<StoredValue>>;
+
+    fn put(&mut self, key: Vec<u8>, value: StoredValue) -> Result<()>;
+
+    /// Removes `key`, returning the value it held.
+    fn delete(&mut self, key: &[u8]) -> Result<Option<StoredValue>>;
+
+    fn wipe(&mut self) -> Result<()>;
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (Vec<u8>, StoredValue)> + '_>;
+
+    /// Iterates over the keys alone, which unlike `iter` never touches the disk.
+    fn keys(&self) -> Box<dyn Iterator<Item = &Vec<u8>> + '_>;
+
+    /// Keys from `start` up to but excluding `end`, in lexicographic order.
+    ///
+    /// Engines without ordered keys put the keys in range in a heap, which
+    /// takes linear time, and take them out in order as they are iterated,
+    /// so that a scan reading a page of keys does not sort all of them.
+    fn range(&self, start: &[u8], end: Option<&[u8]>) -> Box<dyn Iterator<Item = &Vec<u8>> + '_> {
+        let mut heap: BinaryHeap<Reverse<&Vec<u8>>> = self.keys()
+            .filter(|key| key.as_slice() >= start && end.is_none_or(|end| key.as_slice() < end))
+            .map(Reverse)
+            .collect();
+        Box::new(std::iter::from_fn(move || heap.pop().map(|Reverse(key)| key)))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Memory used by the stored pairs
+    fn memory_usage(&self) -> u64;
+
+    /// Memory a pair with a key of `key_len` bytes and a value of
+    /// `value_len` bytes would use.
+    fn get_entry_mem_usage(&self, key_len: usize, value_len: usize) -> u64;
+
+    /// Memory used by the pair stored under `key`, if any.
+    fn get_stored_mem_usage(&self, key: &[u8]) -> u64;
+
+    fn get_expired_keys(&self, now: Instant) -> Vec<Vec<u8>> {
+        self.iter()
+            .filter(|(_, stored)| stored.is_expired(now))
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Whether the pairs outlive the process, in which case the node does
+    /// not need a write-ahead log.
+    fn is_persistent(&self) -> bool {
+        false
+    }
+}
+
+/// Unordered in-memory engine
+#[derive(Default)]
+pub struct HashMapEngine {
+    map: HashMap<Vec<u8>, StoredValue>,
+    mem_usage: u64,
+}
+
+impl StorageEngine for HashMapEngine {
+    fn get(&self, key: &[u8]) -> Result<Option<StoredValue>> {
+        Ok(self.map.get(key).cloned())
+    }
+
+    fn put(&mut self, key: Vec<u8>, value: StoredValue) -> Result<()> {
+        // An overwritten value gives its memory back
+        self.mem_usage -= self.get_stored_mem_usage(&key);
+        self.mem_usage += self.get_entry_mem_usage(key.len(), value.value.len());
+        self.map.insert(key, value);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Option<StoredValue>> {
+        self.mem_usage -= self.get_stored_mem_usage(key);
+        Ok(self.map.remove(key))
+    }
+
+    fn wipe(&mut self) -> Result<()> {
+        self.map = HashMap::new();
+        self.mem_usage = 0;
+        Ok(())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (Vec<u8>, StoredValue)> + '_> {
+        Box::new(self.map.iter().map(|(key, stored)| (key.clone(), stored.clone())))
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &Vec<u8>> + '_> {
+        Box::new(self.map.keys())
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.map.contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn memory_usage(&self) -> u64 {
+        self.mem_usage
+    }
+
+    fn get_entry_mem_usage(&self, key_len: usize, value_len: usize) -> u64 {
+        (key_len + value_len) as u64 + HASH_MAP_ENTRY_OVERHEAD_BYTES
+    }
+
+    fn get_stored_mem_usage(&self, key: &[u8]) -> u64 {
+        match self.map.get(key) {
+            Some(stored) => self.get_entry_mem_usage(key.len(), stored.value.len()),
+            None => 0,
+        }
+    }
+
+    fn get_expired_keys(&self, now: Instant) -> Vec<Vec<u8>> {
+        self.map
+            .iter()
+            .filter(|(_, stored)| stored.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+/// In-memory engine keeping keys in order
+#[derive(Default)]
+pub struct BTreeMapEngine {
+    map: BTreeMap<Vec<u8>, StoredValue>,
+    mem_usage: u64,
+}
+
+impl StorageEngine for BTreeMapEngine {
+    fn get(&self, key: &[u8]) -> Result<Option<StoredValue>> {
+        Ok(self.map.get(key).cloned())
+    }
+
+    fn put(&mut self, key: Vec<u8>, value: StoredValue) -> Result<()> {
+        self.mem_usage -= self.get_stored_mem_usage(&key);
+        self.mem_usage += self.get_entry_mem_usage(key.len(), value.value.len());
+        self.map.insert(key, value);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<Option<StoredValue>> {
+        self.mem_usage -= self.get_stored_mem_usage(key);
+        Ok(self.map.remove(key))
+    }
+
+    fn wipe(&mut self) -> Result<()> {
+        self.map = BTreeMap::new();
+        self.mem_usage = 0;
+        Ok(())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (Vec<u8>, StoredValue)> + '_> {
+        Box::new(self.map.iter().map(|(key, stored)| (key.clone(), stored.clone())))
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &Vec<u8>> + '_> {
+        Box::new(self.map.keys())
+    }
+
+    fn range(&self, start: &[u8], end: Option<&[u8]>) -> Box<dyn Iterator<Item = &Vec<u8>> + '_> {
+        // BTreeMap::range panics on an empty interval
+        if end.is_some_and(|end| end <= start) {
+            return Box::new(std::iter::empty());
+        }
+        let end_bound: Bound<&[u8]> = match end {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        Box::new(self.map.range::<[u8], _>((Bound::Included(start), end_bound)).map(|(key, _)| key))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.map.contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn memory_usage(&self) -> u64 {
+        self.mem_usage
+    }
+
+    fn get_entry_mem_usage(&self, key_len: usize, value_len: usize) -> u64 {
+        (key_len + value_len) as u64 + BTREE_MAP_ENTRY_OVERHEAD_BYTES
+    }
+
+    fn get_stored_mem_usage(&self, key: &[u8]) -> u64 {
+        match self.map.get(key) {
+            Some(stored) => self.get_entry_mem_usage(key.len(), stored.value.len()),
+            None => 0,
+        }
+    }
+
+    fn get_expired_keys(&self, now: Instant) -> Vec<Vec<u8>> {
+        self.map
+            .iter()
+            .filter(|(_, stored)| stored.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub fn create_value(value: &[u8], timestamp: u64) -> StoredValue {
+        let mut version: Version = Version::new();
+        version.timestamp = timestamp;
+        StoredValue { value: value.to_vec(), version, expires_at: None }
+    }
+
+    /// Checks the behaviour every engine must share.
+    pub fn check_engine<S: StorageEngine>(engine: &mut S) {
+        assert!(engine.is_empty());
+        assert_eq!(engine.memory_usage(), 0);
+
+        engine.put(b"a".to_vec(), create_value(b"1", 1)).unwrap();
+        engine.put(b"b".to_vec(), create_value(b"22", 2)).unwrap();
+        assert_eq!(engine.get(b"a").unwrap(), Some(create_value(b"1", 1)));
+        assert_eq!(engine.len(), 2);
+        assert!(engine.contains_key(b"b"));
+        assert_eq!(engine.memory_usage(), engine.get_stored_mem_usage(b"a") + engine.get_stored_mem_usage(b"b"));
+
+        // Overwriting replaces the value and its memory usage
+        let mem_usage: u64 = engine.memory_usage();
+        engine.put(b"a".to_vec(), create_value(b"3", 3)).unwrap();
+        assert_eq!(engine.get(b"a").unwrap(), Some(create_value(b"3", 3)));
+        assert_eq!(engine.memory_usage(), mem_usage);
+
+        assert_eq!(engine.delete(b"a").unwrap(), Some(create_value(b"3", 3)));
+        assert_eq!(engine.delete(b"a").unwrap(), None);
+        assert_eq!(engine.get(b"a").unwrap(), None);
+        assert_eq!(engine.memory_usage(), engine.get_stored_mem_usage(b"b"));
+
+        let mut expiring: StoredValue = create_value(b"4", 4);
+        expiring.expires_at = Some(Instant::now());
+        engine.put(b"c".to_vec(), expiring).unwrap();
+        assert_eq!(engine.get_expired_keys(Instant::now()), vec![b"c".to_vec()]);
+
+        let mut entries: Vec<(Vec<u8>, StoredValue)> = engine.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], (b"b".to_vec(), create_value(b"22", 2)));
+        let mut keys: Vec<&Vec<u8>> = engine.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec![&b"b".to_vec(), &b"c".to_vec()]);
+
+        engine.put(b"ba".to_vec(), create_value(b"5", 5)).unwrap();
+        let range: Vec<&Vec<u8>> = engine.range(b"b", Some(b"c")).collect();
+        assert_eq!(range, vec![&b"b".to_vec(), &b"ba".to_vec()]);
+        let range: Vec<&Vec<u8>> = engine.range(b"b0", None).collect();
+        assert_eq!(range, vec![&b"ba".to_vec(), &b"c".to_vec()]);
+        assert_eq!(engine.range(b"c", Some(b"b")).count(), 0);
+
+        engine.wipe().unwrap();
+        assert!(engine.is_empty());
+        assert_eq!(engine.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_hash_map_engine() {
+        check_engine(&mut HashMapEngine::default());
+    }
+
+    #[test]
+    fn test_btree_map_engine() {
+        check_engine(&mut BTreeMapEngine::default());
+    }
+
+    #[test]
+    fn test_btree_map_engine_iterates_in_order() {
+        let mut engine: BTreeMapEngine = BTreeMapEngine::default();
+        for key in [b"c", b"a", b"b"] {
+            engine.put(key.to_vec(), create_value(key, 1)).unwrap();
+        }
+        let keys: Vec<Vec<u8>> = engine.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+    }
+
+    #[test]
+    fn test_log_record_round_trip() {
+        let mut stored: StoredValue = create_value(b"1", 7);
+        assert_eq!(StoredValue::from_log_record(stored.to_log_record(b"a".to_vec())), Some(stored.clone()));
+
+        stored.expires_at = Some(Instant::now() + Duration::from_secs(60));
+        let restored: StoredValue = StoredValue::from_log_record(stored.to_log_record(b"a".to_vec())).unwrap();
+        assert!(restored.remaining_ttl_ms(Instant::now()).unwrap() > 59_000);
+
+        stored.expires_at = Some(Instant::now());
+        assert_eq!(StoredValue::from_log_record(stored.to_log_record(b"a".to_vec())), None);
+    }
+
+    #[test]
+    fn test_storage_engine_kind_from_str() {
+        assert_eq!("hashmap".parse::<StorageEngineKind>().unwrap(), StorageEngineKind::HashMap);
+        assert_eq!("btreemap".parse::<StorageEngineKind>().unwrap(), StorageEngineKind::BTreeMap);
+        assert_eq!("disk".parse::<StorageEngineKind>().unwrap(), StorageEngineKind::Disk);
+        assert!("sqlite".parse::<StorageEngineKind>().is_err());
+    }
+}
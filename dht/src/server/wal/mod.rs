@@ -0,0 +1,308 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use crc::{Crc, CRC_32_CKSUM};
+use protobuf::Message;
+
+use crate::comm::protogen::api::LogRecord;
+
+const WAL_FILE_NAME: &str = "wal.log";
+const SNAPSHOT_FILE_NAME: &str = "snapshot";
+const SNAPSHOT_TEMP_FILE_NAME: &str = "snapshot.tmp";
+const RECORD_HEADER_SIZE_BYTES: usize = 8;
+const RECORD_CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_CKSUM);
+
+/// Append-only log of the changes made to the data store, along with the
+/// snapshot that the log starts from.
+///
+/// Each record is framed as its length and CRC, both big-endian `u32`s,
+/// followed by the serialized `LogRecord`. A record torn by a crash fails
+/// its length or CRC check and everything from it onwards is dropped.
+/// Snapshots use the same framing and replace the log once written, so a
+/// corrupt snapshot cannot be made up for and the log refuses to open.
+pub struct WriteAheadLog {
+    file: File,
+    path: PathBuf,
+    data_dir: PathBuf,
+    len: u64,
+}
+
+impl WriteAheadLog {
+    /// Opens the log in `data_dir`, creating it if needed, and returns the
+    /// records of the last snapshot followed by those logged since. Fails
+    /// with `InvalidData` if the snapshot is corrupt.
+    pub fn open(data_dir: &Path) -> Result<(Self, Vec<LogRecord>)> {
+        fs::create_dir_all(data_dir)?;
+        let mut records: Vec<LogRecord> = read_snapshot(data_dir)?;
+
+        let path: PathBuf = data_dir.join(WAL_FILE_NAME);
+        let mut file: File = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+
+        let mut bytes: Vec<u8> = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let (log_records, valid_len) = decode_records(&bytes);
+        if valid_len < bytes.len() {
+            log::warn!("Dropping {} B of torn or corrupt records from {}", bytes.len() - valid_len, path.display());
+            file.set_len(valid_len as u64)?;
+        }
+
+        log::info!("Replaying {} records from {}", log_records.len(), path.display());
+        records.extend(log_records);
+        let wal: WriteAheadLog = WriteAheadLog { file, path, data_dir: data_dir.to_path_buf(), len: valid_len as u64 };
+        Ok((wal, records))
+    }
+
+    /// Appends `record` and waits for it to reach the disk.
+    pub fn append(&mut self, record: &LogRecord) -> Result<()> {
+        let bytes: Vec<u8> = encode_record(record)?;
+        self.file.write_all(&bytes)?;
+        self.file.sync_data()?;
+        self.len += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Replaces the snapshot with `records` and empties the log, returning
+    /// the number of records written.
+    ///
+    /// The snapshot is written to a temporary file and renamed into place,
+    /// so a crash part way through leaves the previous snapshot and the
+    /// full log behind.
+    pub fn write_snapshot<I: IntoIterator<Item = LogRecord>>(&mut self, records: I) -> Result<usize> {
+        let temp_path: PathBuf = self.data_dir.join(SNAPSHOT_TEMP_FILE_NAME);
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(&temp_path)?);
+        let mut num_records: usize = 0;
+        for record in records {
+            writer.write_all(&encode_record(&record)?)?;
+            num_records += 1;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        fs::rename(&temp_path, self.data_dir.join(SNAPSHOT_FILE_NAME))?;
+        File::open(&self.data_dir)?.sync_all()?;
+
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.len = 0;
+
+        log::info!("Wrote snapshot of {} records to {}", num_records, self.data_dir.display());
+        Ok(num_records)
+    }
+
+    /// Size in bytes of the records logged since the last snapshot
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn read_snapshot(data_dir: &Path) -> Result<Vec<LogRecord>> {
+    let path: PathBuf = data_dir.join(SNAPSHOT_FILE_NAME);
+    let bytes: Vec<u8> = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    // The log only holds what changed since the snapshot, so starting
+    // without part of it would silently lose keys
+    let (records, valid_len) = decode_records(&bytes);
+    if valid_len < bytes.len() {
+        log::error!("Snapshot {} is corrupt after {} records", path.display(), records.len());
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Snapshot {} is corrupt after {} records", path.display(), records.len())
+        ));
+    }
+    log::info!("Loading {} records from {}", records.len(), path.display());
+    Ok(records)
+}
+
+/// Frames `record` with its length and CRC.
+pub fn encode_record(record: &LogRecord) -> Result<Vec<u8>> {
+    let payload: Vec<u8> = record.write_to_bytes()?;
+    let mut bytes: Vec<u8> = Vec::with_capacity(RECORD_HEADER_SIZE_BYTES + payload.len());
+    bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&RECORD_CRC.checksum(&payload).to_be_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+/// Decodes the record at the start of `bytes`, returning it along with the
+/// number of bytes it spans, or `None` if it is torn or corrupt.
+pub fn decode_record(bytes: &[u8]) -> Option<(LogRecord, usize)> {
+    if bytes.len() < RECORD_HEADER_SIZE_BYTES {
+        return None;
+    }
+    let payload_len: usize = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let checksum: u32 = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+
+    if bytes.len() - RECORD_HEADER_SIZE_BYTES < payload_len {
+        return None;
+    }
+    let payload: &[u8] = &bytes[RECORD_HEADER_SIZE_BYTES..RECORD_HEADER_SIZE_BYTES + payload_len];
+    if RECORD_CRC.checksum(payload) != checksum {
+        return None;
+    }
+    match LogRecord::parse_from_bytes(payload) {
+        Ok(record) => Some((record, RECORD_HEADER_SIZE_BYTES + payload_len)),
+        Err(_) => None,
+    }
+}
+
+/// Decodes records up to the first invalid one, returning them along with
+/// the number of bytes they span.
+fn decode_records(bytes: &[u8]) -> (Vec<LogRecord>, usize) {
+    let mut records: Vec<LogRecord> = Vec::new();
+    let mut offset: usize = 0;
+
+    while let Some((record, record_len)) = decode_record(&bytes[offset..]) {
+        records.push(record);
+        offset += record_len;
+    }
+
+    (records, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comm::proto::Operation;
+
+    fn create_data_dir(name: &str) -> PathBuf {
+        let data_dir: PathBuf = std::env::temp_dir().join(format!("dht-wal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        data_dir
+    }
+
+    fn create_record(operation: Operation, key: &[u8], value: &[u8]) -> LogRecord {
+        let mut record: LogRecord = LogRecord::new();
+        record.operation = operation as u32;
+        record.key = key.to_vec();
+        record.value = value.to_vec();
+        record
+    }
+
+    #[test]
+    fn test_records_are_replayed_in_order() {
+        let data_dir: PathBuf = create_data_dir("replay");
+        let records: Vec<LogRecord> = vec![
+            create_record(Operation::Put, b"a", b"1"),
+            create_record(Operation::Delete, b"a", b""),
+            create_record(Operation::Wipe, b"", b""),
+        ];
+
+        let (mut wal, replayed) = WriteAheadLog::open(&data_dir).unwrap();
+        assert!(replayed.is_empty());
+        for record in records.iter() {
+            wal.append(record).unwrap();
+        }
+        drop(wal);
+
+        let (_, replayed) = WriteAheadLog::open(&data_dir).unwrap();
+        assert_eq!(replayed, records);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_record_is_dropped() {
+        let data_dir: PathBuf = create_data_dir("torn");
+        let (mut wal, _) = WriteAheadLog::open(&data_dir).unwrap();
+        wal.append(&create_record(Operation::Put, b"a", b"1")).unwrap();
+        let path: PathBuf = wal.path().to_path_buf();
+        drop(wal);
+
+        // Half of a second record, as if the node crashed while writing it
+        let torn: Vec<u8> = encode_record(&create_record(Operation::Put, b"b", b"2")).unwrap();
+        let mut file: File = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&torn[..torn.len() / 2]).unwrap();
+        let valid_len: u64 = fs::metadata(&path).unwrap().len() - (torn.len() / 2) as u64;
+
+        let (mut wal, replayed) = WriteAheadLog::open(&data_dir).unwrap();
+        assert_eq!(replayed, vec![create_record(Operation::Put, b"a", b"1")]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), valid_len);
+
+        // New records follow the last valid one
+        wal.append(&create_record(Operation::Put, b"c", b"3")).unwrap();
+        let (_, replayed) = WriteAheadLog::open(&data_dir).unwrap();
+        assert_eq!(replayed.len(), 2);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_replaces_log() {
+        let data_dir: PathBuf = create_data_dir("snapshot");
+        let (mut wal, _) = WriteAheadLog::open(&data_dir).unwrap();
+        wal.append(&create_record(Operation::Put, b"a", b"1")).unwrap();
+        wal.append(&create_record(Operation::Put, b"a", b"2")).unwrap();
+        wal.append(&create_record(Operation::Put, b"b", b"3")).unwrap();
+        wal.append(&create_record(Operation::Delete, b"b", b"")).unwrap();
+        assert!(!wal.is_empty());
+
+        let snapshot: Vec<LogRecord> = vec![create_record(Operation::Put, b"a", b"2")];
+        assert_eq!(wal.write_snapshot(snapshot.clone()).unwrap(), 1);
+        assert!(wal.is_empty());
+        assert_eq!(fs::metadata(wal.path()).unwrap().len(), 0);
+
+        wal.append(&create_record(Operation::Put, b"c", b"4")).unwrap();
+        drop(wal);
+
+        let (_, replayed) = WriteAheadLog::open(&data_dir).unwrap();
+        assert_eq!(replayed, vec![snapshot[0].clone(), create_record(Operation::Put, b"c", b"4")]);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_unfinished_snapshot_is_ignored() {
+        let data_dir: PathBuf = create_data_dir("unfinished");
+        let (mut wal, _) = WriteAheadLog::open(&data_dir).unwrap();
+        wal.append(&create_record(Operation::Put, b"a", b"1")).unwrap();
+        drop(wal);
+
+        // A crash before the rename leaves only the temporary file
+        fs::write(data_dir.join(SNAPSHOT_TEMP_FILE_NAME), b"partial").unwrap();
+
+        let (_, replayed) = WriteAheadLog::open(&data_dir).unwrap();
+        assert_eq!(replayed, vec![create_record(Operation::Put, b"a", b"1")]);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_snapshot_fails_open() {
+        let data_dir: PathBuf = create_data_dir("corrupt-snapshot");
+        let (mut wal, _) = WriteAheadLog::open(&data_dir).unwrap();
+        wal.write_snapshot(vec![create_record(Operation::Put, b"a", b"1"), create_record(Operation::Put, b"b", b"2")]).unwrap();
+        wal.append(&create_record(Operation::Put, b"c", b"3")).unwrap();
+        drop(wal);
+
+        let snapshot_path: PathBuf = data_dir.join(SNAPSHOT_FILE_NAME);
+        let mut bytes: Vec<u8> = fs::read(&snapshot_path).unwrap();
+        let last: usize = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        fs::write(&snapshot_path, bytes).unwrap();
+
+        let e: Error = WriteAheadLog::open(&data_dir).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_record_stops_replay() {
+        let mut bytes: Vec<u8> = encode_record(&create_record(Operation::Put, b"a", b"1")).unwrap();
+        let second: Vec<u8> = encode_record(&create_record(Operation::Put, b"b", b"2")).unwrap();
+        let first_len: usize = bytes.len();
+        bytes.extend_from_slice(&second);
+        let last: usize = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+
+        let (records, valid_len) = decode_records(&bytes);
+        assert_eq!(records.len(), 1);
+        assert_eq!(valid_len, first_len);
+    }
+}
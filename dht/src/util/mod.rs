@@ -1,4 +1,4 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader};
 use std::net::SocketAddr;
 use std::path::Path;
@@ -23,6 +23,24 @@ pub fn read_socket_addresses(server_file_path: &Path) -> Result<Vec<SocketAddr>,
     Ok(addresses)
 }
 
+/// Resident set size of this process in bytes.
+pub fn get_resident_memory() -> Result<u64, io::Error> {
+    let status: String = fs::read_to_string("/proc/self/status")?;
+    for line in status.lines() {
+        if let Some(rss) = line.strip_prefix("VmRSS:") {
+            let rss_kb: u64 = rss
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            return Ok(rss_kb * 1024);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound, "Resident memory not reported"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -38,7 +56,7 @@ mod tests {
             SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080),
             SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8081),
         ];
-        writeln!(file, "{}", format!("{}\n{}", addrs[0], addrs[1])).unwrap();
+        writeln!(file, "{}\n{}", addrs[0], addrs[1]).unwrap();
 
         let result = read_socket_addresses(Path::new(test_file_path)).unwrap();
         remove_file(test_file_path).unwrap();
@@ -55,7 +73,7 @@ mod tests {
             SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080),
             SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8081),
         ];
-        writeln!(file, "{}", format!("{}\n{}\n\n", addrs[0], addrs[1])).unwrap();
+        writeln!(file, "{}\n{}\n\n", addrs[0], addrs[1]).unwrap();
 
         let result = read_socket_addresses(Path::new(test_file_path)).unwrap();
         remove_file(test_file_path).unwrap();
@@ -72,11 +90,22 @@ mod tests {
             SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080),
             SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8081),
         ];
-        writeln!(file, "{}", format!(" {} \n{} \n ", addrs[0], addrs[1])).unwrap();
+        writeln!(file, " {} \n{} \n ", addrs[0], addrs[1]).unwrap();
 
         let result = read_socket_addresses(Path::new(test_file_path)).unwrap();
         remove_file(test_file_path).unwrap();
 
         assert_eq!(result, addrs);
     }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_get_resident_memory() {
+        let before: u64 = get_resident_memory().unwrap();
+        let buffer: Vec<u8> = vec![1; 16 * 1024 * 1024];
+        let after: u64 = get_resident_memory().unwrap();
+
+        assert!(before > 0);
+        assert!(after >= before + (buffer.len() as u64) / 2);
+    }
 }
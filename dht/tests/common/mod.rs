@@ -6,12 +6,18 @@ use log4rs::append::console::ConsoleAppender;
 use log4rs::encode::pattern::PatternEncoder;
 use log4rs::config::{Appender, Config, Root};
 use rand::{self, Rng, RngCore};
-use std::io::{Error, ErrorKind, Result};
+use std::io::{Error, Result};
 use std::net::{SocketAddr, UdpSocket};
+use std::process::{Child, Command, Stdio};
 
 use dht::comm::ProtoInterface;
-use dht::comm::proto::{extract_reply, Operation, Status};
-use dht::comm::protogen::api::{Request, Reply};
+use dht::comm::transport::TcpTransport;
+use dht::comm::proto::{extract_reply, Consistency, Operation, Status};
+use dht::comm::protogen::api::{KeyValue, Request, Reply, Version};
+use protobuf::MessageField;
+
+/// How long a graceful shutdown may take to hand the keys off
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 lazy_static! {
     static ref CLIENT_ADDR: SocketAddr = {
@@ -25,7 +31,7 @@ pub fn init_logger() {
 
     // Appenders
     let stdout = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new(&pattern)))
+        .encoder(Box::new(PatternEncoder::new(pattern)))
         .build();
 
     // Initialize the loggers
@@ -37,8 +43,32 @@ pub fn init_logger() {
     let _handle = log4rs::init_config(config).unwrap();
 }
 
+/// Starts a server on a free port with the given extra arguments, giving it
+/// `wait_time_sec` to come up.
+pub fn start_server(server_id: u32, args: &[&str], wait_time_sec: u64) -> (Child, SocketAddr) {
+    let addr: SocketAddr = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let child: Child = Command::new(env!("CARGO_BIN_EXE_dht"))
+        .args(["-p", &addr.port().to_string()])
+        .args(["-s", &server_id.to_string()])
+        .args(["-l", "info"])
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    std::thread::sleep(std::time::Duration::from_secs(wait_time_sec));
+    (child, addr)
+}
+
 pub fn get_proto_interface() -> Result<ProtoInterface> {
-    Ok(ProtoInterface::new(*CLIENT_ADDR)?)
+    ProtoInterface::new(*CLIENT_ADDR)
+}
+
+/// A client talking TCP, listening on a port of its own so that tests can
+/// run side by side.
+pub fn get_tcp_proto_interface() -> Result<ProtoInterface<TcpTransport>> {
+    ProtoInterface::bind("127.0.0.1:0".parse().unwrap())
 }
 
 pub fn ping_servers(server_addrs: Vec<SocketAddr>, should_panic_if_fail: bool) -> Result<()> {
@@ -74,7 +104,7 @@ pub fn ping_servers(server_addrs: Vec<SocketAddr>, should_panic_if_fail: bool) -
             if should_panic_if_fail {
                 panic!("Ping failed: {:?}", reply.status);
             } else {
-                return Err(Error::new(ErrorKind::Other, "Ping did not return SUCCESS"));
+                return Err(Error::other("Ping did not return SUCCESS"));
             }
         }
     }
@@ -101,21 +131,31 @@ pub fn wipe_servers(server_addrs: Vec<SocketAddr>, wait_time_sec: u64) -> Result
     std::thread::sleep(std::time::Duration::from_secs(wait_time_sec));
 
     if failed {
-        Err(Error::new(ErrorKind::Other, "Wipe failed"))
+        Err(Error::other("Wipe failed"))
     } else {
         Ok(())
     }
 }
 
 pub fn shutdown_servers(server_addrs: Vec<SocketAddr>, wait_time_sec: u64) -> Result<()> {
+    send_shutdown(server_addrs, Operation::Shutdown, wait_time_sec)
+}
+
+pub fn hard_shutdown_servers(server_addrs: Vec<SocketAddr>, wait_time_sec: u64) -> Result<()> {
+    send_shutdown(server_addrs, Operation::HardShutdown, wait_time_sec)
+}
+
+fn send_shutdown(server_addrs: Vec<SocketAddr>, operation: Operation, wait_time_sec: u64) -> Result<()> {
     let proto_interface = get_proto_interface()?;
     let mut failed = false;
+    let operation: u32 = operation as u32;
 
     for server_addr in server_addrs {
         log::info!("Shutting down server at {}", server_addr);
         let mut request: Request = Request::new();
-        request.operation = Operation::Shutdown as u32;
-        let (reply_msg, _) = proto_interface.send_and_recv(request, server_addr)?;
+        request.operation = operation;
+        // A graceful shutdown is only answered once the keys are handed off
+        let (reply_msg, _) = proto_interface.send_and_recv_within(request, server_addr, SHUTDOWN_TIMEOUT)?;
         let reply: Reply = extract_reply(&reply_msg)?;
         if reply.status != Status::Success as u32 {
             log::error!("Shutdown failed for server at {} with status code {:?}", server_addr, reply.status);
@@ -127,7 +167,7 @@ pub fn shutdown_servers(server_addrs: Vec<SocketAddr>, wait_time_sec: u64) -> Re
     std::thread::sleep(std::time::Duration::from_secs(wait_time_sec));
 
     if failed {
-        Err(Error::new(ErrorKind::Other, "Shutdown failed"))
+        Err(Error::other("Shutdown failed"))
     } else {
         Ok(())
     }
@@ -157,7 +197,7 @@ pub fn put_rand_key_value(server_addr: SocketAddr) -> Result<(Vec<u8>, Vec<u8>,
     Ok((key, value, status))
 }
 
-pub fn get_value(server_addr: SocketAddr, key: &Vec<u8>) -> Result<(Option<Vec<u8>>, u32)> {
+pub fn get_value(server_addr: SocketAddr, key: &[u8]) -> Result<(Option<Vec<u8>>, u32)> {
     let proto_interface = get_proto_interface()?;
 
     let mut request: Request = Request::new();
@@ -170,7 +210,99 @@ pub fn get_value(server_addr: SocketAddr, key: &Vec<u8>) -> Result<(Option<Vec<u
     Ok((reply.value, reply.status))
 }
 
-pub fn delete_key_value(server_addr: SocketAddr, key: &Vec<u8>) -> Result<(Option<Vec<u8>>, u32)> {
+pub fn put_versioned_key_value(
+    server_addr: SocketAddr,
+    key: &[u8],
+    value: &[u8],
+    version: Option<Version>
+) -> Result<(Option<Version>, u32)> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::Put as u32;
+    request.key = Some(key.to_vec());
+    request.value = Some(value.to_vec());
+    request.version = MessageField::from_option(version);
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    let reply: Reply = extract_reply(&reply_msg)?;
+
+    Ok((reply.version.into_option(), reply.status))
+}
+
+pub fn put_key_value_with_ttl(server_addr: SocketAddr, key: &[u8], value: &[u8], ttl_ms: u64) -> Result<u32> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::Put as u32;
+    request.key = Some(key.to_vec());
+    request.value = Some(value.to_vec());
+    request.ttl_ms = Some(ttl_ms);
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    let reply: Reply = extract_reply(&reply_msg)?;
+
+    Ok(reply.status)
+}
+
+pub fn get_versioned_value(server_addr: SocketAddr, key: &[u8]) -> Result<(Option<Vec<u8>>, Option<Version>, u32)> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::Get as u32;
+    request.key = Some(key.to_vec());
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    let reply: Reply = extract_reply(&reply_msg)?;
+
+    Ok((reply.value, reply.version.into_option(), reply.status))
+}
+
+pub fn compare_and_swap(
+    server_addr: SocketAddr,
+    key: &[u8],
+    value: &[u8],
+    expected_value: Option<Vec<u8>>,
+    expected_version: Option<Version>
+) -> Result<Reply> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::CompareAndSwap as u32;
+    request.key = Some(key.to_vec());
+    request.value = Some(value.to_vec());
+    request.expected_value = expected_value;
+    request.version = MessageField::from_option(expected_version);
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    extract_reply(&reply_msg)
+}
+
+pub fn put_if_absent(server_addr: SocketAddr, key: &[u8], value: &[u8]) -> Result<Reply> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::PutIfAbsent as u32;
+    request.key = Some(key.to_vec());
+    request.value = Some(value.to_vec());
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    extract_reply(&reply_msg)
+}
+
+pub fn delete_if_value(server_addr: SocketAddr, key: &[u8], expected_value: &[u8]) -> Result<Reply> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::DeleteIfValue as u32;
+    request.key = Some(key.to_vec());
+    request.expected_value = Some(expected_value.to_vec());
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    extract_reply(&reply_msg)
+}
+
+pub fn delete_key_value(server_addr: SocketAddr, key: &[u8]) -> Result<(Option<Vec<u8>>, u32)> {
     let proto_interface = get_proto_interface()?;
 
     let mut request: Request = Request::new();
@@ -183,6 +315,146 @@ pub fn delete_key_value(server_addr: SocketAddr, key: &Vec<u8>) -> Result<(Optio
     Ok((reply.value, reply.status))
 }
 
+pub fn get_memory_usage(server_addr: SocketAddr) -> Result<Reply> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::GetMemoryUsage as u32;
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    extract_reply(&reply_msg)
+}
+
+pub fn snapshot(server_addr: SocketAddr) -> Result<u32> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::Snapshot as u32;
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    let reply: Reply = extract_reply(&reply_msg)?;
+
+    Ok(reply.status)
+}
+
+/// Builds a scan request; a `prefix` makes it a PrefixScan.
+pub fn create_scan_request(
+    start: Option<&[u8]>,
+    end: Option<&[u8]>,
+    prefix: Option<&[u8]>,
+    limit: Option<u32>,
+    include_values: bool
+) -> Request {
+    let mut request: Request = Request::new();
+    request.operation = match prefix {
+        Some(_) => Operation::PrefixScan as u32,
+        None => Operation::Scan as u32,
+    };
+    request.key = start.map(|start| start.to_vec());
+    request.end_key = end.map(|end| end.to_vec());
+    request.prefix = prefix.map(|prefix| prefix.to_vec());
+    request.limit = limit;
+    request.include_values = Some(include_values);
+    request
+}
+
+pub fn scan(server_addr: SocketAddr, request: Request) -> Result<Reply> {
+    let proto_interface = get_proto_interface()?;
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    extract_reply(&reply_msg)
+}
+
+/// Follows `request` through every page of the scan, returning the entries
+/// and the number of pages.
+pub fn scan_all_pages(server_addr: SocketAddr, mut request: Request) -> Result<(Vec<KeyValue>, usize)> {
+    let mut entries: Vec<KeyValue> = Vec::new();
+    let mut num_pages: usize = 0;
+    loop {
+        let reply: Reply = scan(server_addr, request.clone())?;
+        if reply.status != Status::Success as u32 {
+            return Err(Error::other(format!("Scan failed with status {}", reply.status)));
+        }
+        num_pages += 1;
+        request.limit = request.limit.map(|limit| limit - reply.entries.len() as u32);
+        entries.extend(reply.entries);
+
+        match reply.next_key {
+            Some(next_key) => request.key = Some(next_key),
+            None => return Ok((entries, num_pages)),
+        }
+    }
+}
+
+/// Sends an Increment or Decrement of the counter in `key`, by one unless
+/// `delta` is given.
+pub fn update_counter(server_addr: SocketAddr, operation: Operation, key: &[u8], delta: Option<i64>) -> Result<Reply> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = operation as u32;
+    request.key = Some(key.to_vec());
+    request.delta = delta;
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    extract_reply(&reply_msg)
+}
+
+/// Sends an Append, SetRange or GetRange request for `key`.
+pub fn update_range(
+    server_addr: SocketAddr,
+    operation: Operation,
+    key: &[u8],
+    value: Option<&[u8]>,
+    offset: Option<u64>,
+    length: Option<u64>
+) -> Result<Reply> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = operation as u32;
+    request.key = Some(key.to_vec());
+    request.value = value.map(|value| value.to_vec());
+    request.offset = offset;
+    request.length = length;
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    extract_reply(&reply_msg)
+}
+
+/// Sends a MultiGet, MultiPut or MultiDelete of `entries`, returning the
+/// entry replied for each key.
+pub fn run_batch(server_addr: SocketAddr, operation: Operation, entries: Vec<KeyValue>) -> Result<(Vec<KeyValue>, u32)> {
+    run_batch_with_consistency(server_addr, operation, entries, Consistency::One)
+}
+
+/// Sends a batch as `run_batch` does, applied to or read from as many
+/// copies of each key as `consistency` asks for.
+pub fn run_batch_with_consistency(
+    server_addr: SocketAddr,
+    operation: Operation,
+    entries: Vec<KeyValue>,
+    consistency: Consistency
+) -> Result<(Vec<KeyValue>, u32)> {
+    let proto_interface = get_proto_interface()?;
+
+    let mut request: Request = Request::new();
+    request.operation = operation as u32;
+    request.entries = entries;
+    request.consistency = Some(consistency as u32);
+
+    let (reply_msg, _server_socket) = proto_interface.send_and_recv(request, server_addr)?;
+    let reply: Reply = extract_reply(&reply_msg)?;
+
+    Ok((reply.entries, reply.status))
+}
+
+pub fn create_batch_entry(key: &[u8], value: &[u8]) -> KeyValue {
+    let mut entry: KeyValue = KeyValue::new();
+    entry.key = key.to_vec();
+    entry.value = value.to_vec();
+    entry
+}
+
 pub fn get_rand_bytes(min_len: usize, max_len: usize) -> Vec<u8> {
     let len = rand::thread_rng().gen_range(min_len..max_len);
     get_bytes(len)
@@ -0,0 +1,55 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::{Consistency, Operation, Status};
+use dht::comm::protogen::api::KeyValue;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const NUM_KEYS: usize = 30;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Batch_Spans_Every_Node() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 0);
+
+    let pairs: Vec<(Vec<u8>, Vec<u8>)> = (0..NUM_KEYS).map(|_| (common::get_rand_key(), common::get_rand_bytes(8, 256))).collect();
+    let entries: Vec<KeyValue> = pairs.iter().map(|(key, value)| common::create_batch_entry(key, value)).collect();
+    let (results, status) = common::run_batch_with_consistency(MULTI_SERVER_ADDRS[0], Operation::MultiPut, entries, Consistency::All).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert!(results.iter().all(|result| result.status == Some(Status::Success as u32)));
+
+    // Each key went to its own node, so any node can read it back
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        let server_addr: SocketAddr = MULTI_SERVER_ADDRS[i % MULTI_SERVER_ADDRS.len()];
+        let (value_received, status) = common::get_value(server_addr, key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(value_received, Some(value.clone()));
+    }
+
+    let entries: Vec<KeyValue> = pairs.iter().map(|(key, _)| common::create_batch_entry(key, &[])).collect();
+    let (results, status) = common::run_batch(MULTI_SERVER_ADDRS[1], Operation::MultiGet, entries.clone()).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    for (result, (key, value)) in results.iter().zip(pairs.iter()) {
+        assert_eq!(result.status, Some(Status::Success as u32));
+        assert_eq!(result.key, *key);
+        assert_eq!(result.value, *value);
+    }
+
+    // Every copy is deleted before the reply, so no read finds one left
+    let (results, status) = common::run_batch_with_consistency(MULTI_SERVER_ADDRS[2], Operation::MultiDelete, entries.clone(), Consistency::All).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert!(results.iter().all(|result| result.status == Some(Status::Success as u32)));
+
+    let (results, _) = common::run_batch(MULTI_SERVER_ADDRS[0], Operation::MultiGet, entries).unwrap();
+    assert!(results.iter().all(|result| result.status == Some(Status::KeyNotFound as u32)));
+
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
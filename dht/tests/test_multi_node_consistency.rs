@@ -0,0 +1,99 @@
+#![allow(non_snake_case)]
+
+use dht::comm::ProtoInterface;
+use dht::comm::proto::{extract_reply, Consistency, Operation, Status};
+use dht::comm::protogen::api::{Request, Reply, Version};
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const KEY_VALUE_SIZE_BYTES: usize = 64;
+const INVALID_CONSISTENCY: u32 = 3;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+fn send_request(
+    proto_interface: &ProtoInterface,
+    server_addr: SocketAddr,
+    operation: Operation,
+    key: &[u8],
+    value: Option<Vec<u8>>,
+    consistency: u32
+) -> Reply {
+    let mut request = Request::new();
+    request.operation = operation as u32;
+    request.key = Some(key.to_vec());
+    request.value = value;
+    request.consistency = Some(consistency);
+
+    let (reply_msg, _) = proto_interface.send_and_recv(request, server_addr).unwrap();
+    extract_reply(&reply_msg).unwrap()
+}
+
+#[test]
+fn Put_Get_Success_Every_Consistency() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+    let proto_interface = common::get_proto_interface().unwrap();
+
+    for consistency in [Consistency::One, Consistency::Quorum, Consistency::All] {
+        let consistency: u32 = consistency as u32;
+        let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+        let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+        let reply: Reply = send_request(&proto_interface, MULTI_SERVER_ADDRS[0], Operation::Put, &key, Some(value.clone()), consistency);
+        assert_eq!(reply.status, Status::Success as u32);
+        let version: Version = reply.version.unwrap();
+
+        for server_addr in MULTI_SERVER_ADDRS.iter() {
+            let reply: Reply = send_request(&proto_interface, *server_addr, Operation::Get, &key, None, consistency);
+            assert_eq!(reply.status, Status::Success as u32);
+            assert_eq!(reply.value.unwrap(), value);
+            assert_eq!(reply.version.unwrap(), version);
+        }
+    }
+
+    drop(proto_interface);
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
+
+#[test]
+fn Delete_All_Get_All_KeyNotFound() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+
+    let (key, value, status) = common::put_rand_key_value(MULTI_SERVER_ADDRS[0]).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let proto_interface = common::get_proto_interface().unwrap();
+    let all: u32 = Consistency::All as u32;
+
+    let reply: Reply = send_request(&proto_interface, MULTI_SERVER_ADDRS[1], Operation::Delete, &key, None, all);
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(reply.value.unwrap(), value);
+
+    // No replica is left holding the deleted value
+    let reply: Reply = send_request(&proto_interface, MULTI_SERVER_ADDRS[2], Operation::Get, &key, None, all);
+    assert_eq!(reply.status, Status::KeyNotFound as u32);
+
+    drop(proto_interface);
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
+
+#[test]
+fn Invalid_Consistency_InvalidValue() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+    let proto_interface = common::get_proto_interface().unwrap();
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let reply: Reply = send_request(&proto_interface, MULTI_SERVER_ADDRS[0], Operation::Put, &key, Some(value), INVALID_CONSISTENCY);
+    assert_eq!(reply.status, Status::InvalidValue as u32);
+
+    let reply: Reply = send_request(&proto_interface, MULTI_SERVER_ADDRS[0], Operation::Get, &key, None, INVALID_CONSISTENCY);
+    assert_eq!(reply.status, Status::InvalidValue as u32);
+}
@@ -0,0 +1,79 @@
+#![allow(non_snake_case)]
+
+use dht::comm::ProtoInterface;
+use dht::comm::proto::{extract_reply, Consistency, Operation, Status};
+use dht::comm::protogen::api::{Request, Reply};
+use std::thread;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const NUM_INCREMENTS: usize = 20;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Concurrent_Increments_Through_Every_Node() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    // Every increment lands on the key's coordinator, so none are lost
+    let handles: Vec<thread::JoinHandle<()>> = MULTI_SERVER_ADDRS
+        .iter()
+        .map(|server_addr| {
+            let (server_addr, key) = (*server_addr, key.clone());
+            thread::spawn(move || {
+                // Each client needs its own socket to run alongside the others
+                let proto_interface = ProtoInterface::new("127.0.0.1:0".parse().unwrap()).unwrap();
+                let mut request: Request = Request::new();
+                request.operation = Operation::Increment as u32;
+                request.key = Some(key);
+                for _ in 0..NUM_INCREMENTS {
+                    let (reply_msg, _) = proto_interface.send_and_recv(request.clone(), server_addr).unwrap();
+                    let reply: Reply = extract_reply(&reply_msg).unwrap();
+                    assert_eq!(reply.status, Status::Success as u32);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let expected: i64 = (NUM_INCREMENTS * MULTI_SERVER_ADDRS.len()) as i64;
+    for server_addr in MULTI_SERVER_ADDRS.iter() {
+        let (value, status) = common::get_value(*server_addr, &key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(value, Some(expected.to_string().into_bytes()));
+    }
+
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
+
+#[test]
+fn Increment_At_Quorum_Through_Every_Node() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+    let key: Vec<u8> = common::get_rand_key();
+    let status: u32 = common::put_key_value(MULTI_SERVER_ADDRS[0], &Some(key.clone()), &Some(b"40".to_vec())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let proto_interface = ProtoInterface::new("127.0.0.1:0".parse().unwrap()).unwrap();
+    let mut request: Request = Request::new();
+    request.operation = Operation::Increment as u32;
+    request.key = Some(key.clone());
+    request.consistency = Some(Consistency::Quorum as u32);
+    for (i, server_addr) in MULTI_SERVER_ADDRS.iter().enumerate() {
+        let (reply_msg, _) = proto_interface.send_and_recv(request.clone(), *server_addr).unwrap();
+        let reply: Reply = extract_reply(&reply_msg).unwrap();
+        assert_eq!(reply.status, Status::Success as u32);
+        assert_eq!(reply.counter, Some(41 + i as i64));
+    }
+    drop(proto_interface);
+
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
@@ -0,0 +1,111 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::{extract_reply, Operation, Status};
+use dht::comm::protogen::api::{Request, Reply};
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const NUM_KEYS: usize = 64;
+const JOINING_SERVER_ID: u32 = 4;
+const JOIN_WAIT_TIME_SEC: u64 = 2;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Join_Leave_Keys_Handed_Off() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for i in 0..NUM_KEYS {
+        let (key, value, status) = common::put_rand_key_value(MULTI_SERVER_ADDRS[i % MULTI_SERVER_ADDRS.len()]).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+
+    let bootstrap: String = MULTI_SERVER_ADDRS[0].to_string();
+    let (mut child, joined_addr) = common::start_server(JOINING_SERVER_ID, &["-b", &bootstrap], JOIN_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![joined_addr], true);
+
+    let proto_interface = common::get_proto_interface().unwrap();
+    let mut num_keys_on_joined_node: usize = 0;
+    for (key, value) in pairs.iter() {
+        let mut request = Request::new();
+        request.operation = Operation::Get as u32;
+        request.key = Some(key.clone());
+
+        let (reply_msg, _) = proto_interface.send_and_recv(request, joined_addr).unwrap();
+        let reply: Reply = extract_reply(&reply_msg).unwrap();
+        assert_eq!(reply.status, Status::Success as u32);
+        assert_eq!(reply.value.unwrap(), *value);
+
+        // Only the copy held by the joined node itself answers a replica read
+        let mut request = Request::new();
+        request.operation = Operation::ReplicaGet as u32;
+        request.key = Some(key.clone());
+        let (reply_msg, _) = proto_interface.send_and_recv(request, joined_addr).unwrap();
+        if extract_reply(&reply_msg).unwrap().status == Status::Success as u32 {
+            num_keys_on_joined_node += 1;
+        }
+    }
+    drop(proto_interface);
+
+    log::info!("Joined node holds a copy of {} of {} keys", num_keys_on_joined_node, NUM_KEYS);
+    assert!(num_keys_on_joined_node > 0);
+
+    let result = common::shutdown_servers(vec![joined_addr], 1);
+    let _ = child.wait();
+    assert!(result.is_ok());
+
+    // The joined node handed its keys back before leaving
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        let (retrived_value_opt, status) = common::get_value(MULTI_SERVER_ADDRS[i % MULTI_SERVER_ADDRS.len()], key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrived_value_opt.unwrap(), *value);
+    }
+
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
+
+#[test]
+fn Join_Leave_Large_Values_Handed_Off() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+    const NUM_LARGE_KEYS: usize = 16;
+    const VALUE_SIZE_BYTES: usize = 1024 * 6;
+
+    // Any two of these values are larger than a handoff batch
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for i in 0..NUM_LARGE_KEYS {
+        let key: Vec<u8> = common::get_rand_key();
+        let value: Vec<u8> = common::get_bytes(VALUE_SIZE_BYTES);
+        let status: u32 = common::put_key_value(MULTI_SERVER_ADDRS[i % MULTI_SERVER_ADDRS.len()], &Some(key.clone()), &Some(value.clone())).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+
+    let bootstrap: String = MULTI_SERVER_ADDRS[0].to_string();
+    let (mut child, joined_addr) = common::start_server(JOINING_SERVER_ID, &["-b", &bootstrap], JOIN_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![joined_addr], true);
+    for (key, value) in pairs.iter() {
+        let (retrived_value_opt, status) = common::get_value(joined_addr, key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrived_value_opt.unwrap(), *value);
+    }
+
+    let result = common::shutdown_servers(vec![joined_addr], 1);
+    let _ = child.wait();
+    assert!(result.is_ok());
+
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        let (retrived_value_opt, status) = common::get_value(MULTI_SERVER_ADDRS[i % MULTI_SERVER_ADDRS.len()], key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrived_value_opt.unwrap(), *value);
+    }
+
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
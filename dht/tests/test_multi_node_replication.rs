@@ -0,0 +1,96 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::Status;
+use std::time::Duration;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const NUM_KEYS: usize = 64;
+const REPLICATION_FACTOR: usize = 2;
+const JOINING_SERVER_ID: u32 = 5;
+const JOIN_WAIT_TIME_SEC: u64 = 2;
+const FAILURE_DETECTION_WAIT_TIME_SEC: u64 = 8;
+const DEAD_DETECTION_WAIT_TIME_SEC: u64 = 12;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+// Expects the cluster to run with the same replication factor
+#[test]
+fn Crashed_Node_Keys_Served_By_Replicas() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+
+    let bootstrap: String = MULTI_SERVER_ADDRS[0].to_string();
+    let replication_factor: String = REPLICATION_FACTOR.to_string();
+    let (mut child, joined_addr) = common::start_server(
+        JOINING_SERVER_ID,
+        &["-r", &replication_factor, "-b", &bootstrap],
+        JOIN_WAIT_TIME_SEC
+    );
+    let _result = common::ping_servers(vec![joined_addr], true);
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for _ in 0..NUM_KEYS {
+        let (key, value, status) = common::put_rand_key_value(joined_addr).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+
+    // The node goes down without handing off any of its keys
+    let result = common::hard_shutdown_servers(vec![joined_addr], 0);
+    let _ = child.wait();
+    assert!(result.is_ok());
+    std::thread::sleep(Duration::from_secs(FAILURE_DETECTION_WAIT_TIME_SEC));
+
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        let (retrived_value_opt, status) = common::get_value(MULTI_SERVER_ADDRS[i % MULTI_SERVER_ADDRS.len()], key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrived_value_opt.unwrap(), *value);
+    }
+
+    // The crashed node leaves the ring before another test joins one
+    std::thread::sleep(Duration::from_secs(DEAD_DETECTION_WAIT_TIME_SEC - FAILURE_DETECTION_WAIT_TIME_SEC));
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
+
+#[test]
+fn Crashed_Owner_Keys_Served_By_Replicas_Before_Suspected() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+
+    let bootstrap: String = MULTI_SERVER_ADDRS[0].to_string();
+    let replication_factor: String = REPLICATION_FACTOR.to_string();
+    let (mut child, joined_addr) = common::start_server(
+        JOINING_SERVER_ID,
+        &["-r", &replication_factor, "-b", &bootstrap],
+        JOIN_WAIT_TIME_SEC
+    );
+    let _result = common::ping_servers(vec![joined_addr], true);
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for _ in 0..NUM_KEYS {
+        let (key, value, status) = common::put_rand_key_value(joined_addr).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+
+    // Reads start right away, while the other nodes still take the crashed
+    // one for alive
+    let result = common::hard_shutdown_servers(vec![joined_addr], 0);
+    let _ = child.wait();
+    assert!(result.is_ok());
+
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        let (retrived_value_opt, status) = common::get_value(MULTI_SERVER_ADDRS[i % MULTI_SERVER_ADDRS.len()], key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrived_value_opt.unwrap(), *value);
+    }
+
+    // The crashed node leaves the ring before another test joins one
+    std::thread::sleep(Duration::from_secs(DEAD_DETECTION_WAIT_TIME_SEC));
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
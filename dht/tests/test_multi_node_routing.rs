@@ -0,0 +1,108 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::{extract_reply, Operation, Status};
+use dht::comm::protogen::api::{Request, Reply};
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const KEY_VALUE_SIZE_BYTES: usize = 64;
+const NUM_KEYS: usize = 32;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Put_Get_Success_From_Every_Node() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+
+    for put_addr in MULTI_SERVER_ADDRS.iter() {
+        let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+        let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+        let status: u32 = common::put_key_value(*put_addr, &Some(key.clone()), &Some(value.clone())).unwrap();
+        assert_eq!(status, Status::Success as u32);
+
+        for get_addr in MULTI_SERVER_ADDRS.iter() {
+            let (retrived_value_opt, status) = common::get_value(*get_addr, &key).unwrap();
+            assert_eq!(status, Status::Success as u32);
+
+            let retrived_value: Vec<u8> = retrived_value_opt.ok_or("GET failed. No value present").unwrap();
+            assert_eq!(retrived_value, value);
+        }
+    }
+
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
+
+#[test]
+fn Put_Many_Get_Success() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+    let num_servers: usize = MULTI_SERVER_ADDRS.len();
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for i in 0..NUM_KEYS {
+        let (key, value, status) = common::put_rand_key_value(MULTI_SERVER_ADDRS[i % num_servers]).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        let (retrived_value_opt, status) = common::get_value(MULTI_SERVER_ADDRS[(i + 1) % num_servers], key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrived_value_opt.unwrap(), *value);
+    }
+
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
+
+#[test]
+fn Put_Delete_Get_KeyNotFound_Across_Nodes() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let status: u32 = common::put_key_value(MULTI_SERVER_ADDRS[0], &Some(key.clone()), &Some(value.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let (deleted_value_opt, status) = common::delete_key_value(MULTI_SERVER_ADDRS[1], &key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(deleted_value_opt.unwrap(), value);
+
+    let (_, status) = common::get_value(MULTI_SERVER_ADDRS[2], &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
+
+#[test]
+fn Get_Reply_Relayed_By_Node_Asked() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+
+    let (key, value, status) = common::put_rand_key_value(MULTI_SERVER_ADDRS[0]).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let proto_interface = common::get_proto_interface().unwrap();
+
+    for server_addr in MULTI_SERVER_ADDRS.iter() {
+        let mut request = Request::new();
+        request.operation = Operation::Get as u32;
+        request.key = Some(key.clone());
+
+        // The node asked waits on the owner's reply, so that it can read
+        // from a replica instead should the owner not answer
+        let (reply_msg, reply_addr) = proto_interface.send_and_recv(request, *server_addr).unwrap();
+        let reply: Reply = extract_reply(&reply_msg).unwrap();
+        assert_eq!(reply.status, Status::Success as u32);
+        assert_eq!(reply.value.unwrap(), value);
+        assert_eq!(reply_addr, *server_addr);
+    }
+
+    drop(proto_interface);
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
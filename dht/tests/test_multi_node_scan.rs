@@ -0,0 +1,92 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::Status;
+use dht::comm::protogen::api::KeyValue;
+use std::time::Duration;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const VALUE_SIZE_BYTES: usize = 512;
+const NUM_KEYS: usize = 48;
+const REPLICATION_FACTOR: usize = 2;
+const JOINING_SERVER_ID: u32 = 6;
+const JOIN_WAIT_TIME_SEC: u64 = 2;
+const DEAD_DETECTION_WAIT_TIME_SEC: u64 = 12;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Scan_Gathers_Keys_From_Every_Node() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+    let num_servers: usize = MULTI_SERVER_ADDRS.len();
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for i in 0..NUM_KEYS {
+        let key: Vec<u8> = format!("scan-{:02}", i).into_bytes();
+        let value: Vec<u8> = common::get_bytes(VALUE_SIZE_BYTES);
+        let status: u32 = common::put_key_value(MULTI_SERVER_ADDRS[i % num_servers], &Some(key.clone()), &Some(value.clone())).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+
+    // Keys come back once each and in order, whichever node is asked,
+    // even though replicas hold several copies of them
+    for server_addr in MULTI_SERVER_ADDRS.iter() {
+        let request = common::create_scan_request(None, None, Some(b"scan-"), None, true);
+        let (entries, num_pages) = common::scan_all_pages(*server_addr, request).unwrap();
+        assert!(num_pages > 1);
+        let pairs_found: Vec<(Vec<u8>, Vec<u8>)> = entries
+            .into_iter()
+            .map(|entry: KeyValue| (entry.key, entry.value))
+            .collect();
+        assert_eq!(pairs_found, pairs);
+    }
+
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
+
+// Expects the cluster to run with the same replication factor
+#[test]
+fn Scan_Crashed_Node_Keys_Served_By_Replicas() {
+    let _result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+
+    let bootstrap: String = MULTI_SERVER_ADDRS[0].to_string();
+    let replication_factor: String = REPLICATION_FACTOR.to_string();
+    let (mut child, joined_addr) = common::start_server(
+        JOINING_SERVER_ID,
+        &["-r", &replication_factor, "-b", &bootstrap],
+        JOIN_WAIT_TIME_SEC
+    );
+    let _result = common::ping_servers(vec![joined_addr], true);
+
+    let mut keys: Vec<Vec<u8>> = Vec::new();
+    for i in 0..NUM_KEYS {
+        let key: Vec<u8> = format!("scan-crash-{:02}", i).into_bytes();
+        let status: u32 = common::put_key_value(joined_addr, &Some(key.clone()), &Some(common::get_rand_value())).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        keys.push(key);
+    }
+
+    // Scans start right away, while the other nodes still take the crashed
+    // one for alive and fail to get its keys from it
+    let result = common::hard_shutdown_servers(vec![joined_addr], 0);
+    let _ = child.wait();
+    assert!(result.is_ok());
+
+    for server_addr in MULTI_SERVER_ADDRS.iter() {
+        let request = common::create_scan_request(None, None, Some(b"scan-crash-"), None, false);
+        let (entries, _) = common::scan_all_pages(*server_addr, request).unwrap();
+        let scanned_keys: Vec<Vec<u8>> = entries.into_iter().map(|entry: KeyValue| entry.key).collect();
+        assert_eq!(scanned_keys, keys);
+    }
+
+    // The crashed node leaves the ring before another test joins one
+    std::thread::sleep(Duration::from_secs(DEAD_DETECTION_WAIT_TIME_SEC));
+    let _ = common::wipe_servers(MULTI_SERVER_ADDRS.clone(), 1);
+}
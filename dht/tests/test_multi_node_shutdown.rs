@@ -0,0 +1,21 @@
+#![allow(non_snake_case)]
+
+mod common;
+mod tests_prelude;
+use tests_prelude::*;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+// Tearing down the whole cluster, so there is no point in handing keys off
+#[test]
+fn HardShutdown_Success() {
+    let _ = common::ping_servers(MULTI_SERVER_ADDRS.clone(), true);
+    let result = common::hard_shutdown_servers(MULTI_SERVER_ADDRS.clone(), 5);
+    assert!(result.is_ok());
+
+    let result = common::ping_servers(MULTI_SERVER_ADDRS.clone(), false);
+    assert!(result.is_err());
+}
@@ -0,0 +1,125 @@
+#![cfg(feature = "async")]
+#![allow(non_snake_case)]
+
+use std::sync::Arc;
+use dht::comm::ProtoInterface;
+use dht::comm::async_interface::AsyncProtoInterface;
+use dht::comm::proto::{extract_reply, Operation, Status};
+use dht::comm::protogen::api::{Request, Reply};
+use dht::comm::transport::TcpTransport;
+use tokio::task::JoinHandle;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const SERVER_ID: u32 = 16;
+const NUM_REQUESTS: usize = 512;
+const START_WAIT_TIME_SEC: u64 = 1;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+async fn send_request(
+    proto_interface: &AsyncProtoInterface,
+    server_addr: SocketAddr,
+    operation: Operation,
+    key: &[u8],
+    value: Option<Vec<u8>>
+) -> Reply {
+    let mut request: Request = Request::new();
+    request.operation = operation as u32;
+    request.key = Some(key.to_vec());
+    request.value = value;
+    let (reply_msg, _) = proto_interface.send_and_recv(request, server_addr).await.unwrap();
+    extract_reply(&reply_msg).unwrap()
+}
+
+async fn hard_shutdown(proto_interface: &AsyncProtoInterface, server_addr: SocketAddr) {
+    let mut request: Request = Request::new();
+    request.operation = Operation::HardShutdown as u32;
+    let _ = proto_interface.send_and_recv(request, server_addr).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn Many_Requests_In_Flight_Over_One_Interface() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &["--async"], START_WAIT_TIME_SEC);
+    let proto_interface: Arc<AsyncProtoInterface> = Arc::new(
+        AsyncProtoInterface::bind("127.0.0.1:0".parse().unwrap()).await.unwrap()
+    );
+
+    let requests: Vec<JoinHandle<()>> = (0..NUM_REQUESTS)
+        .map(|_| {
+            let proto_interface: Arc<AsyncProtoInterface> = proto_interface.clone();
+            tokio::spawn(async move {
+                let key: Vec<u8> = common::get_rand_key();
+                let value: Vec<u8> = common::get_rand_value();
+                let reply: Reply = send_request(&proto_interface, server_addr, Operation::Put, &key, Some(value.clone())).await;
+                assert_eq!(reply.status, Status::Success as u32);
+
+                let reply: Reply = send_request(&proto_interface, server_addr, Operation::Get, &key, None).await;
+                assert_eq!(reply.status, Status::Success as u32);
+                assert_eq!(reply.value.unwrap(), value);
+            })
+        })
+        .collect();
+    for request in requests {
+        request.await.unwrap();
+    }
+
+    hard_shutdown(&proto_interface, server_addr).await;
+    let _ = child.wait();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn Put_Get_Fragmented_Value() {
+    let (mut child, server_addr) = common::start_server(
+        SERVER_ID,
+        &["--async", "--max-value-size", "64"],
+        START_WAIT_TIME_SEC
+    );
+    let proto_interface: AsyncProtoInterface = AsyncProtoInterface::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+    let key: Vec<u8> = common::get_rand_key();
+    let value: Vec<u8> = common::get_bytes(1024 * 64);
+    let reply: Reply = send_request(&proto_interface, server_addr, Operation::Put, &key, Some(value.clone())).await;
+    assert_eq!(reply.status, Status::Success as u32);
+
+    let reply: Reply = send_request(&proto_interface, server_addr, Operation::Get, &key, None).await;
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(reply.value.unwrap(), value);
+
+    hard_shutdown(&proto_interface, server_addr).await;
+    let _ = child.wait();
+}
+
+#[test]
+fn Put_Get_Over_Tcp() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &["--async"], START_WAIT_TIME_SEC);
+    let proto_interface: ProtoInterface<TcpTransport> = common::get_tcp_proto_interface().unwrap();
+
+    let key: Vec<u8> = common::get_rand_key();
+    let value: Vec<u8> = common::get_rand_value();
+    let mut request: Request = Request::new();
+    request.operation = Operation::Put as u32;
+    request.key = Some(key.clone());
+    request.value = Some(value.clone());
+    let (reply_msg, _) = proto_interface.send_and_recv(request, server_addr).unwrap();
+    assert_eq!(extract_reply(&reply_msg).unwrap().status, Status::Success as u32);
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::Get as u32;
+    request.key = Some(key);
+    let (reply_msg, _) = proto_interface.send_and_recv(request, server_addr).unwrap();
+    let reply: Reply = extract_reply(&reply_msg).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(reply.value.unwrap(), value);
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::HardShutdown as u32;
+    let _ = proto_interface.send_and_recv(request, server_addr);
+    let _ = child.wait();
+}
@@ -1,9 +1,12 @@
 #![allow(non_snake_case)]
 
-use dht::comm::proto::{extract_reply, Operation, Status};
-use dht::comm::protogen::api::{Request, Reply};
+use dht::comm::proto::{self, extract_reply, Operation, Status};
+use dht::comm::protogen::api::{Request, Reply, UDPMessage, Version};
+use protobuf::MessageField;
 
 use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 mod common;
 mod tests_prelude;
@@ -79,7 +82,8 @@ fn Put_InvalidValueSize() {
 }
 
 #[test]
-fn test_large_PUT_WouldBlock() {
+fn Put_Fragmented_InvalidValueSize() {
+    // Larger than a datagram, so the request arrives in fragments
     const VALUE_SIZE_BYTES: usize = 13 * 1024;
 
     let _result = common::ping_servers(vec![*SERVER_ADDR], true);
@@ -87,13 +91,8 @@ fn test_large_PUT_WouldBlock() {
     let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
     let value: Vec<u8> = common::get_bytes(VALUE_SIZE_BYTES);
 
-    match common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(value.clone())) {
-        Ok(_) => panic!("The server should not be responding to packets exceeding 12KB, you have {} B", VALUE_SIZE_BYTES),
-        Err(e) => match e.kind() {
-            ErrorKind::WouldBlock => log::info!("Expected port blocking occurred, test passed"),
-            _ => panic!("The server should not be responding with error: {}", e)
-        }
-    }
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(value.clone())).unwrap();
+    assert_eq!(status, Status::InvalidValueSize as u32);
 }
 
 #[test]
@@ -266,6 +265,115 @@ fn Put_Delete_Get_KeyNotFound() {
     let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
 }
 
+#[test]
+fn Put_Put_Get_Version_Increases() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let (first_version_opt, status) = common::put_versioned_key_value(*SERVER_ADDR, &key, &value, None).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    let (second_version_opt, status) = common::put_versioned_key_value(*SERVER_ADDR, &key, &value, None).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let first_version: Version = first_version_opt.unwrap();
+    let second_version: Version = second_version_opt.unwrap();
+    assert!((first_version.timestamp, first_version.counter) < (second_version.timestamp, second_version.counter));
+
+    let (_, retrieved_version_opt, status) = common::get_versioned_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(retrieved_version_opt.unwrap(), second_version);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn Put_With_Version_Orders_After_It() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    // A version from a node whose clock runs a minute ahead
+    let now_ms: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    let mut seen_version: Version = Version::new();
+    seen_version.timestamp = now_ms + 60 * 1000;
+    seen_version.counter = 7;
+
+    let (version_opt, status) = common::put_versioned_key_value(*SERVER_ADDR, &key, &value, Some(seen_version.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let version: Version = version_opt.unwrap();
+    assert!((seen_version.timestamp, seen_version.counter) < (version.timestamp, version.counter));
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn Put_With_Version_Too_Far_Ahead_Rejected() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let now_ms: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    let mut far_version: Version = Version::new();
+    far_version.timestamp = now_ms + 60 * 60 * 1000;
+    let (_, status) = common::put_versioned_key_value(*SERVER_ADDR, &key, &value, Some(far_version)).unwrap();
+    assert_eq!(status, Status::InvalidValue as u32);
+
+    let mut full_version: Version = Version::new();
+    full_version.timestamp = now_ms;
+    full_version.counter = u32::MAX;
+    let (_, status) = common::put_versioned_key_value(*SERVER_ADDR, &key, &value, Some(full_version)).unwrap();
+    assert_eq!(status, Status::InvalidValue as u32);
+
+    let (_, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn Put_Delete_Older_Copy_Not_Brought_Back() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let (version_opt, status) = common::put_versioned_key_value(*SERVER_ADDR, &key, &value, None).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    let (_, status) = common::delete_key_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    // A copy of the key from a node that missed the delete
+    let proto_interface = common::get_proto_interface().unwrap();
+    let mut request: Request = Request::new();
+    request.operation = Operation::ReplicaPut as u32;
+    request.key = Some(key.clone());
+    request.value = Some(value.clone());
+    request.version = MessageField::from_option(version_opt.clone());
+    let (reply_msg, _) = proto_interface.send_and_recv(request, *SERVER_ADDR).unwrap();
+    assert_eq!(extract_reply(&reply_msg).unwrap().status, Status::Success as u32);
+
+    // Other copies are told which delete the key is missing since
+    let mut request: Request = Request::new();
+    request.operation = Operation::ReplicaGet as u32;
+    request.key = Some(key.clone());
+    let (reply_msg, _) = proto_interface.send_and_recv(request, *SERVER_ADDR).unwrap();
+    let reply: Reply = extract_reply(&reply_msg).unwrap();
+    assert_eq!(reply.status, Status::KeyNotFound as u32);
+    let deleted_version: Version = reply.version.unwrap();
+    let version: Version = version_opt.unwrap();
+    assert!((version.timestamp, version.counter) < (deleted_version.timestamp, deleted_version.counter));
+    drop(proto_interface);
+
+    let (_, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
 #[test]
 fn Undefined_Operation() {
     let _result = common::ping_servers(vec![*SERVER_ADDR], true);
@@ -280,3 +388,70 @@ fn Undefined_Operation() {
 
     let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
 }
+
+#[test]
+fn Pipelined_Puts_Then_Gets_Matched_To_Requests() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+    let proto_interface = common::get_proto_interface().unwrap();
+    const NUM_KEYS: usize = 256;
+
+    let pairs: Vec<(Vec<u8>, Vec<u8>)> = (0..NUM_KEYS)
+        .map(|_| (common::get_rand_key(), common::get_rand_value()))
+        .collect();
+    let put_requests: Vec<(Request, SocketAddr)> = pairs
+        .iter()
+        .map(|(key, value)| {
+            let mut request: Request = Request::new();
+            request.operation = Operation::Put as u32;
+            request.key = Some(key.clone());
+            request.value = Some(value.clone());
+            (request, *SERVER_ADDR)
+        })
+        .collect();
+    for reply in proto_interface.send_and_recv_all(put_requests) {
+        let (reply_msg, _server_socket) = reply.unwrap();
+        assert_eq!(extract_reply(&reply_msg).unwrap().status, Status::Success as u32);
+    }
+
+    let get_requests: Vec<(Request, SocketAddr)> = pairs
+        .iter()
+        .map(|(key, _)| {
+            let mut request: Request = Request::new();
+            request.operation = Operation::Get as u32;
+            request.key = Some(key.clone());
+            (request, *SERVER_ADDR)
+        })
+        .collect();
+    let replies = proto_interface.send_and_recv_all(get_requests);
+    for ((_, value), reply) in pairs.iter().zip(replies) {
+        let (reply_msg, _server_socket) = reply.unwrap();
+        let reply: Reply = extract_reply(&reply_msg).unwrap();
+        assert_eq!(reply.status, Status::Success as u32);
+        assert_eq!(reply.value.unwrap(), *value);
+    }
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn Reply_Address_From_Client_Ignored() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+    let proto_interface = common::get_proto_interface().unwrap();
+    let bystander: UdpSocket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    bystander.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::Ping as u32;
+    let mut request_msg: UDPMessage = proto::create_udp_message(request, SERVER_ADDR.ip(), 0).unwrap();
+    proto::set_reply_addr(&mut request_msg, bystander.local_addr().unwrap());
+    proto_interface.forward(&request_msg, *SERVER_ADDR).unwrap();
+
+    // Only a node of the ring may have replies sent elsewhere
+    let (reply_msg, server_addr) = proto_interface.listen().unwrap();
+    assert_eq!(server_addr, *SERVER_ADDR);
+    assert_eq!(extract_reply(&reply_msg).unwrap().status, Status::Success as u32);
+
+    let mut buf: [u8; 64] = [0; 64];
+    let e = bystander.recv_from(&mut buf).unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut));
+}
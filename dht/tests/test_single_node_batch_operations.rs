@@ -0,0 +1,139 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::{Operation, Status};
+use dht::comm::protogen::api::KeyValue;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+fn get_statuses(entries: &[KeyValue]) -> Vec<u32> {
+    entries.iter().map(|entry| entry.status.unwrap()).collect()
+}
+
+#[test]
+fn MultiPut_Then_MultiGet_Success() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+
+    let pairs: Vec<(Vec<u8>, Vec<u8>)> = (0..20).map(|_| (common::get_rand_key(), common::get_rand_bytes(8, 256))).collect();
+    let entries: Vec<KeyValue> = pairs.iter().map(|(key, value)| common::create_batch_entry(key, value)).collect();
+    let (results, status) = common::run_batch(*SERVER_ADDR, Operation::MultiPut, entries).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(get_statuses(&results), vec![Status::Success as u32; pairs.len()]);
+    assert!(results.iter().all(|result| result.version.is_some()));
+
+    for (key, value) in pairs.iter() {
+        let (value_received, status) = common::get_value(*SERVER_ADDR, key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(value_received, Some(value.clone()));
+    }
+
+    let entries: Vec<KeyValue> = pairs.iter().map(|(key, _)| common::create_batch_entry(key, &[])).collect();
+    let (results, status) = common::run_batch(*SERVER_ADDR, Operation::MultiGet, entries).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    for (result, (key, value)) in results.iter().zip(pairs.iter()) {
+        assert_eq!(result.status, Some(Status::Success as u32));
+        assert_eq!(result.key, *key);
+        assert_eq!(result.value, *value);
+    }
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn MultiGet_Reports_Status_Per_Key() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+
+    let (key, value, _) = common::put_rand_key_value(*SERVER_ADDR).unwrap();
+    let entries: Vec<KeyValue> = vec![
+        common::create_batch_entry(b"missing-1", &[]),
+        common::create_batch_entry(&key, &[]),
+        common::create_batch_entry(b"missing-2", &[]),
+    ];
+    let (results, status) = common::run_batch(*SERVER_ADDR, Operation::MultiGet, entries).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(get_statuses(&results), vec![
+        Status::KeyNotFound as u32,
+        Status::Success as u32,
+        Status::KeyNotFound as u32,
+    ]);
+    assert_eq!(results[1].value, value);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn MultiPut_Rejects_Oversized_Value_Only() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+
+    let entries: Vec<KeyValue> = vec![
+        common::create_batch_entry(b"small", b"value"),
+        common::create_batch_entry(b"big", &common::get_bytes(1024 * 11)),
+    ];
+    let (results, status) = common::run_batch(*SERVER_ADDR, Operation::MultiPut, entries).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(get_statuses(&results), vec![Status::Success as u32, Status::InvalidValueSize as u32]);
+
+    let (_, status) = common::get_value(*SERVER_ADDR, b"big").unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn MultiGet_Too_Large_For_Reply() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+
+    let value: Vec<u8> = common::get_bytes(1024 * 5);
+    for key in [b"a", b"b", b"c"] {
+        let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.to_vec()), &Some(value.clone())).unwrap();
+        assert_eq!(status, Status::Success as u32);
+    }
+
+    // The values that do not fit are left for a smaller batch
+    let entries: Vec<KeyValue> = [b"a", b"b", b"c"].iter().map(|key| common::create_batch_entry(*key, &[])).collect();
+    let (results, status) = common::run_batch(*SERVER_ADDR, Operation::MultiGet, entries).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(get_statuses(&results), vec![
+        Status::Success as u32,
+        Status::Success as u32,
+        Status::InvalidValueSize as u32,
+    ]);
+    assert_eq!(results[1].value, value);
+    assert!(results[2].value.is_empty());
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn MultiDelete_Success() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+
+    let (key, _, _) = common::put_rand_key_value(*SERVER_ADDR).unwrap();
+    let entries: Vec<KeyValue> = vec![
+        common::create_batch_entry(&key, &[]),
+        common::create_batch_entry(b"missing", &[]),
+    ];
+    let (results, status) = common::run_batch(*SERVER_ADDR, Operation::MultiDelete, entries).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(get_statuses(&results), vec![Status::Success as u32, Status::KeyNotFound as u32]);
+
+    let (_, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+
+    let (_, status) = common::run_batch(*SERVER_ADDR, Operation::MultiDelete, Vec::new()).unwrap();
+    assert_eq!(status, Status::MissingKey as u32);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
@@ -0,0 +1,196 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::Status;
+use dht::comm::protogen::api::{Reply, Version};
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const KEY_VALUE_SIZE_BYTES: usize = 64;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn CompareAndSwap_Value_Success() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let old_value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let new_value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(old_value.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let reply: Reply = common::compare_and_swap(*SERVER_ADDR, &key, &new_value, Some(old_value), None).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+
+    let (retrived_value_opt, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(retrived_value_opt.unwrap(), new_value);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn CompareAndSwap_Value_PreconditionFailed() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let wrong_value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES + 1);
+
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(value.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    // The current value comes back so the client can retry
+    let reply: Reply = common::compare_and_swap(*SERVER_ADDR, &key, &wrong_value, Some(wrong_value.clone()), None).unwrap();
+    assert_eq!(reply.status, Status::PreconditionFailed as u32);
+    assert_eq!(reply.value.unwrap(), value);
+
+    let (retrived_value_opt, _) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(retrived_value_opt.unwrap(), value);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn CompareAndSwap_Version_Success_Then_Stale() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let (version_opt, status) = common::put_versioned_key_value(*SERVER_ADDR, &key, &value, None).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    let version: Version = version_opt.unwrap();
+
+    let reply: Reply = common::compare_and_swap(*SERVER_ADDR, &key, &value, None, Some(version.clone())).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    let new_version: Version = reply.version.unwrap();
+
+    // Same value, but the version it was read at has been replaced
+    let reply: Reply = common::compare_and_swap(*SERVER_ADDR, &key, &value, None, Some(version)).unwrap();
+    assert_eq!(reply.status, Status::PreconditionFailed as u32);
+    assert_eq!(reply.version.unwrap(), new_version);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn CompareAndSwap_KeyNotFound_PreconditionFailed() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let reply: Reply = common::compare_and_swap(*SERVER_ADDR, &key, &value, Some(value.clone()), None).unwrap();
+    assert_eq!(reply.status, Status::PreconditionFailed as u32);
+
+    let (_, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+}
+
+#[test]
+fn CompareAndSwap_MissingValue() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let reply: Reply = common::compare_and_swap(*SERVER_ADDR, &key, &value, None, None).unwrap();
+    assert_eq!(reply.status, Status::MissingValue as u32);
+}
+
+#[test]
+fn PutIfAbsent_Success() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let reply: Reply = common::put_if_absent(*SERVER_ADDR, &key, &value).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+
+    let (retrived_value_opt, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(retrived_value_opt.unwrap(), value);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn PutIfAbsent_KeyExists() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let other_value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let reply: Reply = common::put_if_absent(*SERVER_ADDR, &key, &value).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+
+    let reply: Reply = common::put_if_absent(*SERVER_ADDR, &key, &other_value).unwrap();
+    assert_eq!(reply.status, Status::KeyExists as u32);
+    assert_eq!(reply.value.unwrap(), value);
+
+    let (retrived_value_opt, _) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(retrived_value_opt.unwrap(), value);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn DeleteIfValue_Success() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(value.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let reply: Reply = common::delete_if_value(*SERVER_ADDR, &key, &value).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(reply.value.unwrap(), value);
+
+    let (_, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+}
+
+#[test]
+fn DeleteIfValue_PreconditionFailed() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let wrong_value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES + 1);
+
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(value.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let reply: Reply = common::delete_if_value(*SERVER_ADDR, &key, &wrong_value).unwrap();
+    assert_eq!(reply.status, Status::PreconditionFailed as u32);
+    assert_eq!(reply.value.unwrap(), value);
+
+    let (retrived_value_opt, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(retrived_value_opt.unwrap(), value);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn DeleteIfValue_KeyNotFound() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let reply: Reply = common::delete_if_value(*SERVER_ADDR, &key, &value).unwrap();
+    assert_eq!(reply.status, Status::KeyNotFound as u32);
+}
@@ -0,0 +1,95 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::{extract_reply, Operation, Status};
+use dht::comm::protogen::api::{Request, Reply};
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Increment_Creates_Missing_Counter() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    let reply: Reply = common::update_counter(*SERVER_ADDR, Operation::Increment, &key, None).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(reply.counter, Some(1));
+    assert_eq!(reply.value, Some(b"1".to_vec()));
+
+    let reply: Reply = common::update_counter(*SERVER_ADDR, Operation::Increment, &key, Some(41)).unwrap();
+    assert_eq!(reply.counter, Some(42));
+
+    let (value, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(value, Some(b"42".to_vec()));
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn Decrement_Below_Zero() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(b"3".to_vec())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let reply: Reply = common::update_counter(*SERVER_ADDR, Operation::Decrement, &key, Some(5)).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(reply.counter, Some(-2));
+
+    let reply: Reply = common::update_counter(*SERVER_ADDR, Operation::Decrement, &key, Some(-10)).unwrap();
+    assert_eq!(reply.counter, Some(8));
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn Increment_Non_Integer_InvalidValue() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(b"not a number".to_vec())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let reply: Reply = common::update_counter(*SERVER_ADDR, Operation::Increment, &key, None).unwrap();
+    assert_eq!(reply.status, Status::InvalidValue as u32);
+
+    let (value, _) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(value, Some(b"not a number".to_vec()));
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn Increment_Overflow_InvalidValue() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    let reply: Reply = common::update_counter(*SERVER_ADDR, Operation::Increment, &key, Some(i64::MAX)).unwrap();
+    assert_eq!(reply.counter, Some(i64::MAX));
+
+    let reply: Reply = common::update_counter(*SERVER_ADDR, Operation::Increment, &key, None).unwrap();
+    assert_eq!(reply.status, Status::InvalidValue as u32);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn Increment_Missing_Key() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let proto_interface = common::get_proto_interface().unwrap();
+    let mut request = Request::new();
+    request.operation = Operation::Increment as u32;
+    let (reply_msg, _) = proto_interface.send_and_recv(request, *SERVER_ADDR).unwrap();
+    let reply: Reply = extract_reply(&reply_msg).unwrap();
+    assert_eq!(reply.status, Status::MissingKey as u32);
+}
@@ -0,0 +1,160 @@
+#![allow(non_snake_case)]
+
+use std::path::PathBuf;
+use dht::comm::proto::Status;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const SERVER_ID: u32 = 11;
+const NUM_KEYS: usize = 32;
+const START_WAIT_TIME_SEC: u64 = 1;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+fn create_data_dir(name: &str) -> PathBuf {
+    let data_dir: PathBuf = std::env::temp_dir().join(format!("dht-durability-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    data_dir
+}
+
+/// Puts, overwrites and deletes keys on a node started with `args`, then
+/// checks that they are all back after a crash and restart.
+fn check_restart_recovers_keys(args: &[&str]) {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, args, START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for _ in 0..NUM_KEYS {
+        let (key, value, status) = common::put_rand_key_value(server_addr).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+
+    // An overwritten and a deleted key come back in their final state
+    let new_value: Vec<u8> = common::get_rand_value();
+    let status: u32 = common::put_key_value(server_addr, &Some(pairs[0].0.clone()), &Some(new_value.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    pairs[0].1 = new_value;
+    let (deleted_key, _) = pairs.pop().unwrap();
+    let (_, status) = common::delete_key_value(server_addr, &deleted_key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+
+    let (mut child, server_addr) = common::start_server(SERVER_ID, args, START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    for (key, value) in pairs.iter() {
+        let (retrieved_value, status) = common::get_value(server_addr, key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrieved_value.unwrap(), *value);
+    }
+    let (_, status) = common::get_value(server_addr, &deleted_key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
+
+#[test]
+fn Restarted_Node_Recovers_Keys() {
+    let data_dir: PathBuf = create_data_dir("recover");
+    check_restart_recovers_keys(&["-d", data_dir.to_str().unwrap()]);
+    let _ = std::fs::remove_dir_all(&data_dir);
+}
+
+#[test]
+fn Restarted_Disk_Engine_Node_Recovers_Keys() {
+    let data_dir: PathBuf = create_data_dir("disk");
+    check_restart_recovers_keys(&["-d", data_dir.to_str().unwrap(), "--storage-engine", "disk"]);
+    let _ = std::fs::remove_dir_all(&data_dir);
+}
+
+#[test]
+fn Restarted_Node_Stays_Wiped() {
+    let data_dir: PathBuf = create_data_dir("wipe");
+    let args: [&str; 2] = ["-d", data_dir.to_str().unwrap()];
+
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &args, START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let (key, _, status) = common::put_rand_key_value(server_addr).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    let _ = common::wipe_servers(vec![server_addr], 0);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &args, START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let (_, status) = common::get_value(server_addr, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+    let _ = std::fs::remove_dir_all(&data_dir);
+}
+
+#[test]
+fn Snapshot_Then_Restart_Recovers_Keys() {
+    let data_dir: PathBuf = create_data_dir("snapshot");
+    let args: [&str; 2] = ["-d", data_dir.to_str().unwrap()];
+
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &args, START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for _ in 0..NUM_KEYS {
+        let (key, value, status) = common::put_rand_key_value(server_addr).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+    let status: u32 = common::snapshot(server_addr).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    // Changes after the snapshot are replayed from the log on top of it
+    let (key, value, status) = common::put_rand_key_value(server_addr).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    pairs.push((key, value));
+    let (deleted_key, _) = pairs.remove(0);
+    let (_, status) = common::delete_key_value(server_addr, &deleted_key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &args, START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    for (key, value) in pairs.iter() {
+        let (retrieved_value, status) = common::get_value(server_addr, key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrieved_value.unwrap(), *value);
+    }
+    let (_, status) = common::get_value(server_addr, &deleted_key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+    let _ = std::fs::remove_dir_all(&data_dir);
+}
+
+#[test]
+fn Snapshot_Without_Data_Dir_InternalError() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &[], START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let status: u32 = common::snapshot(server_addr).unwrap();
+    assert_eq!(status, Status::InternalError as u32);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
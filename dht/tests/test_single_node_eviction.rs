@@ -0,0 +1,99 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::Status;
+use dht::comm::protogen::api::Reply;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const SERVER_ID: u32 = 10;
+const MAX_MEMORY_MB: &str = "1";
+const KEY_SIZE_BYTES: usize = 64;
+const VALUE_SIZE_BYTES: usize = 8 * 1024;
+const NUM_KEYS: usize = 256;
+const START_WAIT_TIME_SEC: u64 = 1;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Full_Memory_LRU_Evicts_Least_Recently_Used() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &["-m", MAX_MEMORY_MB, "-e", "lru"], START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let hot_key: Vec<u8> = common::get_bytes(KEY_SIZE_BYTES);
+    let hot_value: Vec<u8> = common::get_bytes(VALUE_SIZE_BYTES);
+    let status: u32 = common::put_key_value(server_addr, &Some(hot_key.clone()), &Some(hot_value.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    // Twice the memory limit, reading the hot key in between writes
+    let mut keys: Vec<Vec<u8>> = Vec::new();
+    for _ in 0..NUM_KEYS {
+        let key: Vec<u8> = common::get_bytes(KEY_SIZE_BYTES);
+        let value: Vec<u8> = common::get_bytes(VALUE_SIZE_BYTES);
+        let status: u32 = common::put_key_value(server_addr, &Some(key.clone()), &Some(value)).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        keys.push(key);
+
+        let (_, status) = common::get_value(server_addr, &hot_key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+    }
+
+    let (_, status) = common::get_value(server_addr, &keys[0]).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+    let (_, status) = common::get_value(server_addr, &keys[NUM_KEYS - 1]).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
+
+#[test]
+fn Full_Memory_NoEviction_OutOfMemory() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &["-m", MAX_MEMORY_MB, "-e", "noeviction"], START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let mut keys: Vec<Vec<u8>> = Vec::new();
+    let mut status: u32 = Status::Success as u32;
+    while status == Status::Success as u32 && keys.len() < NUM_KEYS {
+        let key: Vec<u8> = common::get_bytes(KEY_SIZE_BYTES);
+        let value: Vec<u8> = common::get_bytes(VALUE_SIZE_BYTES);
+        status = common::put_key_value(server_addr, &Some(key.clone()), &Some(value)).unwrap();
+        keys.push(key);
+    }
+    assert_eq!(status, Status::OutOfMemory as u32);
+
+    // Nothing was evicted to make room
+    let (_, status) = common::get_value(server_addr, &keys[0]).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
+
+#[test]
+fn Full_Memory_LRU_Stays_Within_Limit_Across_Shards() {
+    let (mut child, server_addr) = common::start_server(
+        SERVER_ID,
+        &["-m", MAX_MEMORY_MB, "-e", "lru", "--threads", "4"],
+        START_WAIT_TIME_SEC
+    );
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    for _ in 0..NUM_KEYS {
+        let key: Vec<u8> = common::get_bytes(KEY_SIZE_BYTES);
+        let value: Vec<u8> = common::get_bytes(VALUE_SIZE_BYTES);
+        let status: u32 = common::put_key_value(server_addr, &Some(key), &Some(value)).unwrap();
+        assert_eq!(status, Status::Success as u32);
+    }
+
+    let reply: Reply = common::get_memory_usage(server_addr).unwrap();
+    assert!(reply.mem_usage.unwrap() + reply.cache_mem_usage.unwrap() <= reply.max_mem.unwrap());
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
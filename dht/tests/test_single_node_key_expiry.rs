@@ -0,0 +1,81 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::{extract_reply, Operation, Status};
+use dht::comm::protogen::api::{Request, Reply};
+use std::time::Duration;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const KEY_VALUE_SIZE_BYTES: usize = 64;
+const TTL_MS: u64 = 500;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Put_TTL_Get_Success_Then_KeyNotFound() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let status: u32 = common::put_key_value_with_ttl(*SERVER_ADDR, &key, &value, TTL_MS).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let proto_interface = common::get_proto_interface().unwrap();
+    let mut request = Request::new();
+    request.operation = Operation::Get as u32;
+    request.key = Some(key.clone());
+
+    let (reply_msg, _) = proto_interface.send_and_recv(request, *SERVER_ADDR).unwrap();
+    let reply: Reply = extract_reply(&reply_msg).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(reply.value.unwrap(), value);
+    assert!(reply.ttl_ms.unwrap() <= TTL_MS);
+    drop(proto_interface);
+
+    std::thread::sleep(Duration::from_millis(TTL_MS * 2));
+
+    let (_, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+}
+
+#[test]
+fn Put_TTL_Overwrite_Without_TTL_Persists() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let status: u32 = common::put_key_value_with_ttl(*SERVER_ADDR, &key, &value, TTL_MS).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(value.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    std::thread::sleep(Duration::from_millis(TTL_MS * 2));
+
+    let (retrived_value_opt, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(retrived_value_opt.unwrap(), value);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+}
+
+#[test]
+fn Put_Zero_TTL_InvalidValue() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+    let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let status: u32 = common::put_key_value_with_ttl(*SERVER_ADDR, &key, &value, 0).unwrap();
+    assert_eq!(status, Status::InvalidValue as u32);
+
+    let (_, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+}
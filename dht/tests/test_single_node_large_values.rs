@@ -0,0 +1,89 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::{Operation, Status};
+use dht::comm::protogen::api::{KeyValue, Reply};
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const SERVER_ID: u32 = 13;
+const START_WAIT_TIME_SEC: u64 = 1;
+const MAX_VALUE_SIZE_KB: usize = 4096;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Put_Get_Values_Larger_Than_A_Datagram() {
+    let max_value_size: String = MAX_VALUE_SIZE_KB.to_string();
+    let (mut child, server_addr) = common::start_server(
+        SERVER_ID,
+        &["-m", "64", "--max-value-size", &max_value_size],
+        START_WAIT_TIME_SEC
+    );
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    for value_size in [64 * 1024, 1024 * 1024, MAX_VALUE_SIZE_KB * 1024] {
+        let key: Vec<u8> = common::get_rand_key();
+        let value: Vec<u8> = common::get_rand_bytes(value_size, value_size + 1);
+        let status: u32 = common::put_key_value(server_addr, &Some(key.clone()), &Some(value.clone())).unwrap();
+        assert_eq!(status, Status::Success as u32);
+
+        let (retrieved_value, status) = common::get_value(server_addr, &key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrieved_value.unwrap(), value);
+
+        let reply: Reply = common::update_range(server_addr, Operation::GetRange, &key, None, Some(1000), Some(10)).unwrap();
+        assert_eq!(reply.value.unwrap(), value[1000..1010]);
+    }
+
+    let key: Vec<u8> = common::get_rand_key();
+    let value: Vec<u8> = common::get_bytes(MAX_VALUE_SIZE_KB * 1024 + 1);
+    let status: u32 = common::put_key_value(server_addr, &Some(key), &Some(value)).unwrap();
+    assert_eq!(status, Status::InvalidValueSize as u32);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
+
+#[test]
+fn Batch_And_Scan_Return_Large_Values_Whole() {
+    let max_value_size: String = MAX_VALUE_SIZE_KB.to_string();
+    let (mut child, server_addr) = common::start_server(
+        SERVER_ID,
+        &["-m", "64", "--max-value-size", &max_value_size, "--storage-engine", "btreemap"],
+        START_WAIT_TIME_SEC
+    );
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let key: Vec<u8> = b"large-scan-key".to_vec();
+    let value: Vec<u8> = common::get_bytes(1024 * 1024);
+    let status: u32 = common::put_key_value(server_addr, &Some(key.clone()), &Some(value.clone())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let entries: Vec<KeyValue> = vec![common::create_batch_entry(&key, &[])];
+    let (results, status) = common::run_batch(server_addr, Operation::MultiGet, entries).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(results[0].status, Some(Status::Success as u32));
+    assert_eq!(results[0].value, value);
+
+    let request = common::create_scan_request(None, None, Some(b"large-scan-"), None, true);
+    let (entries, _) = common::scan_all_pages(server_addr, request).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].value, value);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
+
+#[test]
+fn Max_Value_Size_Beyond_Message_Size_Fails_To_Start() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &["--max-value-size", "1000000"], START_WAIT_TIME_SEC);
+    assert!(common::ping_servers(vec![server_addr], false).is_err());
+    let _ = child.kill();
+    let _ = child.wait();
+}
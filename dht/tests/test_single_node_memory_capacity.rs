@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 
 use dht::comm::proto::Status;
+use dht::comm::protogen::api::Reply;
 use std::time::{Duration, Instant};
 
 use ntest::timeout;
@@ -10,6 +11,11 @@ mod tests_prelude;
 
 use tests_prelude::*;
 
+const KEY_VALUE_SIZE_BYTES: usize = 64;
+const SERVER_ID: u32 = 17;
+const MAX_MEMORY_MB: u64 = 1;
+const START_WAIT_TIME_SEC: u64 = 1;
+
 #[ctor]
 fn init() {
     common::init_logger();
@@ -18,13 +24,11 @@ fn init() {
 #[test]
 #[timeout(30000)]
 fn test_memory_capacity() {
-    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
-
-    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+    // A node of its own with little memory fills up quickly
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &["-m", &MAX_MEMORY_MB.to_string()], START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
 
-    // Default memory capacity is 32MB defined in main.rs
-    // This test assumes that the node runs with the default capacity
-    const MEMORY_CAPACITY_BYTES: u64 = 32 * 1024 * 1024;
+    const MEMORY_CAPACITY_BYTES: u64 = MAX_MEMORY_MB * 1024 * 1024;
     const KEY_LEN: usize = 32;
     const VALUE_LEN: usize = 32;
     const PAYLOAD_SIZE: usize = KEY_LEN + VALUE_LEN;
@@ -47,7 +51,7 @@ fn test_memory_capacity() {
         let key: Vec<u8> = common::get_bytes(KEY_LEN);
         let value: Vec<u8> = common::get_bytes(VALUE_LEN);
 
-        latest_status = match common::put_key_value(*SERVER_ADDR, &Some(key), &Some(value)) {
+        latest_status = match common::put_key_value(server_addr, &Some(key), &Some(value)) {
             Ok(status) => {
                 log::debug!("Received status code {}", status);
                 status
@@ -77,10 +81,46 @@ fn test_memory_capacity() {
         };
     };
 
-    // TODO: Use resident memory from process to compare to utilized memory
     log::info!("Test completed in {}s", test_start_time.elapsed().as_secs());
 
-    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
+    let reply: Reply = common::get_memory_usage(server_addr).unwrap();
+    let mem_usage: u64 = reply.mem_usage.unwrap() + reply.cache_mem_usage.unwrap();
+    let resident_mem: u64 = reply.resident_mem.unwrap();
+    log::info!("Accounted memory: {} Bytes, Resident memory: {} Bytes, Max Size: {} Bytes",
+        mem_usage,
+        resident_mem,
+        MEMORY_CAPACITY_BYTES);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
 
     assert!(test_passed);
+    assert!(mem_usage <= MEMORY_CAPACITY_BYTES);
+    // Everything accounted for must actually be held by the process
+    assert!(resident_mem >= mem_usage);
+}
+
+#[test]
+fn Put_Overwrite_Memory_Usage_Unchanged() {
+    let _result = common::ping_servers(vec![*SERVER_ADDR], true);
+
+    const NUM_OVERWRITES: usize = 100;
+    let key: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(common::get_bytes(KEY_VALUE_SIZE_BYTES))).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    let mem_usage: u64 = common::get_memory_usage(*SERVER_ADDR).unwrap().mem_usage.unwrap();
+
+    for _ in 0..NUM_OVERWRITES {
+        let value: Vec<u8> = common::get_bytes(KEY_VALUE_SIZE_BYTES);
+        let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(value)).unwrap();
+        assert_eq!(status, Status::Success as u32);
+    }
+
+    let reply: Reply = common::get_memory_usage(*SERVER_ADDR).unwrap();
+    assert_eq!(reply.mem_usage.unwrap(), mem_usage);
+    // Each entry costs more than its key and value
+    assert!(mem_usage > 2 * KEY_VALUE_SIZE_BYTES as u64);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 1);
 }
@@ -0,0 +1,145 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::{Operation, Status};
+use dht::comm::protogen::api::Reply;
+use std::thread;
+use std::time::Duration;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const MAX_VALUE_SIZE_BYTES: usize = 1024 * 10;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+#[test]
+fn Append_Success() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::Append, &key, Some(b"hello"), None, None).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::Append, &key, Some(b" world"), None, None).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+
+    let (value, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(value, Some(b"hello world".to_vec()));
+
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::Append, &key, None, None, None).unwrap();
+    assert_eq!(reply.status, Status::MissingValue as u32);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn Append_Past_Max_Value_Size() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+    let value: Vec<u8> = common::get_bytes(MAX_VALUE_SIZE_BYTES / 2);
+
+    for _ in 0..2 {
+        let reply: Reply = common::update_range(*SERVER_ADDR, Operation::Append, &key, Some(&value), None, None).unwrap();
+        assert_eq!(reply.status, Status::Success as u32);
+    }
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::Append, &key, Some(b"x"), None, None).unwrap();
+    assert_eq!(reply.status, Status::InvalidValueSize as u32);
+
+    let (value_received, _) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(value_received.unwrap().len(), MAX_VALUE_SIZE_BYTES);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn Append_Keeps_TTL() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    let status: u32 = common::put_key_value_with_ttl(*SERVER_ADDR, &key, b"a", 300).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::Append, &key, Some(b"b"), None, None).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+
+    thread::sleep(Duration::from_millis(400));
+    let (_, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+}
+
+#[test]
+fn SetRange_Success() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(b"hello world".to_vec())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::SetRange, &key, Some(b"there"), Some(6), None).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    let (value, _) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(value, Some(b"hello there".to_vec()));
+
+    // Writing past the end pads the value with zero bytes
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::SetRange, &key, Some(b"!"), Some(13), None).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    let (value, _) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(value, Some(b"hello there\0\0!".to_vec()));
+
+    let missing_key: Vec<u8> = common::get_rand_key();
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::SetRange, &missing_key, Some(&[0xFF]), Some(2), None).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    let (value, _) = common::get_value(*SERVER_ADDR, &missing_key).unwrap();
+    assert_eq!(value, Some(vec![0, 0, 0xFF]));
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn SetRange_Past_Max_Value_Size() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    let offset: Option<u64> = Some(MAX_VALUE_SIZE_BYTES as u64);
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::SetRange, &key, Some(b"x"), offset, None).unwrap();
+    assert_eq!(reply.status, Status::InvalidValueSize as u32);
+
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::SetRange, &key, Some(b"x"), Some(u64::MAX), None).unwrap();
+    assert_eq!(reply.status, Status::InvalidValueSize as u32);
+
+    let (_, status) = common::get_value(*SERVER_ADDR, &key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+}
+
+#[test]
+fn GetRange_Success() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let key: Vec<u8> = common::get_rand_key();
+
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key.clone()), &Some(b"hello world".to_vec())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+
+    let cases: Vec<(Option<u64>, Option<u64>, &[u8])> = vec![
+        (Some(6), Some(5), b"world"),
+        (Some(0), Some(5), b"hello"),
+        (Some(6), None, b"world"),
+        (None, None, b"hello world"),
+        (Some(8), Some(100), b"rld"),
+        (Some(20), Some(1), b""),
+        (Some(1), Some(u64::MAX), b"ello world"),
+    ];
+    for (offset, length, expected) in cases {
+        let reply: Reply = common::update_range(*SERVER_ADDR, Operation::GetRange, &key, None, offset, length).unwrap();
+        assert_eq!(reply.status, Status::Success as u32);
+        assert_eq!(reply.value, Some(expected.to_vec()));
+    }
+
+    let missing_key: Vec<u8> = common::get_rand_key();
+    let reply: Reply = common::update_range(*SERVER_ADDR, Operation::GetRange, &missing_key, None, None, None).unwrap();
+    assert_eq!(reply.status, Status::KeyNotFound as u32);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
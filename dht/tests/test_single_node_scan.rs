@@ -0,0 +1,122 @@
+#![allow(non_snake_case)]
+
+use dht::comm::proto::Status;
+use dht::comm::protogen::api::{KeyValue, Reply};
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const VALUE_SIZE_BYTES: usize = 1024;
+const NUM_KEYS: usize = 32;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+/// Stores `key-00` to `key-31` along with `other-0`, returning the values.
+fn put_keys() -> Vec<Vec<u8>> {
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+
+    let mut values: Vec<Vec<u8>> = Vec::new();
+    for i in 0..NUM_KEYS {
+        let value: Vec<u8> = common::get_bytes(VALUE_SIZE_BYTES);
+        let key: Vec<u8> = format!("key-{:02}", i).into_bytes();
+        let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(key), &Some(value.clone())).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        values.push(value);
+    }
+    let status: u32 = common::put_key_value(*SERVER_ADDR, &Some(b"other-0".to_vec()), &Some(b"value".to_vec())).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    values
+}
+
+fn get_keys(entries: &[KeyValue]) -> Vec<String> {
+    entries.iter().map(|entry| String::from_utf8(entry.key.clone()).unwrap()).collect()
+}
+
+#[test]
+fn Scan_Range_In_Order() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let values: Vec<Vec<u8>> = put_keys();
+
+    let request = common::create_scan_request(Some(b"key-10"), Some(b"key-14"), None, None, true);
+    let reply: Reply = common::scan(*SERVER_ADDR, request).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(get_keys(&reply.entries), vec!["key-10", "key-11", "key-12", "key-13"]);
+    assert_eq!(reply.entries[0].value, values[10]);
+    assert!(reply.next_key.is_none());
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn Scan_Limit_Without_Values() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    put_keys();
+
+    let request = common::create_scan_request(None, None, None, Some(3), false);
+    let reply: Reply = common::scan(*SERVER_ADDR, request).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(get_keys(&reply.entries), vec!["key-00", "key-01", "key-02"]);
+    assert!(reply.entries.iter().all(|entry| entry.value.is_empty()));
+    assert!(reply.next_key.is_none());
+
+    let request = common::create_scan_request(None, None, None, Some(0), false);
+    let reply: Reply = common::scan(*SERVER_ADDR, request).unwrap();
+    assert_eq!(reply.status, Status::InvalidValue as u32);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn Scan_Paginated_Across_Replies() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    let values: Vec<Vec<u8>> = put_keys();
+
+    // Every value together is bigger than a single message
+    let request = common::create_scan_request(Some(b"key-"), Some(b"key-~"), None, None, true);
+    let (entries, num_pages) = common::scan_all_pages(*SERVER_ADDR, request).unwrap();
+    assert!(num_pages > 1);
+    assert_eq!(entries.len(), NUM_KEYS);
+    for (i, entry) in entries.iter().enumerate() {
+        assert_eq!(entry.key, format!("key-{:02}", i).into_bytes());
+        assert_eq!(entry.value, values[i]);
+    }
+
+    let request = common::create_scan_request(None, None, None, Some(20), true);
+    let (entries, _) = common::scan_all_pages(*SERVER_ADDR, request).unwrap();
+    assert_eq!(entries.len(), 20);
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
+
+#[test]
+fn PrefixScan_Success() {
+    let _ = common::ping_servers(vec![*SERVER_ADDR], true);
+    put_keys();
+
+    let request = common::create_scan_request(None, None, Some(b"key-2"), None, false);
+    let reply: Reply = common::scan(*SERVER_ADDR, request).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    let expected: Vec<String> = (20..30).map(|i| format!("key-{}", i)).collect();
+    assert_eq!(get_keys(&reply.entries), expected);
+
+    // A start key resumes part way through the prefix
+    let request = common::create_scan_request(Some(b"key-25"), None, Some(b"key-2"), Some(2), false);
+    let reply: Reply = common::scan(*SERVER_ADDR, request).unwrap();
+    assert_eq!(get_keys(&reply.entries), vec!["key-25", "key-26"]);
+
+    let request = common::create_scan_request(None, None, Some(b"other"), None, false);
+    let reply: Reply = common::scan(*SERVER_ADDR, request).unwrap();
+    assert_eq!(get_keys(&reply.entries), vec!["other-0"]);
+
+    let request = common::create_scan_request(None, None, Some(b"missing"), None, false);
+    let reply: Reply = common::scan(*SERVER_ADDR, request).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    assert!(reply.entries.is_empty());
+
+    let _ = common::wipe_servers(vec![*SERVER_ADDR], 0);
+}
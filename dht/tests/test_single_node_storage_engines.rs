@@ -0,0 +1,77 @@
+#![allow(non_snake_case)]
+
+use std::path::PathBuf;
+use dht::comm::proto::Status;
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const SERVER_ID: u32 = 12;
+const NUM_KEYS: usize = 32;
+const START_WAIT_TIME_SEC: u64 = 1;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+/// Runs the basic operations against a node started with `args`.
+fn check_basic_operations(args: &[&str]) {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, args, START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for _ in 0..NUM_KEYS {
+        let (key, value, status) = common::put_rand_key_value(server_addr).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+
+    for (key, value) in pairs.iter() {
+        let (retrieved_value, status) = common::get_value(server_addr, key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrieved_value.unwrap(), *value);
+    }
+
+    let (key, value) = &pairs[0];
+    let (deleted_value, status) = common::delete_key_value(server_addr, key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(deleted_value.unwrap(), *value);
+    let (_, status) = common::get_value(server_addr, key).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+
+    let memory_usage = common::get_memory_usage(server_addr).unwrap();
+    assert!(memory_usage.mem_usage.unwrap() > 0);
+
+    let _ = common::wipe_servers(vec![server_addr], 0);
+    let (_, status) = common::get_value(server_addr, &pairs[1].0).unwrap();
+    assert_eq!(status, Status::KeyNotFound as u32);
+    let memory_usage = common::get_memory_usage(server_addr).unwrap();
+    assert_eq!(memory_usage.mem_usage.unwrap(), 0);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
+
+#[test]
+fn BTreeMap_Engine_Basic_Operations() {
+    check_basic_operations(&["--storage-engine", "btreemap"]);
+}
+
+#[test]
+fn Disk_Engine_Basic_Operations() {
+    let data_dir: PathBuf = std::env::temp_dir().join(format!("dht-storage-engines-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    check_basic_operations(&["-d", data_dir.to_str().unwrap(), "--storage-engine", "disk"]);
+    let _ = std::fs::remove_dir_all(&data_dir);
+}
+
+#[test]
+fn Disk_Engine_Without_Data_Dir_Fails_To_Start() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &["--storage-engine", "disk"], START_WAIT_TIME_SEC);
+    assert!(common::ping_servers(vec![server_addr], false).is_err());
+    let _ = child.kill();
+    let _ = child.wait();
+}
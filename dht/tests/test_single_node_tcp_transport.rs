@@ -0,0 +1,116 @@
+#![allow(non_snake_case)]
+
+use dht::comm::ProtoInterface;
+use dht::comm::proto::{extract_reply, Operation, Status};
+use dht::comm::protogen::api::{Request, Reply};
+use dht::comm::transport::{TcpTransport, UdpTransport};
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const SERVER_ID: u32 = 14;
+const START_WAIT_TIME_SEC: u64 = 1;
+const MAX_VALUE_SIZE_KB: usize = 1024 * 7;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+fn send_request(
+    proto_interface: &ProtoInterface<TcpTransport>,
+    server_addr: SocketAddr,
+    operation: Operation,
+    key: &[u8],
+    value: Option<Vec<u8>>
+) -> Reply {
+    let mut request: Request = Request::new();
+    request.operation = operation as u32;
+    request.key = Some(key.to_vec());
+    request.value = value;
+    let (reply_msg, _) = proto_interface.send_and_recv(request, server_addr).unwrap();
+    extract_reply(&reply_msg).unwrap()
+}
+
+#[test]
+fn Put_Get_Delete_Over_Tcp() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &["--transport", "tcp"], START_WAIT_TIME_SEC);
+    let proto_interface: ProtoInterface<TcpTransport> = common::get_tcp_proto_interface().unwrap();
+
+    for _ in 0..10 {
+        let key: Vec<u8> = common::get_rand_key();
+        let value: Vec<u8> = common::get_rand_value();
+        let reply: Reply = send_request(&proto_interface, server_addr, Operation::Put, &key, Some(value.clone()));
+        assert_eq!(reply.status, Status::Success as u32);
+
+        let reply: Reply = send_request(&proto_interface, server_addr, Operation::Get, &key, None);
+        assert_eq!(reply.status, Status::Success as u32);
+        assert_eq!(reply.value.unwrap(), value);
+
+        let reply: Reply = send_request(&proto_interface, server_addr, Operation::Delete, &key, None);
+        assert_eq!(reply.status, Status::Success as u32);
+        let reply: Reply = send_request(&proto_interface, server_addr, Operation::Get, &key, None);
+        assert_eq!(reply.status, Status::KeyNotFound as u32);
+    }
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::HardShutdown as u32;
+    let _ = proto_interface.send_and_recv(request, server_addr);
+    let _ = child.wait();
+}
+
+#[test]
+fn Put_Get_Values_Up_To_The_Limit_Over_Tcp() {
+    let max_value_size: String = MAX_VALUE_SIZE_KB.to_string();
+    let (mut child, server_addr) = common::start_server(
+        SERVER_ID,
+        &["-m", "128", "--max-value-size", &max_value_size],
+        START_WAIT_TIME_SEC
+    );
+    let proto_interface: ProtoInterface<TcpTransport> = common::get_tcp_proto_interface().unwrap();
+
+    let key: Vec<u8> = common::get_rand_key();
+    let value: Vec<u8> = common::get_bytes(MAX_VALUE_SIZE_KB * 1024);
+    let reply: Reply = send_request(&proto_interface, server_addr, Operation::Put, &key, Some(value.clone()));
+    assert_eq!(reply.status, Status::Success as u32);
+
+    let reply: Reply = send_request(&proto_interface, server_addr, Operation::Get, &key, None);
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(reply.value.unwrap(), value);
+
+    let value: Vec<u8> = common::get_bytes(MAX_VALUE_SIZE_KB * 1024 + 1);
+    let reply: Reply = send_request(&proto_interface, server_addr, Operation::Put, &key, Some(value));
+    assert_eq!(reply.status, Status::InvalidValueSize as u32);
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::HardShutdown as u32;
+    let _ = proto_interface.send_and_recv(request, server_addr);
+    let _ = child.wait();
+}
+
+#[test]
+fn Put_Over_Tcp_Get_Over_Udp() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &[], START_WAIT_TIME_SEC);
+    let tcp_interface: ProtoInterface<TcpTransport> = common::get_tcp_proto_interface().unwrap();
+    let udp_interface: ProtoInterface<UdpTransport> = common::get_proto_interface().unwrap();
+
+    let key: Vec<u8> = common::get_rand_key();
+    let value: Vec<u8> = common::get_rand_value();
+    let reply: Reply = send_request(&tcp_interface, server_addr, Operation::Put, &key, Some(value.clone()));
+    assert_eq!(reply.status, Status::Success as u32);
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::Get as u32;
+    request.key = Some(key);
+    let (reply_msg, _) = udp_interface.send_and_recv(request, server_addr).unwrap();
+    let reply: Reply = extract_reply(&reply_msg).unwrap();
+    assert_eq!(reply.status, Status::Success as u32);
+    assert_eq!(reply.value.unwrap(), value);
+
+    let mut request: Request = Request::new();
+    request.operation = Operation::HardShutdown as u32;
+    let _ = udp_interface.send_and_recv(request, server_addr);
+    let _ = child.wait();
+}
@@ -0,0 +1,138 @@
+#![allow(non_snake_case)]
+
+use std::path::PathBuf;
+use std::thread;
+use dht::comm::ProtoInterface;
+use dht::comm::proto::{extract_reply, Operation, Status};
+use dht::comm::protogen::api::{KeyValue, Request, Reply};
+
+mod common;
+mod tests_prelude;
+
+use tests_prelude::*;
+
+const SERVER_ID: u32 = 15;
+const NUM_THREADS: &str = "4";
+const NUM_CLIENTS: usize = 8;
+const NUM_KEYS: usize = 32;
+const START_WAIT_TIME_SEC: u64 = 1;
+
+#[ctor]
+fn init() {
+    common::init_logger();
+}
+
+fn send_request(proto_interface: &ProtoInterface, server_addr: SocketAddr, operation: Operation, key: &[u8], value: Option<Vec<u8>>) -> Reply {
+    let mut request: Request = Request::new();
+    request.operation = operation as u32;
+    request.key = Some(key.to_vec());
+    request.value = value;
+    let (reply_msg, _) = proto_interface.send_and_recv(request, server_addr).unwrap();
+    extract_reply(&reply_msg).unwrap()
+}
+
+#[test]
+fn Concurrent_Clients_Served_By_Workers() {
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &["--threads", NUM_THREADS], START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+    let counter_key: Vec<u8> = common::get_rand_key();
+
+    let handles: Vec<thread::JoinHandle<()>> = (0..NUM_CLIENTS)
+        .map(|_| {
+            let counter_key: Vec<u8> = counter_key.clone();
+            thread::spawn(move || {
+                // Each client needs its own socket to run alongside the others
+                let proto_interface = ProtoInterface::new("127.0.0.1:0".parse().unwrap()).unwrap();
+                for _ in 0..NUM_KEYS {
+                    let key: Vec<u8> = common::get_rand_key();
+                    let value: Vec<u8> = common::get_rand_value();
+                    let reply: Reply = send_request(&proto_interface, server_addr, Operation::Put, &key, Some(value.clone()));
+                    assert_eq!(reply.status, Status::Success as u32);
+
+                    let reply: Reply = send_request(&proto_interface, server_addr, Operation::Get, &key, None);
+                    assert_eq!(reply.status, Status::Success as u32);
+                    assert_eq!(reply.value.unwrap(), value);
+
+                    let reply: Reply = send_request(&proto_interface, server_addr, Operation::Increment, &counter_key, None);
+                    assert_eq!(reply.status, Status::Success as u32);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // Workers share the shard holding the counter, so no increment is lost
+    let (value, status) = common::get_value(server_addr, &counter_key).unwrap();
+    assert_eq!(status, Status::Success as u32);
+    assert_eq!(value, Some((NUM_CLIENTS * NUM_KEYS).to_string().into_bytes()));
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
+
+#[test]
+fn Scan_Pages_Through_Every_Shard_In_Order() {
+    let (mut child, server_addr) = common::start_server(
+        SERVER_ID,
+        &["--threads", NUM_THREADS, "--storage-engine", "btreemap"],
+        START_WAIT_TIME_SEC
+    );
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let mut keys: Vec<Vec<u8>> = (0..NUM_KEYS).map(|i| format!("worker-pool-{:02}", i).into_bytes()).collect();
+    for key in keys.iter() {
+        let status: u32 = common::put_key_value(server_addr, &Some(key.clone()), &Some(common::get_rand_value())).unwrap();
+        assert_eq!(status, Status::Success as u32);
+    }
+    keys.sort();
+
+    let request: Request = common::create_scan_request(None, None, Some(b"worker-pool-"), Some(NUM_KEYS as u32), false);
+    let (entries, num_pages) = common::scan_all_pages(server_addr, request).unwrap();
+    let scanned_keys: Vec<Vec<u8>> = entries.into_iter().map(|entry: KeyValue| entry.key).collect();
+    assert_eq!(scanned_keys, keys);
+    assert!(num_pages >= 1);
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+}
+
+#[test]
+fn Restart_With_Fewer_Threads_Keeps_Disk_Keys() {
+    let data_dir: PathBuf = std::env::temp_dir().join(format!("dht-worker-pool-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    let data_dir_str: &str = data_dir.to_str().unwrap();
+
+    let args: [&str; 6] = ["--threads", NUM_THREADS, "--storage-engine", "disk", "-d", data_dir_str];
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &args, START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for _ in 0..NUM_KEYS {
+        let (key, value, status) = common::put_rand_key_value(server_addr).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        pairs.push((key, value));
+    }
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+
+    // The shards left by the first run are opened too, so every key is still
+    // found in the shard it was written to
+    let args: [&str; 6] = ["--threads", "1", "--storage-engine", "disk", "-d", data_dir_str];
+    for index in 1..NUM_THREADS.parse::<usize>().unwrap() {
+        assert!(data_dir.join(format!("shard-{}", index)).is_dir());
+    }
+    let (mut child, server_addr) = common::start_server(SERVER_ID, &args, START_WAIT_TIME_SEC);
+    let _result = common::ping_servers(vec![server_addr], true);
+
+    for (key, value) in pairs.iter() {
+        let (retrieved_value, status) = common::get_value(server_addr, key).unwrap();
+        assert_eq!(status, Status::Success as u32);
+        assert_eq!(retrieved_value.unwrap(), *value);
+    }
+
+    let _ = common::hard_shutdown_servers(vec![server_addr], 0);
+    let _ = child.wait();
+    let _ = std::fs::remove_dir_all(&data_dir);
+}
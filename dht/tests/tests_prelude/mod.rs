@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 pub use ctor::ctor;
 pub use std::net::SocketAddr;
 
@@ -6,6 +8,8 @@ use std::path::Path;
 use dht::util::read_socket_addresses;
 
 const SERVER_FILE: &str = "servers/single_server.txt";
+const MULTI_SERVER_FILE: &str = "servers/multi_server.txt";
 lazy_static! {
     pub static ref SERVER_ADDR: SocketAddr = read_socket_addresses(Path::new(SERVER_FILE)).unwrap()[0];
+    pub static ref MULTI_SERVER_ADDRS: Vec<SocketAddr> = read_socket_addresses(Path::new(MULTI_SERVER_FILE)).unwrap();
 }